@@ -5,9 +5,29 @@ REM Set project root directory
 set PROJECT_ROOT=%CD%
 echo Project root is: %PROJECT_ROOT%
 
+REM Target architecture: x86_64 (default) or aarch64
+set ARCH=%1
+if "%ARCH%"=="" set ARCH=x86_64
+echo Target architecture is: %ARCH%
+
+if "%ARCH%"=="x86_64" (
+    set BOOT_TARGET=x86_64-unknown-uefi
+    set BOOT_NAME=BOOTX64.EFI
+    set KERNEL_TARGET=x86_64-unknown-none
+)
+if "%ARCH%"=="aarch64" (
+    set BOOT_TARGET=aarch64-unknown-uefi
+    set BOOT_NAME=BOOTAA64.EFI
+    set KERNEL_TARGET=aarch64-unknown-none
+)
+if not defined KERNEL_TARGET (
+    echo Unknown architecture: %ARCH%
+    exit /b 1
+)
+
 echo Building UEFI bootloader with nightly toolchain...
 cd uefi_bootloader
-cargo +nightly build --target x86_64-unknown-uefi --release
+cargo +nightly build --target %BOOT_TARGET% --release
 if %ERRORLEVEL% neq 0 (
     echo Failed to build bootloader
     cd %PROJECT_ROOT%
@@ -17,7 +37,6 @@ cd %PROJECT_ROOT%
 
 echo Building test kernel...
 cd kernel
-set ARCH=x86_64
 
 REM Ensure .cargo directory exists
 if not exist .cargo mkdir .cargo
@@ -37,6 +56,21 @@ if "%ARCH%"=="x86_64" (
     )
     set KERNEL_NAME=KERNEL_X64.ELF
 )
+
+if "%ARCH%"=="aarch64" (
+    echo Using aarch64 config
+    if exist .cargo\config.toml.aarch64 (
+        copy /Y .cargo\config.toml.aarch64 .cargo\config.toml
+    )
+
+    cargo +nightly build --release
+    if %ERRORLEVEL% neq 0 (
+        echo Failed to build kernel
+        cd %PROJECT_ROOT%
+        exit /b %ERRORLEVEL%
+    )
+    set KERNEL_NAME=KERNEL_ARM64.ELF
+)
 cd %PROJECT_ROOT%
 
 echo Setting up disk image files...
@@ -66,33 +100,33 @@ if not exist %PROJECT_ROOT%\esp\EFI\KERNEL (
 
 REM Check source files existence
 echo Checking source files...
-if exist %PROJECT_ROOT%\target\x86_64-unknown-uefi\release\uefi_bootloader.efi (
+if exist %PROJECT_ROOT%\target\%BOOT_TARGET%\release\uefi_bootloader.efi (
     echo Bootloader EFI file exists
 ) else (
-    echo ERROR: Bootloader EFI file does not exist at %PROJECT_ROOT%\target\x86_64-unknown-uefi\release\uefi_bootloader.efi!
+    echo ERROR: Bootloader EFI file does not exist at %PROJECT_ROOT%\target\%BOOT_TARGET%\release\uefi_bootloader.efi!
     exit /b 1
 )
 
-if exist %PROJECT_ROOT%\target\x86_64-unknown-none\release\kernel (
+REM Copy files
+echo Copying bootloader file...
+copy /Y "%PROJECT_ROOT%\target\%BOOT_TARGET%\release\uefi_bootloader.efi" "%PROJECT_ROOT%\esp\EFI\BOOT\%BOOT_NAME%"
+
+if exist %PROJECT_ROOT%\target\%KERNEL_TARGET%\release\kernel (
     echo Kernel file exists
 ) else (
-    echo ERROR: Kernel file does not exist at %PROJECT_ROOT%\target\x86_64-unknown-none\release\kernel!
+    echo ERROR: Kernel file does not exist at %PROJECT_ROOT%\target\%KERNEL_TARGET%\release\kernel!
     exit /b 1
 )
 
-REM Copy files
-echo Copying bootloader file...
-copy /Y "%PROJECT_ROOT%\target\x86_64-unknown-uefi\release\uefi_bootloader.efi" "%PROJECT_ROOT%\esp\EFI\BOOT\BOOTX64.EFI"
-
 echo Copying kernel file...
-copy /Y "%PROJECT_ROOT%\target\x86_64-unknown-none\release\kernel" "%PROJECT_ROOT%\esp\EFI\KERNEL\%KERNEL_NAME%"
+copy /Y "%PROJECT_ROOT%\target\%KERNEL_TARGET%\release\kernel" "%PROJECT_ROOT%\esp\EFI\KERNEL\%KERNEL_NAME%"
 
 REM Check destination files
 echo Verifying copied files...
-if exist "%PROJECT_ROOT%\esp\EFI\BOOT\BOOTX64.EFI" (
-    echo BOOTX64.EFI copied successfully
+if exist "%PROJECT_ROOT%\esp\EFI\BOOT\%BOOT_NAME%" (
+    echo %BOOT_NAME% copied successfully
 ) else (
-    echo ERROR: BOOTX64.EFI not found in destination!
+    echo ERROR: %BOOT_NAME% not found in destination!
     exit /b 1
 )
 
@@ -104,4 +138,10 @@ if exist "%PROJECT_ROOT%\esp\EFI\KERNEL\%KERNEL_NAME%" (
 )
 
 echo Build completed. Files ready at esp\ directory.
-qemu-system-x86_64 -drive file=fat:rw:esp,format=raw -bios OVMF.fd -m 128M -debugcon stdio
\ No newline at end of file
+if "%ARCH%"=="x86_64" (
+    qemu-system-x86_64 -drive file=fat:rw:esp,format=raw -bios OVMF.fd -m 128M -debugcon stdio
+)
+if "%ARCH%"=="aarch64" (
+    REM AAVMF has no VGA, ramfb provides the GOP framebuffer
+    qemu-system-aarch64 -M virt -cpu cortex-a72 -m 512M -bios QEMU_EFI.fd -device ramfb -serial stdio -drive if=none,id=esp,format=raw,file=fat:rw:esp -device virtio-blk-device,drive=esp
+)
@@ -0,0 +1,149 @@
+// kernel/src/acpi/mod.rs
+//
+// ACPI table discovery. The RSDP from the bootloader leads to the firmware's
+// tables, which stay where it put them and are handed out as byte slices.
+// The walk and the table parsers are in firmware::acpi, which builds for
+// the host too, and are re-exported from here.
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+pub use kernel::firmware::acpi::*;
+
+use crate::mm::phys_to_virt;
+use crate::BootInfo;
+
+static RSDP_ADDR: AtomicU64 = AtomicU64::new(0);
+
+pub fn init(boot_info: &BootInfo) {
+    RSDP_ADDR.store(boot_info.acpi_rsdp_addr, Ordering::Relaxed);
+    if RSDP_ADDR.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+
+    if !is_available() {
+        warn!(
+            "ACPI RSDP at {:#x} leads to no valid tables",
+            boot_info.acpi_rsdp_addr
+        );
+        return;
+    }
+    info!("ACPI tables: {}", Signatures);
+
+    if let Some(mcfg) = find_table(mcfg::SIGNATURE).and_then(mcfg::Mcfg::parse) {
+        for window in mcfg.windows() {
+            let (base, size) = window.range();
+            info!(
+                "PCI segment {} buses {:02x}-{:02x}: ECAM at {:#x}, {} MiB",
+                window.segment,
+                window.start_bus,
+                window.end_bus,
+                base,
+                size >> 20
+            );
+        }
+    }
+    if let Some(srat) = find_table(srat::SIGNATURE).and_then(srat::Srat::parse) {
+        log_numa(&srat);
+    }
+}
+
+// What the SRAT says about NUMA, in one line. Nothing places memory or
+// threads by proximity domain yet.
+fn log_numa(srat: &srat::Srat) {
+    let mut cpus = 0;
+    let mut ranges = 0;
+    let mut highest_domain = 0;
+    for entry in srat.entries() {
+        let (domain, flags, is_cpu) = match entry {
+            srat::Entry::LocalApic {
+                proximity_domain,
+                flags,
+                ..
+            }
+            | srat::Entry::LocalX2Apic {
+                proximity_domain,
+                flags,
+                ..
+            }
+            | srat::Entry::Gicc {
+                proximity_domain,
+                flags,
+                ..
+            } => (proximity_domain, flags, true),
+            srat::Entry::Memory {
+                proximity_domain,
+                flags,
+                ..
+            } => (proximity_domain, flags, false),
+            srat::Entry::Other { .. } => continue,
+        };
+        if flags & srat::ENABLED == 0 {
+            continue;
+        }
+        match is_cpu {
+            true => cpus += 1,
+            false => ranges += 1,
+        }
+        highest_domain = highest_domain.max(domain);
+    }
+    info!(
+        "NUMA: {} CPUs and {} memory ranges, proximity domains up to {}",
+        cpus, ranges, highest_domain
+    );
+}
+
+// Every table's signature, for the log
+struct Signatures;
+
+impl fmt::Display for Signatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, table) in tables().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(core::str::from_utf8(&table[..4]).unwrap_or("????"))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn is_available() -> bool {
+    tables().next().is_some()
+}
+
+// The first table with `signature` whose checksum is valid
+pub fn find_table(signature: &[u8; 4]) -> Option<&'static [u8]> {
+    tables().find(|table| &table[..4] == signature)
+}
+
+// The DSDT, which the FADT points at rather than the XSDT
+pub fn dsdt() -> Option<&'static [u8]> {
+    let fadt = fadt::Fadt::parse(find_table(fadt::SIGNATURE)?)?;
+    let dsdt = validate(unsafe { table_at(fadt.dsdt)? })?;
+    (&dsdt[..4] == b"DSDT").then_some(dsdt)
+}
+
+// Every valid table listed in the XSDT/RSDT, in memory
+pub fn tables() -> impl Iterator<Item = &'static [u8]> {
+    let rsdp_addr = RSDP_ADDR.load(Ordering::Relaxed);
+    let rsdp = match rsdp_addr {
+        0 => &[][..],
+        _ => unsafe { core::slice::from_raw_parts(phys_to_virt(rsdp_addr), RSDP_V2_SIZE) },
+    };
+    walk(rsdp, |phys| unsafe { table_at(phys) })
+}
+
+// The table at `phys`, as long as its header says
+unsafe fn table_at(phys: u64) -> Option<&'static [u8]> {
+    if phys == 0 {
+        return None;
+    }
+
+    let table = phys_to_virt(phys);
+    let length = (table.add(4) as *const u32).read_unaligned() as usize;
+    Some(core::slice::from_raw_parts(
+        table as *const u8,
+        length.max(HEADER_SIZE),
+    ))
+}
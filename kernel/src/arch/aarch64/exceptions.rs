@@ -0,0 +1,327 @@
+// kernel/src/arch/aarch64/exceptions.rs
+//
+// EL1 exception vector table. Each of the 16 entries (synchronous, IRQ, FIQ
+// and SError, for each of the four sources) saves X0-X30, the interrupted
+// SP, ELR, SPSR, ESR and FAR as an ExceptionFrame, plus the SIMD registers
+// the handler's Rust code may clobber, and calls handle_exception.
+//
+// IRQs and FIQs go to interrupts::handle. A data abort inside a range
+// registered with arch::fixup resumes at its fixup address; every other
+// synchronous exception and every SError is reported through a panic, with
+// ESR_EL1 decoded.
+
+use core::arch::{asm, global_asm};
+use core::fmt;
+
+use super::Registers;
+use crate::arch::fixup;
+use crate::interrupts;
+
+// Exception types, the low two bits of an entry's index
+const SYNCHRONOUS: u64 = 0;
+const IRQ: u64 = 1;
+const FIQ: u64 = 2;
+
+const SOURCES: [&str; 4] = [
+    "EL1 with SP_EL0",
+    "EL1 with SP_EL1",
+    "EL0 (AArch64)",
+    "EL0 (AArch32)",
+];
+
+// ESR_EL1 exception classes
+const EC_UNKNOWN: u64 = 0x00;
+const EC_WFI_WFE: u64 = 0x01;
+const EC_SIMD_FP: u64 = 0x07;
+const EC_ILLEGAL_STATE: u64 = 0x0E;
+const EC_SVC: u64 = 0x15;
+const EC_SYSTEM_REGISTER: u64 = 0x18;
+const EC_INSTRUCTION_ABORT_LOWER: u64 = 0x20;
+const EC_INSTRUCTION_ABORT: u64 = 0x21;
+const EC_PC_ALIGNMENT: u64 = 0x22;
+const EC_DATA_ABORT_LOWER: u64 = 0x24;
+const EC_DATA_ABORT: u64 = 0x25;
+const EC_SP_ALIGNMENT: u64 = 0x26;
+const EC_FP_EXCEPTION: u64 = 0x2C;
+const EC_SERROR: u64 = 0x2F;
+const EC_BREAKPOINT_LOWER: u64 = 0x30;
+const EC_BREAKPOINT: u64 = 0x31;
+const EC_STEP_LOWER: u64 = 0x32;
+const EC_STEP: u64 = 0x33;
+const EC_WATCHPOINT_LOWER: u64 = 0x34;
+const EC_WATCHPOINT: u64 = 0x35;
+const EC_BRK: u64 = 0x3C;
+
+// Abort ISS fields
+const ISS_WNR: u64 = 1 << 6;
+const ISS_FNV: u64 = 1 << 10;
+
+// What the vector entries leave on the stack, lowest address first
+#[repr(C)]
+pub struct ExceptionFrame {
+    pub x: [u64; 31],
+    pub sp: u64,
+    pub elr: u64,
+    pub spsr: u64,
+    pub esr: u64,
+    pub far: u64,
+}
+
+impl ExceptionFrame {
+    // The interrupted state, with the system registers as they are now
+    pub fn registers(&self) -> Registers {
+        let (current_el, sctlr_el1, ttbr0_el1): (u64, u64, u64);
+        unsafe {
+            asm!(
+                "mrs {}, currentel",
+                "mrs {}, sctlr_el1",
+                "mrs {}, ttbr0_el1",
+                out(reg) current_el,
+                out(reg) sctlr_el1,
+                out(reg) ttbr0_el1,
+                options(nomem, nostack)
+            );
+        }
+
+        Registers {
+            x: self.x,
+            sp: self.sp,
+            pc: self.elr,
+            current_el,
+            sctlr_el1,
+            ttbr0_el1,
+            esr_el1: self.esr,
+            far_el1: self.far,
+        }
+    }
+
+    fn class(&self) -> u64 {
+        (self.esr >> 26) & 0x3F
+    }
+
+    fn iss(&self) -> u64 {
+        self.esr & 0x1FF_FFFF
+    }
+}
+
+// An exception about to be reported, `kind` being the vector entry's index
+struct Report<'a> {
+    frame: &'a ExceptionFrame,
+    kind: u64,
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let frame = self.frame;
+        let source = SOURCES[(self.kind >> 2) as usize & 3];
+
+        match self.kind & 3 {
+            SYNCHRONOUS => {
+                write!(f, "Synchronous exception from {}: ", source)?;
+                describe_synchronous(f, frame)?;
+            }
+            IRQ => write!(f, "IRQ from {}", source)?,
+            FIQ => write!(f, "FIQ from {}", source)?,
+            _ => write!(f, "SError from {}, ISS {:#x}", source, frame.iss())?,
+        }
+
+        writeln!(
+            f,
+            "\nESR_EL1={:016x} FAR_EL1={:016x} ELR_EL1={:016x}",
+            frame.esr, frame.far, frame.elr
+        )?;
+        write!(f, "{}", frame.registers())
+    }
+}
+
+fn describe_synchronous(f: &mut fmt::Formatter, frame: &ExceptionFrame) -> fmt::Result {
+    let iss = frame.iss();
+    match frame.class() {
+        EC_DATA_ABORT | EC_DATA_ABORT_LOWER => {
+            let access = if iss & ISS_WNR != 0 { "write" } else { "read" };
+            write!(f, "data abort on {}, ", access)?;
+            describe_fault_status(f, iss & 0x3F)?;
+            if iss & ISS_FNV != 0 {
+                f.write_str(" (FAR not valid)")?;
+            }
+            Ok(())
+        }
+        EC_INSTRUCTION_ABORT | EC_INSTRUCTION_ABORT_LOWER => {
+            f.write_str("instruction abort, ")?;
+            describe_fault_status(f, iss & 0x3F)
+        }
+        EC_PC_ALIGNMENT => f.write_str("PC alignment fault"),
+        EC_SP_ALIGNMENT => f.write_str("SP alignment fault"),
+        EC_SVC => write!(f, "SVC #{:#x}", iss & 0xFFFF),
+        EC_BRK => write!(f, "BRK #{:#x}", iss & 0xFFFF),
+        EC_BREAKPOINT | EC_BREAKPOINT_LOWER => f.write_str("hardware breakpoint"),
+        EC_STEP | EC_STEP_LOWER => f.write_str("software step"),
+        EC_WATCHPOINT | EC_WATCHPOINT_LOWER => f.write_str("watchpoint"),
+        EC_UNKNOWN => f.write_str("unknown reason (undefined instruction?)"),
+        EC_WFI_WFE => f.write_str("trapped WFI/WFE"),
+        EC_SIMD_FP => f.write_str("trapped SIMD/FP access"),
+        EC_ILLEGAL_STATE => f.write_str("illegal execution state"),
+        EC_SYSTEM_REGISTER => f.write_str("trapped system register access"),
+        EC_FP_EXCEPTION => f.write_str("floating-point exception"),
+        EC_SERROR => f.write_str("SError"),
+        class => write!(f, "exception class {:#x}", class),
+    }
+}
+
+// DFSC/IFSC of an abort
+fn describe_fault_status(f: &mut fmt::Formatter, status: u64) -> fmt::Result {
+    let level = status & 3;
+    match status {
+        0x00..=0x03 => write!(f, "address size fault at level {}", level),
+        0x04..=0x07 => write!(f, "translation fault at level {}", level),
+        0x08..=0x0B => write!(f, "access flag fault at level {}", level),
+        0x0C..=0x0F => write!(f, "permission fault at level {}", level),
+        0x10 => f.write_str("synchronous external abort"),
+        0x21 => f.write_str("alignment fault"),
+        0x30 => f.write_str("TLB conflict abort"),
+        _ => write!(f, "fault status {:#x}", status),
+    }
+}
+
+extern "C" {
+    // 2 KiB aligned, 16 entries of 0x80 bytes
+    fn exception_vectors();
+}
+
+// Point VBAR_EL1 at the vector table
+pub unsafe fn init() {
+    asm!(
+        "msr vbar_el1, {}",
+        "isb",
+        in(reg) exception_vectors as *const () as u64,
+        options(nostack)
+    );
+}
+
+extern "C" fn handle_exception(frame: &mut ExceptionFrame, kind: u64) {
+    match kind & 3 {
+        IRQ | FIQ => interrupts::handle(None),
+        SYNCHRONOUS => {
+            if matches!(frame.class(), EC_DATA_ABORT | EC_DATA_ABORT_LOWER) {
+                if let Some(resume) = fixup::search(frame.elr) {
+                    frame.elr = resume;
+                    return;
+                }
+            }
+            panic!("{}", Report { frame, kind });
+        }
+        _ => panic!("{}", Report { frame, kind }),
+    }
+}
+
+// The frame is 288 bytes and the SIMD block 528 (FPCR, FPSR, Q0-Q31). The
+// common code builds a frame record {X29, ELR} below both so backtraces
+// continue into the interrupted code.
+global_asm!(
+    ".section .text.exception_vectors, \"ax\"",
+    ".p2align 11",
+    ".global exception_vectors",
+    "exception_vectors:",
+    ".set kind, 0",
+    ".rept 16",
+    "    .p2align 7",
+    "    sub sp, sp, #288",
+    "    stp x0, x1, [sp, #0]",
+    "    mov x1, #kind",
+    "    b exception_common",
+    "    .set kind, kind + 1",
+    ".endr",
+    "",
+    "exception_common:",
+    "    stp x2, x3, [sp, #16]",
+    "    stp x4, x5, [sp, #32]",
+    "    stp x6, x7, [sp, #48]",
+    "    stp x8, x9, [sp, #64]",
+    "    stp x10, x11, [sp, #80]",
+    "    stp x12, x13, [sp, #96]",
+    "    stp x14, x15, [sp, #112]",
+    "    stp x16, x17, [sp, #128]",
+    "    stp x18, x19, [sp, #144]",
+    "    stp x20, x21, [sp, #160]",
+    "    stp x22, x23, [sp, #176]",
+    "    stp x24, x25, [sp, #192]",
+    "    stp x26, x27, [sp, #208]",
+    "    stp x28, x29, [sp, #224]",
+    "    add x2, sp, #288",
+    "    stp x30, x2, [sp, #240]",
+    "    mrs x2, elr_el1",
+    "    mrs x3, spsr_el1",
+    "    stp x2, x3, [sp, #256]",
+    "    mrs x4, esr_el1",
+    "    mrs x5, far_el1",
+    "    stp x4, x5, [sp, #272]",
+    "    sub sp, sp, #528",
+    "    stp q0, q1, [sp, #16]",
+    "    stp q2, q3, [sp, #48]",
+    "    stp q4, q5, [sp, #80]",
+    "    stp q6, q7, [sp, #112]",
+    "    stp q8, q9, [sp, #144]",
+    "    stp q10, q11, [sp, #176]",
+    "    stp q12, q13, [sp, #208]",
+    "    stp q14, q15, [sp, #240]",
+    "    stp q16, q17, [sp, #272]",
+    "    stp q18, q19, [sp, #304]",
+    "    stp q20, q21, [sp, #336]",
+    "    stp q22, q23, [sp, #368]",
+    "    stp q24, q25, [sp, #400]",
+    "    stp q26, q27, [sp, #432]",
+    "    stp q28, q29, [sp, #464]",
+    "    stp q30, q31, [sp, #496]",
+    "    mrs x4, fpcr",
+    "    mrs x5, fpsr",
+    "    stp x4, x5, [sp, #0]",
+    "    stp x29, x2, [sp, #-16]!",
+    "    mov x29, sp",
+    "    add x0, sp, #(16 + 528)",
+    "    bl {handler}",
+    "    add sp, sp, #16",
+    "    ldp x4, x5, [sp, #0]",
+    "    msr fpcr, x4",
+    "    msr fpsr, x5",
+    "    ldp q0, q1, [sp, #16]",
+    "    ldp q2, q3, [sp, #48]",
+    "    ldp q4, q5, [sp, #80]",
+    "    ldp q6, q7, [sp, #112]",
+    "    ldp q8, q9, [sp, #144]",
+    "    ldp q10, q11, [sp, #176]",
+    "    ldp q12, q13, [sp, #208]",
+    "    ldp q14, q15, [sp, #240]",
+    "    ldp q16, q17, [sp, #272]",
+    "    ldp q18, q19, [sp, #304]",
+    "    ldp q20, q21, [sp, #336]",
+    "    ldp q22, q23, [sp, #368]",
+    "    ldp q24, q25, [sp, #400]",
+    "    ldp q26, q27, [sp, #432]",
+    "    ldp q28, q29, [sp, #464]",
+    "    ldp q30, q31, [sp, #496]",
+    "    add sp, sp, #528",
+    // The handler may have changed where to return to
+    "    ldp x2, x3, [sp, #256]",
+    "    msr elr_el1, x2",
+    "    msr spsr_el1, x3",
+    "    ldp x0, x1, [sp, #0]",
+    "    ldp x2, x3, [sp, #16]",
+    "    ldp x4, x5, [sp, #32]",
+    "    ldp x6, x7, [sp, #48]",
+    "    ldp x8, x9, [sp, #64]",
+    "    ldp x10, x11, [sp, #80]",
+    "    ldp x12, x13, [sp, #96]",
+    "    ldp x14, x15, [sp, #112]",
+    "    ldp x16, x17, [sp, #128]",
+    "    ldp x18, x19, [sp, #144]",
+    "    ldp x20, x21, [sp, #160]",
+    "    ldp x22, x23, [sp, #176]",
+    "    ldp x24, x25, [sp, #192]",
+    "    ldp x26, x27, [sp, #208]",
+    "    ldp x28, x29, [sp, #224]",
+    "    ldr x30, [sp, #240]",
+    "    add sp, sp, #288",
+    "    eret",
+    ".previous",
+    handler = sym handle_exception,
+);
@@ -0,0 +1,253 @@
+// kernel/src/arch/aarch64/mod.rs
+use core::arch::{asm, global_asm};
+use core::fmt;
+
+use super::{fixup, Arch};
+use crate::mm::vmm::{Entry, MapFlags};
+
+pub mod exceptions;
+pub mod paging;
+pub mod psci;
+pub mod timer;
+
+pub struct Aarch64;
+
+// DAIF.I, IRQ mask
+const DAIF_I: u64 = 1 << 7;
+
+// X0-X30, then SP and PC, then the system registers that explain a fault
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Registers {
+    pub x: [u64; 31],
+    pub sp: u64,
+    pub pc: u64,
+    pub current_el: u64,
+    pub sctlr_el1: u64,
+    pub ttbr0_el1: u64,
+    pub esr_el1: u64,
+    pub far_el1: u64,
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, value) in self.x.iter().enumerate() {
+            write!(f, "X{:<2}={:016x}", i, value)?;
+            f.write_str(if i % 4 == 3 { "\n" } else { " " })?;
+        }
+        writeln!(f, "SP ={:016x} PC ={:016x}", self.sp, self.pc)?;
+        writeln!(
+            f,
+            "CurrentEL={} SCTLR_EL1={:016x} TTBR0_EL1={:016x}",
+            (self.current_el >> 2) & 3,
+            self.sctlr_el1,
+            self.ttbr0_el1
+        )?;
+        write!(f, "ESR_EL1={:016x} FAR_EL1={:016x}", self.esr_el1, self.far_el1)
+    }
+}
+
+impl Arch for Aarch64 {
+    type Registers = Registers;
+
+    fn init_cpu(_hw_id: u64) {
+        unsafe {
+            exceptions::init();
+        }
+
+        fixup::register(
+            probe_read_byte as *const () as u64,
+            probe_read_byte_end as *const () as u64,
+            probe_read_byte_fault as *const () as u64,
+        );
+    }
+
+    fn wait_for_interrupt() {
+        unsafe {
+            asm!("wfi", options(nomem, nostack));
+        }
+    }
+
+    fn enable_interrupts() {
+        unsafe {
+            asm!("msr daifclr, #2", options(nomem, nostack));
+        }
+    }
+
+    // wfi wakes for a pending IRQ even while it's masked, which it then is
+    // until the daifclr
+    fn enable_interrupts_and_wait() {
+        unsafe {
+            asm!("wfi", "msr daifclr, #2", options(nomem, nostack));
+        }
+    }
+
+    fn disable_interrupts() -> bool {
+        let was_enabled = Self::interrupts_enabled();
+        unsafe {
+            asm!("msr daifset, #2", options(nomem, nostack));
+        }
+        was_enabled
+    }
+
+    fn interrupts_enabled() -> bool {
+        let daif: u64;
+        unsafe {
+            asm!("mrs {}, daif", out(reg) daif, options(nomem, nostack));
+        }
+        daif & DAIF_I == 0
+    }
+
+    fn read_cycle_counter() -> u64 {
+        let count: u64;
+        unsafe {
+            asm!("isb", "mrs {}, cntvct_el0", out(reg) count, options(nomem, nostack));
+        }
+        count
+    }
+
+    // Affinity fields (Aff3..Aff0) of MPIDR_EL1
+    fn cpu_id() -> u64 {
+        let mpidr: u64;
+        unsafe {
+            asm!("mrs {}, mpidr_el1", out(reg) mpidr, options(nomem, nostack));
+        }
+        mpidr & 0xFF_00FF_FFFF
+    }
+
+    fn flush_tlb_page(virt: u64) {
+        unsafe {
+            asm!(
+                "dsb ishst",
+                "tlbi vaae1is, {}",
+                "dsb ish",
+                "isb",
+                in(reg) virt >> 12,
+                options(nostack)
+            );
+        }
+    }
+
+    fn flush_tlb_all() {
+        unsafe {
+            asm!("dsb ishst", "tlbi vmalle1is", "dsb ish", "isb", options(nostack));
+        }
+    }
+
+    // The kernel lives in the lower half, so its tables go in TTBR0_EL1
+    unsafe fn activate_page_table(root: u64) {
+        paging::configure();
+        asm!("dsb ishst", "msr ttbr0_el1, {}", "isb", in(reg) root, options(nostack));
+        Self::flush_tlb_all();
+    }
+
+    fn current_page_table() -> u64 {
+        let ttbr0: u64;
+        unsafe {
+            asm!("mrs {}, ttbr0_el1", out(reg) ttbr0, options(nomem, nostack));
+        }
+        // Strip the ASID and CnP bits
+        ttbr0 & 0x0000_FFFF_FFFF_F000
+    }
+
+    fn max_leaf_level() -> usize {
+        paging::max_leaf_level()
+    }
+
+    fn table_entry(phys: u64) -> u64 {
+        paging::table_entry(phys)
+    }
+
+    fn leaf_entry(phys: u64, flags: MapFlags, level: usize) -> u64 {
+        paging::leaf_entry(phys, flags, level)
+    }
+
+    fn decode_entry(entry: u64, level: usize) -> Entry {
+        paging::decode_entry(entry, level)
+    }
+
+    fn probe_read(addr: u64) -> Option<u8> {
+        let mut byte = 0;
+        unsafe { probe_read_byte(addr, &mut byte) }.then_some(byte)
+    }
+
+    // X0 carries the destination, so its saved value is the snapshot's address
+    #[inline(always)]
+    fn capture_registers() -> Registers {
+        let mut regs = Registers::default();
+        unsafe {
+            asm!(
+                "stp x0, x1, [x0, #0]",
+                "stp x2, x3, [x0, #16]",
+                "stp x4, x5, [x0, #32]",
+                "stp x6, x7, [x0, #48]",
+                "stp x8, x9, [x0, #64]",
+                "stp x10, x11, [x0, #80]",
+                "stp x12, x13, [x0, #96]",
+                "stp x14, x15, [x0, #112]",
+                "stp x16, x17, [x0, #128]",
+                "stp x18, x19, [x0, #144]",
+                "stp x20, x21, [x0, #160]",
+                "stp x22, x23, [x0, #176]",
+                "stp x24, x25, [x0, #192]",
+                "stp x26, x27, [x0, #208]",
+                "stp x28, x29, [x0, #224]",
+                "str x30, [x0, #240]",
+                "mov x1, sp",
+                "adr x2, .",
+                "stp x1, x2, [x0, #248]",
+                "mrs x1, currentel",
+                "mrs x2, sctlr_el1",
+                "stp x1, x2, [x0, #264]",
+                "mrs x1, ttbr0_el1",
+                "mrs x2, esr_el1",
+                "stp x1, x2, [x0, #280]",
+                "mrs x1, far_el1",
+                "str x1, [x0, #296]",
+                in("x0") &mut regs as *mut Registers,
+                out("x1") _,
+                out("x2") _,
+                options(nostack),
+            );
+        }
+        regs
+    }
+
+    #[inline(always)]
+    fn frame_pointer() -> u64 {
+        let fp: u64;
+        unsafe {
+            asm!("mov {}, x29", out(reg) fp, options(nomem, nostack));
+        }
+        fp
+    }
+
+    // X29 points at the {X29, X30} pair saved by the prologue
+    fn frame_record(fp: u64) -> u64 {
+        fp
+    }
+}
+
+extern "C" {
+    // Copy the byte at `addr` to `out`, or return false if reading it faults
+    fn probe_read_byte(addr: u64, out: *mut u8) -> bool;
+    fn probe_read_byte_end();
+    fn probe_read_byte_fault();
+}
+
+global_asm!(
+    ".section .text.probe_read_byte, \"ax\"",
+    ".global probe_read_byte",
+    ".global probe_read_byte_end",
+    ".global probe_read_byte_fault",
+    "probe_read_byte:",
+    "    ldrb w2, [x0]",
+    "probe_read_byte_end:",
+    "    strb w2, [x1]",
+    "    mov w0, #1",
+    "    ret",
+    "probe_read_byte_fault:",
+    "    mov w0, #0",
+    "    ret",
+    ".previous",
+);
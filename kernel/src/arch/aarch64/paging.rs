@@ -0,0 +1,142 @@
+// kernel/src/arch/aarch64/paging.rs
+//
+// VMSAv8-64 translation table descriptors for the 4 KiB granule with 48-bit
+// virtual addresses: four levels, blocks at level 1 (1 GiB) and level 2
+// (2 MiB). MAIR_EL1 uses the same slot layout as EDK2, so switching from the
+// firmware's tables never changes the type of memory the running code sits in.
+
+use core::arch::asm;
+
+use crate::mm::vmm::{CacheMode, Entry, MapFlags};
+
+const VALID: u64 = 1 << 0;
+// Table at levels 1-3, page at level 0; clear for a block
+const TABLE_OR_PAGE: u64 = 1 << 1;
+const ATTR_INDEX_SHIFT: u64 = 2;
+const ATTR_INDEX_MASK: u64 = 7 << ATTR_INDEX_SHIFT;
+// AP[1]: accessible from EL0
+const AP_USER: u64 = 1 << 6;
+// AP[2]: read-only
+const AP_READ_ONLY: u64 = 1 << 7;
+const SH_INNER: u64 = 3 << 8;
+const ACCESS_FLAG: u64 = 1 << 10;
+const PXN: u64 = 1 << 53;
+const UXN: u64 = 1 << 54;
+
+const ADDRESS_MASK: u64 = 0x0000_FFFF_FFFF_F000;
+
+// MAIR_EL1 slots
+const ATTR_DEVICE: u64 = 0;
+const ATTR_NORMAL_NC: u64 = 1;
+const ATTR_NORMAL_WB: u64 = 3;
+
+// Slot 0 Device-nGnRnE (0x00), then Normal non-cacheable, Normal
+// write-through and Normal write-back
+const MAIR_VALUE: u64 = 0x44 << 8 | 0xBB << 16 | 0xFF << 24;
+
+// TCR_EL1: 48-bit TTBR0 region, 4 KiB granule, inner shareable write-back
+// walks. TTBR1 walks are disabled, the kernel lives in the lower half.
+const TCR_T0SZ_48: u64 = 16;
+const TCR_IRGN0_WB: u64 = 1 << 8;
+const TCR_ORGN0_WB: u64 = 1 << 10;
+const TCR_SH0_INNER: u64 = 3 << 12;
+const TCR_T1SZ_48: u64 = 16 << 16;
+const TCR_EPD1: u64 = 1 << 23;
+const TCR_TG1_4K: u64 = 2 << 30;
+const TCR_IPS_SHIFT: u64 = 32;
+
+// Largest IPS encoding usable without 52-bit addresses
+const MAX_PA_RANGE: u64 = 5;
+
+// Set MAIR_EL1 and TCR_EL1 for tables built by mm::vmm. The firmware may
+// have used a smaller address space, which would change the walk's start
+// level under our 4-level tables.
+pub unsafe fn configure() {
+    let mmfr0: u64;
+    asm!("mrs {}, id_aa64mmfr0_el1", out(reg) mmfr0, options(nomem, nostack));
+    let pa_range = (mmfr0 & 0xF).min(MAX_PA_RANGE);
+
+    let tcr = TCR_T0SZ_48
+        | TCR_IRGN0_WB
+        | TCR_ORGN0_WB
+        | TCR_SH0_INNER
+        | TCR_T1SZ_48
+        | TCR_EPD1
+        | TCR_TG1_4K
+        | pa_range << TCR_IPS_SHIFT;
+
+    asm!(
+        "msr mair_el1, {}",
+        "msr tcr_el1, {}",
+        "isb",
+        in(reg) MAIR_VALUE,
+        in(reg) tcr,
+        options(nostack)
+    );
+}
+
+// Level 0 blocks don't exist with the 4 KiB granule
+pub fn max_leaf_level() -> usize {
+    2
+}
+
+pub fn table_entry(phys: u64) -> u64 {
+    (phys & ADDRESS_MASK) | VALID | TABLE_OR_PAGE
+}
+
+pub fn leaf_entry(phys: u64, flags: MapFlags, level: usize) -> u64 {
+    // The access flag is preset, there's no handler for access flag faults
+    let mut entry = (phys & ADDRESS_MASK) | VALID | ACCESS_FLAG;
+    if level == 0 {
+        entry |= TABLE_OR_PAGE;
+    }
+    if !flags.writable {
+        entry |= AP_READ_ONLY;
+    }
+    if flags.user {
+        entry |= AP_USER;
+    }
+
+    // Executable at one exception level only: user code is never run by
+    // the kernel
+    entry |= match (flags.executable, flags.user) {
+        (false, _) => PXN | UXN,
+        (true, false) => UXN,
+        (true, true) => PXN,
+    };
+
+    entry |= match flags.cache {
+        CacheMode::WriteBack => ATTR_NORMAL_WB << ATTR_INDEX_SHIFT | SH_INNER,
+        CacheMode::WriteCombining => ATTR_NORMAL_NC << ATTR_INDEX_SHIFT | SH_INNER,
+        // Device memory must never be executable, speculatively or not
+        CacheMode::Uncached => ATTR_DEVICE << ATTR_INDEX_SHIFT | PXN | UXN,
+    };
+    entry
+}
+
+pub fn decode_entry(entry: u64, level: usize) -> Entry {
+    if entry & VALID == 0 {
+        return Entry::Empty;
+    }
+    if level > 0 && entry & TABLE_OR_PAGE != 0 {
+        return Entry::Table(entry & ADDRESS_MASK);
+    }
+
+    let user = entry & AP_USER != 0;
+    let executable = if user { entry & UXN == 0 } else { entry & PXN == 0 };
+    let cache = match (entry & ATTR_INDEX_MASK) >> ATTR_INDEX_SHIFT {
+        ATTR_NORMAL_WB => CacheMode::WriteBack,
+        ATTR_NORMAL_NC => CacheMode::WriteCombining,
+        _ => CacheMode::Uncached,
+    };
+    let size_mask = (1u64 << (12 + 9 * level)) - 1;
+    Entry::Leaf(
+        entry & ADDRESS_MASK & !size_mask,
+        MapFlags {
+            writable: entry & AP_READ_ONLY == 0,
+            executable,
+            user,
+            cache,
+        },
+    )
+}
@@ -0,0 +1,45 @@
+// kernel/src/arch/aarch64/psci.rs
+//
+// Power State Coordination Interface calls into firmware, made with SMC
+// (to EL3) or HVC (to a hypervisor) as the firmware tables say. Only the
+// 32-bit function IDs of PSCI 0.2 and later are used.
+
+use core::arch::asm;
+
+pub const SYSTEM_OFF: u32 = 0x8400_0008;
+pub const SYSTEM_RESET: u32 = 0x8400_0009;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conduit {
+    Smc,
+    Hvc,
+}
+
+// Call `function`, returning what firmware left in X0. The caller must
+// know the conduit is right: SMC without EL3, or HVC without a hypervisor,
+// is an undefined instruction.
+pub unsafe fn call(conduit: Conduit, function: u32, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+    let result: i64;
+    // SMC Calling Convention 1.0: X0-X3 in and out, X4-X17 may be trashed
+    macro_rules! smccc {
+        ($instruction:literal) => {
+            asm!(
+                $instruction,
+                inlateout("x0") function as u64 => result,
+                inlateout("x1") arg0 => _,
+                inlateout("x2") arg1 => _,
+                inlateout("x3") arg2 => _,
+                lateout("x4") _, lateout("x5") _, lateout("x6") _, lateout("x7") _,
+                lateout("x8") _, lateout("x9") _, lateout("x10") _, lateout("x11") _,
+                lateout("x12") _, lateout("x13") _, lateout("x14") _, lateout("x15") _,
+                lateout("x16") _, lateout("x17") _,
+                options(nostack)
+            )
+        };
+    }
+    match conduit {
+        Conduit::Smc => smccc!("smc #0"),
+        Conduit::Hvc => smccc!("hvc #0"),
+    }
+    result
+}
@@ -0,0 +1,44 @@
+// kernel/src/arch/aarch64/timer.rs
+//
+// The EL1 physical timer of the generic timer. It compares against the
+// system count (CNTPCT_EL0, ticking at CNTFRQ_EL0) and holds its PPI
+// asserted from the moment the deadline passes until it is reprogrammed
+// or stopped.
+
+use core::arch::asm;
+
+// INTID of the non-secure EL1 physical timer PPI, fixed by the Server Base
+// System Architecture and what QEMU's virt machine uses
+pub const PHYSICAL_TIMER_INTID: u32 = 30;
+
+// CNTP_CTL_EL0
+const CTL_ENABLE: u64 = 1 << 0;
+
+pub fn frequency() -> u64 {
+    let frequency: u64;
+    unsafe {
+        asm!("mrs {}, cntfrq_el0", out(reg) frequency, options(nomem, nostack));
+    }
+    frequency
+}
+
+// Raise the timer interrupt `ticks` counter ticks from now. CNTP_TVAL_EL0
+// is a signed 32-bit down-counter.
+pub fn set_physical_timer(ticks: u32) {
+    unsafe {
+        asm!(
+            "msr cntp_tval_el0, {ticks}",
+            "msr cntp_ctl_el0, {ctl}",
+            "isb",
+            ticks = in(reg) ticks.min(i32::MAX as u32) as u64,
+            ctl = in(reg) CTL_ENABLE,
+            options(nomem, nostack)
+        );
+    }
+}
+
+pub fn stop_physical_timer() {
+    unsafe {
+        asm!("msr cntp_ctl_el0, xzr", "isb", options(nomem, nostack));
+    }
+}
@@ -0,0 +1,62 @@
+// kernel/src/arch/fixup.rs
+//
+// Exception fixups. Code that may fault on purpose, such as a probe of an
+// address that might not be mapped, registers the range of instructions
+// that may fault and where to resume instead. The architectures' exception
+// handlers look here before treating a fault as fatal.
+
+use crate::sync::SpinLock;
+
+const MAX_FIXUPS: usize = 32;
+
+#[derive(Clone, Copy)]
+struct Fixup {
+    start: u64,
+    end: u64,
+    resume: u64,
+}
+
+struct Fixups {
+    entries: [Fixup; MAX_FIXUPS],
+    count: usize,
+}
+
+static FIXUPS: SpinLock<Fixups> = SpinLock::new(Fixups {
+    entries: [Fixup {
+        start: 0,
+        end: 0,
+        resume: 0,
+    }; MAX_FIXUPS],
+    count: 0,
+});
+
+// Resume at `resume` when an instruction in [start, end) faults. Registering
+// the same fixup again (from every CPU's init) is harmless. Returns false
+// when the table is full.
+pub fn register(start: u64, end: u64, resume: u64) -> bool {
+    let mut fixups = FIXUPS.lock();
+    let count = fixups.count;
+    if fixups.entries[..count]
+        .iter()
+        .any(|fixup| fixup.start == start && fixup.end == end)
+    {
+        return true;
+    }
+    if count == MAX_FIXUPS {
+        return false;
+    }
+
+    fixups.entries[count] = Fixup { start, end, resume };
+    fixups.count += 1;
+    true
+}
+
+// Where to resume after a fault at `ip`. A fault while the table is being
+// updated has no fixup, it can't be one of the registered instructions.
+pub fn search(ip: u64) -> Option<u64> {
+    let fixups = FIXUPS.try_lock()?;
+    fixups.entries[..fixups.count]
+        .iter()
+        .find(|fixup| (fixup.start..fixup.end).contains(&ip))
+        .map(|fixup| fixup.resume)
+}
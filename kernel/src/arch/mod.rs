@@ -0,0 +1,188 @@
+// kernel/src/arch/mod.rs
+//
+// Architecture abstraction layer. Every architecture implements `Arch`, and
+// architecture-neutral code only goes through the functions below, never
+// through `asm!` or an architecture module directly.
+
+use core::fmt;
+
+use crate::mm::vmm::{Entry, MapFlags};
+
+pub mod fixup;
+
+#[cfg(target_arch = "x86_64")]
+pub mod x86_64;
+
+#[cfg(target_arch = "aarch64")]
+pub mod aarch64;
+
+#[cfg(target_arch = "x86_64")]
+use self::x86_64::X86_64 as Current;
+
+#[cfg(target_arch = "aarch64")]
+use self::aarch64::Aarch64 as Current;
+
+pub trait Arch {
+    // Register snapshot for panic reports, printed in the architecture's own names
+    type Registers: fmt::Display;
+
+    // Per-CPU setup, `hw_id` is the CpuInfo::hw_id of the calling CPU
+    fn init_cpu(hw_id: u64);
+
+    // Sleep until the next interrupt (or event) arrives
+    fn wait_for_interrupt();
+
+    fn enable_interrupts();
+
+    // Unmask interrupts and sleep until one arrives, taking it on the way
+    // out. Called with interrupts masked, so one that comes in between a
+    // check and this can't be missed.
+    fn enable_interrupts_and_wait();
+
+    // Mask interrupts, returning whether they were enabled before
+    fn disable_interrupts() -> bool;
+
+    fn interrupts_enabled() -> bool;
+
+    // Free-running counter: TSC, CNTVCT_EL0 or the time CSR
+    fn read_cycle_counter() -> u64;
+
+    // Hardware ID of the calling CPU, matching CpuInfo::hw_id
+    fn cpu_id() -> u64;
+
+    // Invalidate the local TLB entry for one virtual address
+    fn flush_tlb_page(virt: u64);
+
+    // Invalidate all local non-global TLB entries
+    fn flush_tlb_all();
+
+    // Switch to the page tables rooted at physical address `root`.
+    // Safety: the new tables must map the running code, stack and data.
+    unsafe fn activate_page_table(root: u64);
+
+    // Physical address of the active page table root
+    fn current_page_table() -> u64;
+
+    // Page table formats for mm::vmm. Tables have 512 entries at every level;
+    // level 0 maps 4 KiB pages, level 1 entries can be 2 MiB pages, level 2
+    // entries 1 GiB pages, and level 3 is the root.
+
+    // Highest level that can hold a leaf rather than a table pointer
+    fn max_leaf_level() -> usize;
+
+    // Entry pointing at the next-level table at physical address `phys`
+    fn table_entry(phys: u64) -> u64;
+
+    // Entry mapping the page at `phys`, whose size is given by `level`
+    fn leaf_entry(phys: u64, flags: MapFlags, level: usize) -> u64;
+
+    fn decode_entry(entry: u64, level: usize) -> Entry;
+
+    // Read a byte that may not be mapped, None if the read faults
+    fn probe_read(addr: u64) -> Option<u8>;
+
+    // Snapshot the calling CPU's registers
+    fn capture_registers() -> Self::Registers;
+
+    // Frame pointer of the calling function
+    fn frame_pointer() -> u64;
+
+    // Address of the frame record {caller's frame pointer, return address}
+    // belonging to the frame at `fp`
+    fn frame_record(fp: u64) -> u64;
+}
+
+pub type Registers = <Current as Arch>::Registers;
+
+pub fn init_cpu(hw_id: u64) {
+    Current::init_cpu(hw_id)
+}
+
+// Idle the processor forever, waking only to go back to sleep
+pub fn halt() -> ! {
+    loop {
+        Current::wait_for_interrupt();
+    }
+}
+
+pub fn enable_interrupts() {
+    Current::enable_interrupts()
+}
+
+pub fn enable_interrupts_and_wait() {
+    Current::enable_interrupts_and_wait()
+}
+
+pub fn disable_interrupts() -> bool {
+    Current::disable_interrupts()
+}
+
+// Re-enable interrupts if `was_enabled`, the result of disable_interrupts
+pub fn restore_interrupts(was_enabled: bool) {
+    if was_enabled {
+        Current::enable_interrupts();
+    }
+}
+
+pub fn interrupts_enabled() -> bool {
+    Current::interrupts_enabled()
+}
+
+pub fn read_cycle_counter() -> u64 {
+    Current::read_cycle_counter()
+}
+
+pub fn cpu_id() -> u64 {
+    Current::cpu_id()
+}
+
+pub fn flush_tlb_page(virt: u64) {
+    Current::flush_tlb_page(virt)
+}
+
+pub fn flush_tlb_all() {
+    Current::flush_tlb_all()
+}
+
+pub unsafe fn activate_page_table(root: u64) {
+    Current::activate_page_table(root)
+}
+
+pub fn current_page_table() -> u64 {
+    Current::current_page_table()
+}
+
+pub fn max_leaf_level() -> usize {
+    Current::max_leaf_level()
+}
+
+pub fn table_entry(phys: u64) -> u64 {
+    Current::table_entry(phys)
+}
+
+pub fn leaf_entry(phys: u64, flags: MapFlags, level: usize) -> u64 {
+    Current::leaf_entry(phys, flags, level)
+}
+
+pub fn decode_entry(entry: u64, level: usize) -> Entry {
+    Current::decode_entry(entry, level)
+}
+
+pub fn probe_read(addr: u64) -> Option<u8> {
+    Current::probe_read(addr)
+}
+
+// Inlined so the snapshot shows the caller's state, not a helper's
+#[inline(always)]
+pub fn capture_registers() -> Registers {
+    Current::capture_registers()
+}
+
+#[inline(always)]
+pub fn frame_pointer() -> u64 {
+    Current::frame_pointer()
+}
+
+pub fn frame_record(fp: u64) -> u64 {
+    Current::frame_record(fp)
+}
@@ -0,0 +1,119 @@
+// kernel/src/arch/x86_64/gdt.rs
+//
+// The kernel's GDT and TSS, replacing the firmware's (which live in boot
+// services memory). Long mode ignores segment bases and limits, so the GDT
+// only has to describe 64-bit code and data for both privilege levels plus
+// the TSS, whose interrupt stack table gives double faults, NMIs and machine
+// checks a known-good stack even when the current one is what broke.
+//
+// There is one set of tables for now: only the BSP runs kernel code.
+
+use core::arch::asm;
+use core::mem::size_of;
+use core::ptr::addr_of;
+
+pub const KERNEL_CODE: u16 = 0x08;
+pub const KERNEL_DATA: u16 = 0x10;
+const TSS: u16 = 0x28;
+
+// Interrupt stack table slots, as used in IDT entries (0 means no switch)
+pub const IST_DOUBLE_FAULT: u8 = 1;
+pub const IST_NMI: u8 = 2;
+pub const IST_MACHINE_CHECK: u8 = 3;
+
+const IST_STACK_SIZE: usize = 16 * 1024;
+const IST_STACK_COUNT: usize = 3;
+
+#[repr(C, packed(4))]
+struct TaskStateSegment {
+    reserved0: u32,
+    // Stacks for entering rings 0-2 from a less privileged ring
+    rsp: [u64; 3],
+    reserved1: u64,
+    ist: [u64; 7],
+    reserved2: u64,
+    reserved3: u16,
+    // Past the end of the TSS: no I/O permission bitmap
+    iomap_base: u16,
+}
+
+#[repr(C, align(16))]
+struct IstStacks([[u8; IST_STACK_SIZE]; IST_STACK_COUNT]);
+
+// Null, kernel code, kernel data, user data, user code, then the two
+// halves of the TSS descriptor. User data comes before user code, the order
+// SYSRET expects; nothing runs in user mode yet.
+static mut GDT: [u64; 7] = [
+    0,
+    0x00AF_9A00_0000_FFFF,
+    0x00CF_9200_0000_FFFF,
+    0x00CF_F200_0000_FFFF,
+    0x00AF_FA00_0000_FFFF,
+    0,
+    0,
+];
+
+static mut TSS_SEGMENT: TaskStateSegment = TaskStateSegment {
+    reserved0: 0,
+    rsp: [0; 3],
+    reserved1: 0,
+    ist: [0; 7],
+    reserved2: 0,
+    reserved3: 0,
+    iomap_base: size_of::<TaskStateSegment>() as u16,
+};
+
+static mut IST_STACKS: IstStacks = IstStacks([[0; IST_STACK_SIZE]; IST_STACK_COUNT]);
+
+#[repr(C, packed(2))]
+struct DescriptorTablePointer {
+    limit: u16,
+    base: u64,
+}
+
+// Load the GDT and TSS and reload every segment register
+pub unsafe fn init() {
+    let stacks = addr_of!(IST_STACKS) as u64;
+    let tss = &mut *core::ptr::addr_of_mut!(TSS_SEGMENT);
+    for slot in [IST_DOUBLE_FAULT, IST_NMI, IST_MACHINE_CHECK] {
+        // Stacks grow down, so each slot gets the end of its stack
+        tss.ist[slot as usize - 1] = stacks + (slot as usize * IST_STACK_SIZE) as u64;
+    }
+
+    // 64-bit available TSS descriptor, base split across both halves
+    let base = addr_of!(TSS_SEGMENT) as u64;
+    let limit = (size_of::<TaskStateSegment>() - 1) as u64;
+    let gdt = &mut *core::ptr::addr_of_mut!(GDT);
+    gdt[5] = (limit & 0xFFFF)
+        | (base & 0xFF_FFFF) << 16
+        | 0x89 << 40
+        | ((base >> 24) & 0xFF) << 56;
+    gdt[6] = base >> 32;
+
+    let pointer = DescriptorTablePointer {
+        limit: (size_of::<[u64; 7]>() - 1) as u16,
+        base: gdt.as_ptr() as u64,
+    };
+
+    asm!(
+        "lgdt [{pointer}]",
+        // A far return is the only way to reload CS in long mode
+        "push {code}",
+        "lea {tmp}, [rip + 2f]",
+        "push {tmp}",
+        "retfq",
+        "2:",
+        "mov ds, {data:x}",
+        "mov es, {data:x}",
+        "mov ss, {data:x}",
+        "xor {tmp:e}, {tmp:e}",
+        "mov fs, {tmp:x}",
+        "mov gs, {tmp:x}",
+        "ltr {tss:x}",
+        pointer = in(reg) &pointer,
+        code = in(reg) KERNEL_CODE as u64,
+        data = in(reg) KERNEL_DATA as u64,
+        tss = in(reg) TSS as u64,
+        tmp = out(reg) _,
+    );
+}
@@ -0,0 +1,412 @@
+// kernel/src/arch/x86_64/idt.rs
+//
+// Interrupt descriptor table and CPU exception handling. Every one of the
+// 256 vectors has a small entry stub that pushes a dummy error code where
+// the CPU doesn't push one, then the vector number, and jumps to a common
+// stub that saves the general purpose registers as an ExceptionFrame and
+// calls handle_exception.
+//
+// Vectors 32 and up are external interrupts and go to interrupts::handle,
+// apart from the legacy PIC's and the local APIC's spurious vectors, which
+// must not be acknowledged. For the 32 exceptions, a fault inside a range registered with arch::fixup
+// resumes at its fixup address; anything else is reported through a panic.
+
+use core::arch::{asm, global_asm};
+use core::fmt;
+use core::mem::size_of;
+use core::ptr::addr_of;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::gdt;
+use super::Registers;
+use crate::arch::fixup;
+use crate::interrupts;
+
+const EXCEPTION_COUNT: usize = 32;
+
+// Spacing of the entry stubs, see exception_stubs below
+const STUB_SIZE: u64 = 16;
+
+const PAGE_FAULT: u64 = 14;
+
+// The 8259 PICs are remapped onto these 16 vectors before being masked,
+// where only their spurious IRQs 7 and 15 can still show up
+pub const PIC_VECTOR_BASE: u8 = 0x20;
+const PIC_VECTOR_COUNT: u8 = 16;
+
+// Vectors handed out by allocate_vector(), up to the spurious vector
+const FIRST_DYNAMIC_VECTOR: u8 = PIC_VECTOR_BASE + PIC_VECTOR_COUNT;
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+// Present 64-bit interrupt gate, DPL 0
+const INTERRUPT_GATE: u8 = 0x8E;
+
+const EXCEPTION_NAMES: [&str; EXCEPTION_COUNT] = [
+    "Divide error",
+    "Debug",
+    "Non-maskable interrupt",
+    "Breakpoint",
+    "Overflow",
+    "Bound range exceeded",
+    "Invalid opcode",
+    "Device not available",
+    "Double fault",
+    "Coprocessor segment overrun",
+    "Invalid TSS",
+    "Segment not present",
+    "Stack-segment fault",
+    "General protection fault",
+    "Page fault",
+    "Reserved (15)",
+    "x87 floating-point exception",
+    "Alignment check",
+    "Machine check",
+    "SIMD floating-point exception",
+    "Virtualization exception",
+    "Control protection exception",
+    "Reserved (22)",
+    "Reserved (23)",
+    "Reserved (24)",
+    "Reserved (25)",
+    "Reserved (26)",
+    "Reserved (27)",
+    "Hypervisor injection exception",
+    "VMM communication exception",
+    "Security exception",
+    "Reserved (31)",
+];
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IdtEntry {
+    offset_low: u16,
+    selector: u16,
+    ist: u8,
+    type_attr: u8,
+    offset_mid: u16,
+    offset_high: u32,
+    reserved: u32,
+}
+
+impl IdtEntry {
+    const MISSING: IdtEntry = IdtEntry {
+        offset_low: 0,
+        selector: 0,
+        ist: 0,
+        type_attr: 0,
+        offset_mid: 0,
+        offset_high: 0,
+        reserved: 0,
+    };
+
+    fn new(handler: u64, ist: u8) -> IdtEntry {
+        IdtEntry {
+            offset_low: handler as u16,
+            selector: gdt::KERNEL_CODE,
+            ist,
+            type_attr: INTERRUPT_GATE,
+            offset_mid: (handler >> 16) as u16,
+            offset_high: (handler >> 32) as u32,
+            reserved: 0,
+        }
+    }
+}
+
+static mut IDT: [IdtEntry; 256] = [IdtEntry::MISSING; 256];
+
+// One bit per vector, set while it's allocated
+static ALLOCATED_VECTORS: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+
+#[repr(C, packed(2))]
+struct DescriptorTablePointer {
+    limit: u16,
+    base: u64,
+}
+
+// What the entry stubs leave on the stack, lowest address first
+#[repr(C)]
+pub struct ExceptionFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rax: u64,
+    pub vector: u64,
+    pub error_code: u64,
+    // Pushed by the CPU
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl ExceptionFrame {
+    // The interrupted state, with the control registers as they are now
+    pub fn registers(&self) -> Registers {
+        let (cr0, cr2, cr3, cr4): (u64, u64, u64, u64);
+        unsafe {
+            asm!(
+                "mov {}, cr0",
+                "mov {}, cr2",
+                "mov {}, cr3",
+                "mov {}, cr4",
+                out(reg) cr0,
+                out(reg) cr2,
+                out(reg) cr3,
+                out(reg) cr4,
+                options(nomem, nostack, preserves_flags)
+            );
+        }
+
+        Registers {
+            gpr: [
+                self.rax, self.rcx, self.rdx, self.rbx, self.rsp, self.rbp, self.rsi, self.rdi,
+                self.r8, self.r9, self.r10, self.r11, self.r12, self.r13, self.r14, self.r15,
+            ],
+            rip: self.rip,
+            rflags: self.rflags,
+            cr0,
+            cr2,
+            cr3,
+            cr4,
+        }
+    }
+}
+
+impl fmt::Display for ExceptionFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = EXCEPTION_NAMES
+            .get(self.vector as usize)
+            .copied()
+            .unwrap_or("Unknown exception");
+        writeln!(
+            f,
+            "{} (vector {}, error code {:#x}) at {:#x}",
+            name, self.vector, self.error_code, self.rip
+        )?;
+
+        let registers = self.registers();
+        if self.vector == PAGE_FAULT {
+            writeln!(f, "CR2={:016x}: {}", registers.cr2, PageFaultCause(self.error_code))?;
+        }
+        write!(f, "{}", registers)
+    }
+}
+
+// Page fault error code bits
+const PF_PRESENT: u64 = 1 << 0;
+const PF_WRITE: u64 = 1 << 1;
+const PF_USER: u64 = 1 << 2;
+const PF_RESERVED: u64 = 1 << 3;
+const PF_INSTRUCTION: u64 = 1 << 4;
+const PF_PROTECTION_KEY: u64 = 1 << 5;
+const PF_SHADOW_STACK: u64 = 1 << 6;
+
+struct PageFaultCause(u64);
+
+impl fmt::Display for PageFaultCause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = self.0;
+        let access = if code & PF_INSTRUCTION != 0 {
+            "instruction fetch"
+        } else if code & PF_WRITE != 0 {
+            "write"
+        } else {
+            "read"
+        };
+        let mode = if code & PF_USER != 0 { "user" } else { "kernel" };
+        write!(f, "{} {} of a ", mode, access)?;
+        f.write_str(if code & PF_PRESENT != 0 {
+            "present page (protection violation)"
+        } else {
+            "non-present page"
+        })?;
+
+        if code & PF_RESERVED != 0 {
+            f.write_str(", reserved bit set in a paging entry")?;
+        }
+        if code & PF_PROTECTION_KEY != 0 {
+            f.write_str(", protection key")?;
+        }
+        if code & PF_SHADOW_STACK != 0 {
+            f.write_str(", shadow stack")?;
+        }
+        Ok(())
+    }
+}
+
+extern "C" {
+    // First of the 256 entry stubs, STUB_SIZE bytes apart
+    fn exception_stubs();
+}
+
+// Fill in every vector and load the IDT
+pub unsafe fn init() {
+    let idt = &mut *core::ptr::addr_of_mut!(IDT);
+    for (vector, entry) in idt.iter_mut().enumerate() {
+        let ist = match vector {
+            2 => gdt::IST_NMI,
+            8 => gdt::IST_DOUBLE_FAULT,
+            18 => gdt::IST_MACHINE_CHECK,
+            _ => 0,
+        };
+        let stub = exception_stubs as *const () as u64 + vector as u64 * STUB_SIZE;
+        *entry = IdtEntry::new(stub, ist);
+    }
+
+    let pointer = DescriptorTablePointer {
+        limit: (size_of::<[IdtEntry; 256]>() - 1) as u16,
+        base: addr_of!(IDT) as u64,
+    };
+    asm!("lidt [{}]", in(reg) &pointer, options(nostack, preserves_flags));
+}
+
+// Claim a free interrupt vector for a device or IPI. Vectors are shared by
+// every CPU; the local APIC prioritises higher vectors.
+pub fn allocate_vector() -> Option<u8> {
+    (FIRST_DYNAMIC_VECTOR..SPURIOUS_VECTOR).find(|&vector| {
+        let bit = 1 << (vector % 64);
+        ALLOCATED_VECTORS[vector as usize / 64].fetch_or(bit, Ordering::AcqRel) & bit == 0
+    })
+}
+
+pub fn free_vector(vector: u8) {
+    if vector >= FIRST_DYNAMIC_VECTOR && vector != SPURIOUS_VECTOR {
+        let bit = 1 << (vector % 64);
+        ALLOCATED_VECTORS[vector as usize / 64].fetch_and(!bit, Ordering::AcqRel);
+    }
+}
+
+extern "C" fn handle_exception(frame: &mut ExceptionFrame) {
+    if frame.vector as usize >= EXCEPTION_COUNT {
+        let vector = frame.vector as u8;
+        let pic_vectors = PIC_VECTOR_BASE..PIC_VECTOR_BASE + PIC_VECTOR_COUNT;
+        if vector != SPURIOUS_VECTOR && !pic_vectors.contains(&vector) {
+            interrupts::handle(Some(vector as u32));
+        }
+        return;
+    }
+
+    if let Some(resume) = fixup::search(frame.rip) {
+        frame.rip = resume;
+        return;
+    }
+
+    panic!("{}", frame);
+}
+
+// The vectors for which the CPU pushes an error code are 8, 10-14, 17, 21,
+// 29 and 30, never for interrupts. The common stub builds a frame record
+// {RBP, RIP} on top of the ExceptionFrame so backtraces continue into the
+// interrupted code.
+global_asm!(
+    ".macro exception_stub vector, error_code",
+    "    .p2align 4",
+    "    .if \\error_code == 0",
+    "    push 0",
+    "    .endif",
+    "    push \\vector",
+    "    jmp exception_common",
+    ".endm",
+    "",
+    ".section .text.exception_stubs, \"ax\"",
+    ".p2align 4",
+    ".global exception_stubs",
+    "exception_stubs:",
+    "exception_stub 0, 0",
+    "exception_stub 1, 0",
+    "exception_stub 2, 0",
+    "exception_stub 3, 0",
+    "exception_stub 4, 0",
+    "exception_stub 5, 0",
+    "exception_stub 6, 0",
+    "exception_stub 7, 0",
+    "exception_stub 8, 1",
+    "exception_stub 9, 0",
+    "exception_stub 10, 1",
+    "exception_stub 11, 1",
+    "exception_stub 12, 1",
+    "exception_stub 13, 1",
+    "exception_stub 14, 1",
+    "exception_stub 15, 0",
+    "exception_stub 16, 0",
+    "exception_stub 17, 1",
+    "exception_stub 18, 0",
+    "exception_stub 19, 0",
+    "exception_stub 20, 0",
+    "exception_stub 21, 1",
+    "exception_stub 22, 0",
+    "exception_stub 23, 0",
+    "exception_stub 24, 0",
+    "exception_stub 25, 0",
+    "exception_stub 26, 0",
+    "exception_stub 27, 0",
+    "exception_stub 28, 0",
+    "exception_stub 29, 1",
+    "exception_stub 30, 1",
+    "exception_stub 31, 0",
+    ".altmacro",
+    ".set vector, 32",
+    ".rept 256 - 32",
+    "exception_stub %vector, 0",
+    ".set vector, vector + 1",
+    ".endr",
+    ".noaltmacro",
+    "",
+    "exception_common:",
+    "    push rax",
+    "    push rcx",
+    "    push rdx",
+    "    push rbx",
+    "    push rbp",
+    "    push rsi",
+    "    push rdi",
+    "    push r8",
+    "    push r9",
+    "    push r10",
+    "    push r11",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    mov rdi, rsp",
+    // RIP sits above the 15 registers, the vector and the error code
+    "    push qword ptr [rsp + 17 * 8]",
+    "    push rbp",
+    "    mov rbp, rsp",
+    "    cld",
+    "    call {handler}",
+    "    add rsp, 16",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop r11",
+    "    pop r10",
+    "    pop r9",
+    "    pop r8",
+    "    pop rdi",
+    "    pop rsi",
+    "    pop rbp",
+    "    pop rbx",
+    "    pop rdx",
+    "    pop rcx",
+    "    pop rax",
+    // Vector and error code
+    "    add rsp, 16",
+    "    iretq",
+    ".purgem exception_stub",
+    ".previous",
+    handler = sym handle_exception,
+);
@@ -0,0 +1,248 @@
+// kernel/src/arch/x86_64/mod.rs
+use core::arch::{asm, global_asm};
+use core::fmt;
+
+use super::{fixup, Arch};
+use crate::mm::vmm::{Entry, MapFlags};
+
+pub mod gdt;
+pub mod idt;
+pub mod msr;
+pub mod paging;
+pub mod port;
+
+pub struct X86_64;
+
+// RFLAGS.IF
+const RFLAGS_IF: u64 = 1 << 9;
+
+// General purpose registers in encoding order, then RIP and RFLAGS
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Registers {
+    pub gpr: [u64; 16],
+    pub rip: u64,
+    pub rflags: u64,
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+}
+
+const GPR_NAMES: [&str; 16] = [
+    "RAX", "RCX", "RDX", "RBX", "RSP", "RBP", "RSI", "RDI",
+    "R8", "R9", "R10", "R11", "R12", "R13", "R14", "R15",
+];
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, value)) in GPR_NAMES.iter().zip(self.gpr).enumerate() {
+            write!(f, "{:>3}={:016x}", name, value)?;
+            f.write_str(if i % 4 == 3 { "\n" } else { " " })?;
+        }
+        writeln!(f, "RIP={:016x} RFLAGS={:016x}", self.rip, self.rflags)?;
+        write!(
+            f,
+            "CR0={:016x} CR2={:016x} CR3={:016x} CR4={:016x}",
+            self.cr0, self.cr2, self.cr3, self.cr4
+        )
+    }
+}
+
+impl Arch for X86_64 {
+    type Registers = Registers;
+
+    fn init_cpu(_hw_id: u64) {
+        unsafe {
+            gdt::init();
+            idt::init();
+            paging::init();
+        }
+        // Entries cached under the old PAT may carry the old memory types
+        Self::flush_tlb_all();
+
+        fixup::register(
+            probe_read_byte as *const () as u64,
+            probe_read_byte_end as *const () as u64,
+            probe_read_byte_fault as *const () as u64,
+        );
+    }
+
+    fn wait_for_interrupt() {
+        unsafe {
+            asm!("hlt", options(nomem, nostack));
+        }
+    }
+
+    fn enable_interrupts() {
+        unsafe {
+            asm!("sti", options(nomem, nostack));
+        }
+    }
+
+    // sti holds interrupts off for one more instruction, so none are taken
+    // before the hlt
+    fn enable_interrupts_and_wait() {
+        unsafe {
+            asm!("sti", "hlt", options(nomem, nostack));
+        }
+    }
+
+    fn disable_interrupts() -> bool {
+        let was_enabled = Self::interrupts_enabled();
+        unsafe {
+            asm!("cli", options(nomem, nostack));
+        }
+        was_enabled
+    }
+
+    fn interrupts_enabled() -> bool {
+        let rflags: u64;
+        unsafe {
+            asm!("pushfq", "pop {}", out(reg) rflags, options(nomem, preserves_flags));
+        }
+        rflags & RFLAGS_IF != 0
+    }
+
+    fn read_cycle_counter() -> u64 {
+        let low: u32;
+        let high: u32;
+        unsafe {
+            asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack));
+        }
+        ((high as u64) << 32) | low as u64
+    }
+
+    // Initial local APIC ID (CPUID leaf 1, EBX[31:24])
+    #[allow(unused_unsafe)]
+    fn cpu_id() -> u64 {
+        let cpuid = unsafe { core::arch::x86_64::__cpuid(1) };
+        (cpuid.ebx >> 24) as u64
+    }
+
+    fn flush_tlb_page(virt: u64) {
+        unsafe {
+            asm!("invlpg [{}]", in(reg) virt, options(nostack, preserves_flags));
+        }
+    }
+
+    fn flush_tlb_all() {
+        // Reloading CR3 drops every non-global entry
+        unsafe {
+            Self::activate_page_table(Self::current_page_table());
+        }
+    }
+
+    unsafe fn activate_page_table(root: u64) {
+        asm!("mov cr3, {}", in(reg) root, options(nostack, preserves_flags));
+    }
+
+    fn current_page_table() -> u64 {
+        let cr3: u64;
+        unsafe {
+            asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+        }
+        // Strip the PCID and flag bits
+        cr3 & 0x000F_FFFF_FFFF_F000
+    }
+
+    fn max_leaf_level() -> usize {
+        paging::max_leaf_level()
+    }
+
+    fn table_entry(phys: u64) -> u64 {
+        paging::table_entry(phys)
+    }
+
+    fn leaf_entry(phys: u64, flags: MapFlags, level: usize) -> u64 {
+        paging::leaf_entry(phys, flags, level)
+    }
+
+    fn decode_entry(entry: u64, level: usize) -> Entry {
+        paging::decode_entry(entry, level)
+    }
+
+    fn probe_read(addr: u64) -> Option<u8> {
+        let mut byte = 0;
+        unsafe { probe_read_byte(addr, &mut byte) }.then_some(byte)
+    }
+
+    // RDI carries the destination, so its saved value is the snapshot's address
+    #[inline(always)]
+    fn capture_registers() -> Registers {
+        let mut regs = Registers::default();
+        unsafe {
+            asm!(
+                "mov [rdi + 0x00], rax",
+                "mov [rdi + 0x08], rcx",
+                "mov [rdi + 0x10], rdx",
+                "mov [rdi + 0x18], rbx",
+                "mov [rdi + 0x20], rsp",
+                "mov [rdi + 0x28], rbp",
+                "mov [rdi + 0x30], rsi",
+                "mov [rdi + 0x38], rdi",
+                "mov [rdi + 0x40], r8",
+                "mov [rdi + 0x48], r9",
+                "mov [rdi + 0x50], r10",
+                "mov [rdi + 0x58], r11",
+                "mov [rdi + 0x60], r12",
+                "mov [rdi + 0x68], r13",
+                "mov [rdi + 0x70], r14",
+                "mov [rdi + 0x78], r15",
+                "lea rax, [rip]",
+                "mov [rdi + 0x80], rax",
+                "pushfq",
+                "pop qword ptr [rdi + 0x88]",
+                "mov rax, cr0",
+                "mov [rdi + 0x90], rax",
+                "mov rax, cr2",
+                "mov [rdi + 0x98], rax",
+                "mov rax, cr3",
+                "mov [rdi + 0xa0], rax",
+                "mov rax, cr4",
+                "mov [rdi + 0xa8], rax",
+                in("rdi") &mut regs as *mut Registers,
+                out("rax") _,
+            );
+        }
+        regs
+    }
+
+    #[inline(always)]
+    fn frame_pointer() -> u64 {
+        let rbp: u64;
+        unsafe {
+            asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+        }
+        rbp
+    }
+
+    // push rbp; mov rbp, rsp: RBP points at the saved RBP, the return address above it
+    fn frame_record(fp: u64) -> u64 {
+        fp
+    }
+}
+
+extern "C" {
+    // Copy the byte at `addr` to `out`, or return false if reading it faults
+    fn probe_read_byte(addr: u64, out: *mut u8) -> bool;
+    fn probe_read_byte_end();
+    fn probe_read_byte_fault();
+}
+
+global_asm!(
+    ".section .text.probe_read_byte, \"ax\"",
+    ".global probe_read_byte",
+    ".global probe_read_byte_end",
+    ".global probe_read_byte_fault",
+    "probe_read_byte:",
+    "    mov al, byte ptr [rdi]",
+    "probe_read_byte_end:",
+    "    mov byte ptr [rsi], al",
+    "    mov eax, 1",
+    "    ret",
+    "probe_read_byte_fault:",
+    "    xor eax, eax",
+    "    ret",
+    ".previous",
+);
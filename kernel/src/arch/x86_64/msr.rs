@@ -0,0 +1,33 @@
+// kernel/src/arch/x86_64/msr.rs
+//
+// Model-specific registers. Writing the wrong value to most of these takes
+// the machine down, which is why both directions are unsafe.
+
+use core::arch::asm;
+
+pub const IA32_APIC_BASE: u32 = 0x1B;
+pub const IA32_PAT: u32 = 0x277;
+pub const IA32_EFER: u32 = 0xC000_0080;
+
+pub unsafe fn read(msr: u32) -> u64 {
+    let low: u32;
+    let high: u32;
+    asm!(
+        "rdmsr",
+        in("ecx") msr,
+        out("eax") low,
+        out("edx") high,
+        options(nomem, nostack, preserves_flags)
+    );
+    ((high as u64) << 32) | low as u64
+}
+
+pub unsafe fn write(msr: u32, value: u64) {
+    asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack, preserves_flags)
+    );
+}
@@ -0,0 +1,113 @@
+// kernel/src/arch/x86_64/paging.rs
+//
+// 4-level (PML4) page table entries. Cache modes are selected through the
+// PAT, which init() reprograms so that the PWT bit alone means
+// write-combining and PWT|PCD means uncached; every PAT slot the firmware
+// could be relying on for write-back stays write-back.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::msr;
+use crate::mm::vmm::{CacheMode, Entry, MapFlags};
+
+const PRESENT: u64 = 1 << 0;
+const WRITABLE: u64 = 1 << 1;
+const USER: u64 = 1 << 2;
+const PWT: u64 = 1 << 3;
+const PCD: u64 = 1 << 4;
+const ACCESSED: u64 = 1 << 5;
+const DIRTY: u64 = 1 << 6;
+// Leaf at level 1 or 2 rather than a pointer to the next table
+const HUGE: u64 = 1 << 7;
+const NO_EXECUTE: u64 = 1 << 63;
+
+const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+// PAT memory types, one byte per slot: WB, WC, UC-, UC, repeated. Slot 0 is
+// write-back, slot 1 (PWT) write-combining, slot 3 (PWT|PCD) uncached.
+const PAT_VALUE: u64 = 0x0007_0106_0007_0106;
+
+const EFER_NXE: u64 = 1 << 11;
+
+// CPUID 0x80000001 EDX
+const CPUID_NX: u32 = 1 << 20;
+const CPUID_1G_PAGES: u32 = 1 << 26;
+
+// Without EFER.NXE bit 63 is reserved and setting it faults
+static NX_ENABLED: AtomicBool = AtomicBool::new(false);
+static HUGE_1G: AtomicBool = AtomicBool::new(false);
+
+// Program the PAT and enable no-execute pages on the calling CPU
+pub unsafe fn init() {
+    let features = core::arch::x86_64::__cpuid(0x8000_0001);
+
+    msr::write(msr::IA32_PAT, PAT_VALUE);
+
+    if features.edx & CPUID_NX != 0 {
+        msr::write(msr::IA32_EFER, msr::read(msr::IA32_EFER) | EFER_NXE);
+        NX_ENABLED.store(true, Ordering::Relaxed);
+    }
+    HUGE_1G.store(features.edx & CPUID_1G_PAGES != 0, Ordering::Relaxed);
+}
+
+pub fn max_leaf_level() -> usize {
+    if HUGE_1G.load(Ordering::Relaxed) {
+        2
+    } else {
+        1
+    }
+}
+
+// Permissions are only enforced at the leaves, so tables allow everything
+pub fn table_entry(phys: u64) -> u64 {
+    (phys & ADDRESS_MASK) | PRESENT | WRITABLE | USER
+}
+
+pub fn leaf_entry(phys: u64, flags: MapFlags, level: usize) -> u64 {
+    // Accessed and dirty are preset so the CPU never writes the entry back
+    let mut entry = (phys & ADDRESS_MASK) | PRESENT | ACCESSED | DIRTY;
+    if level > 0 {
+        entry |= HUGE;
+    }
+    if flags.writable {
+        entry |= WRITABLE;
+    }
+    if flags.user {
+        entry |= USER;
+    }
+    if !flags.executable && NX_ENABLED.load(Ordering::Relaxed) {
+        entry |= NO_EXECUTE;
+    }
+    entry |= match flags.cache {
+        CacheMode::WriteBack => 0,
+        CacheMode::WriteCombining => PWT,
+        CacheMode::Uncached => PWT | PCD,
+    };
+    entry
+}
+
+pub fn decode_entry(entry: u64, level: usize) -> Entry {
+    if entry & PRESENT == 0 {
+        return Entry::Empty;
+    }
+    if level > 0 && entry & HUGE == 0 {
+        return Entry::Table(entry & ADDRESS_MASK);
+    }
+
+    // Bit 12 of a huge page entry is its PAT bit, not part of the address
+    let size_mask = (1u64 << (12 + 9 * level)) - 1;
+    let cache = match entry & (PWT | PCD) {
+        0 => CacheMode::WriteBack,
+        PWT => CacheMode::WriteCombining,
+        _ => CacheMode::Uncached,
+    };
+    Entry::Leaf(
+        entry & ADDRESS_MASK & !size_mask,
+        MapFlags {
+            writable: entry & WRITABLE != 0,
+            executable: entry & NO_EXECUTE == 0,
+            user: entry & USER != 0,
+            cache,
+        },
+    )
+}
@@ -0,0 +1,59 @@
+// kernel/src/cmdline.rs
+//
+// Kernel command line, taken from the boot entry. It is a list of
+// space-separated words, either flags ("quiet") or options ("panic=halt").
+
+use crate::sync::SpinLock;
+use crate::BootInfo;
+
+// Must match MAX_CMDLINE in the bootloader
+pub const MAX_CMDLINE: usize = 256;
+
+#[derive(Clone, Copy)]
+pub struct CommandLine {
+    buffer: [u8; MAX_CMDLINE],
+    len: usize,
+}
+
+impl CommandLine {
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buffer[..self.len]).unwrap_or("")
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.as_str().split_ascii_whitespace()
+    }
+
+    // Value of the last "key=value" word, later words override earlier ones
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.words()
+            .filter_map(|word| word.split_once('='))
+            .filter(|&(k, _)| k == key)
+            .map(|(_, value)| value)
+            .last()
+    }
+
+    // Only the aarch64 test harness reads flags, to find "semihosting"
+    #[cfg_attr(not(all(test, target_arch = "aarch64")), allow(dead_code))]
+    pub fn flag(&self, key: &str) -> bool {
+        self.words().any(|word| word == key)
+    }
+}
+
+static CMDLINE: SpinLock<CommandLine> = SpinLock::new(CommandLine {
+    buffer: [0; MAX_CMDLINE],
+    len: 0,
+});
+
+pub fn init(boot_info: &BootInfo) -> CommandLine {
+    let mut cmdline = CMDLINE.lock();
+    cmdline.len = boot_info.cmdline_len.min(MAX_CMDLINE);
+    cmdline.buffer = boot_info.cmdline;
+    *cmdline
+}
+
+// Only the aarch64 test harness reads the command line after boot
+#[cfg_attr(not(all(test, target_arch = "aarch64")), allow(dead_code))]
+pub fn get() -> CommandLine {
+    *CMDLINE.lock()
+}
@@ -0,0 +1,72 @@
+// kernel/src/console/font.rs
+//
+// PC Screen Font (PSF1 and PSF2) bitmap fonts. Glyphs are looked up by
+// code point, so the font's first 256 glyphs are expected to follow Latin-1;
+// the Unicode tables are not used.
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+
+pub struct Font {
+    glyphs: &'static [u8],
+    glyph_count: usize,
+    bytes_per_glyph: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Font {
+    pub fn parse(data: &'static [u8]) -> Option<Font> {
+        let read_u32 = |offset: usize| -> Option<usize> {
+            let bytes = data.get(offset..offset + 4)?;
+            Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+        };
+
+        let (header_size, glyph_count, bytes_per_glyph, width, height) =
+            if data.starts_with(&PSF1_MAGIC) {
+                let mode = *data.get(2)?;
+                let height = *data.get(3)? as usize;
+                let count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+                (4, count, height, 8, height)
+            } else if data.starts_with(&PSF2_MAGIC) {
+                (
+                    read_u32(8)?,
+                    read_u32(16)?,
+                    read_u32(20)?,
+                    read_u32(28)?,
+                    read_u32(24)?,
+                )
+            } else {
+                return None;
+            };
+
+        if width == 0 || height == 0 || bytes_per_glyph < height * width.div_ceil(8) {
+            return None;
+        }
+
+        let glyphs = data.get(header_size..header_size + glyph_count * bytes_per_glyph)?;
+        Some(Font {
+            glyphs,
+            glyph_count,
+            bytes_per_glyph,
+            width,
+            height,
+        })
+    }
+
+    pub fn bytes_per_row(&self) -> usize {
+        self.width.div_ceil(8)
+    }
+
+    // Rows of the glyph, most significant bit leftmost
+    pub fn glyph(&self, c: char) -> Option<&'static [u8]> {
+        let index = c as usize;
+        if index >= self.glyph_count {
+            return None;
+        }
+        let start = index * self.bytes_per_glyph;
+        Some(&self.glyphs[start..start + self.bytes_per_glyph])
+    }
+}
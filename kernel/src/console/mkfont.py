@@ -0,0 +1,36 @@
+# kernel/src/console/mkfont.py
+#
+# Builds default8x16.psf from the glyph drawings in default8x16.txt:
+#
+#   python3 mkfont.py default8x16.txt default8x16.psf
+#
+# Each glyph is "=XX" (its code point in hex) followed by up to 13 rows of
+# 7 pixels, '#' set and '.' clear. Rows are placed from the third of the 16
+# scanlines down, leaving the top two and at least the bottom one blank.
+# Code points with no drawing stay blank.
+import sys
+
+glyphs = {}
+current = None
+for line in open(sys.argv[1]):
+    line = line.rstrip('\n')
+    if line.startswith('='):
+        current = int(line[1:], 16)
+        glyphs[current] = []
+    elif line:
+        assert len(line) == 7, (hex(current), line)
+        glyphs[current].append(line)
+
+# PSF1 header: magic, mode 0 (256 glyphs, no Unicode table), 16 scanlines
+data = bytearray(b'\x36\x04\x00\x10')
+for c in range(256):
+    rows = [0] * 16
+    for i, row in enumerate(glyphs.get(c, [])):
+        assert i < 13, hex(c)
+        rows[2 + i] = sum(0x80 >> j for j, pixel in enumerate(row) if pixel == '#')
+    data += bytes(rows)
+
+missing = [chr(c) for c in range(0x21, 0x7F) if c not in glyphs]
+if missing:
+    print('no drawing for', ' '.join(missing), file=sys.stderr)
+open(sys.argv[2], 'wb').write(data)
@@ -0,0 +1,336 @@
+// kernel/src/console/mod.rs
+//
+// Text console on the UEFI framebuffer. Renders the built-in 8x16 PSF font,
+// scrolls, and understands \n, \r, \t, backspace and the colour subset of
+// ANSI SGR escapes (ESC [ ... m). Other CSI sequences are swallowed.
+//
+// Colours are 0xRRGGBB and are packed into 32-bit pixels with the channel
+// masks the bootloader got from GOP: BGRx, RGBx or a bitmask. A GOP mode
+// with no framebuffer to draw on (Blt only) leaves the console on serial.
+
+mod font;
+
+use font::Font;
+
+use crate::sync::SpinLock;
+use crate::BootInfo;
+
+#[cfg(test)]
+mod tests;
+
+// Drawn for MelonOS and under the project's BSD 3-Clause license. Generated
+// from the drawings in default8x16.txt by mkfont.py; only printable ASCII
+// has glyphs.
+static DEFAULT_FONT: &[u8] = include_bytes!("default8x16.psf");
+
+const TAB_WIDTH: usize = 8;
+const MAX_CSI_PARAMS: usize = 8;
+
+// xterm's 16 colour palette: the 8 normal colours, then their bright versions
+const PALETTE: [u32; 16] = [
+    0x000000, 0xCD0000, 0x00CD00, 0xCDCD00, 0x0000EE, 0xCD00CD, 0x00CDCD, 0xE5E5E5,
+    0x7F7F7F, 0xFF0000, 0x00FF00, 0xFFFF00, 0x5C5CFF, 0xFF00FF, 0x00FFFF, 0xFFFFFF,
+];
+
+const DEFAULT_FG: u32 = PALETTE[7];
+const DEFAULT_BG: u32 = PALETTE[0];
+
+// Colours of the panic screen
+const PANIC_FG: u32 = PALETTE[15];
+const PANIC_BG: u32 = 0x0000AA;
+
+enum Escape {
+    None,
+    Esc,
+    Csi {
+        params: [u16; MAX_CSI_PARAMS],
+        count: usize,
+    },
+}
+
+// Where one colour goes in a pixel
+#[derive(Clone, Copy)]
+struct Channel {
+    shift: u32,
+    bits: u32,
+}
+
+impl Channel {
+    fn new(mask: u32) -> Channel {
+        Channel {
+            shift: mask.trailing_zeros() % 32,
+            bits: mask.count_ones(),
+        }
+    }
+
+    // Scale an 8-bit colour value to the channel's width and put it in place
+    fn pack(self, value: u32) -> u32 {
+        let scaled = if self.bits >= 8 {
+            value << (self.bits - 8)
+        } else {
+            value >> (8 - self.bits)
+        };
+        scaled << self.shift
+    }
+}
+
+struct Console {
+    framebuffer: *mut u32,
+    channels: [Channel; 3],
+    width: usize,
+    height: usize,
+    stride: usize,
+    font: Font,
+    columns: usize,
+    rows: usize,
+    column: usize,
+    row: usize,
+    fg: u32,
+    bg: u32,
+    // What SGR 0/39/49 go back to
+    default_fg: u32,
+    default_bg: u32,
+    bold: bool,
+    escape: Escape,
+}
+
+// The framebuffer is only ever touched with the console lock held
+unsafe impl Send for Console {}
+
+static CONSOLE: SpinLock<Option<Console>> = SpinLock::new(None);
+
+// Take over the framebuffer and clear it. Without one, output only goes to
+// the kernel log and serial.
+pub fn init(boot_info: &BootInfo) {
+    if boot_info.framebuffer_addr == 0 {
+        return;
+    }
+
+    let font = match Font::parse(DEFAULT_FONT) {
+        Some(font) => font,
+        None => return,
+    };
+
+    let mut console = Console {
+        framebuffer: boot_info.framebuffer_addr as *mut u32,
+        channels: [
+            Channel::new(boot_info.framebuffer_red_mask),
+            Channel::new(boot_info.framebuffer_green_mask),
+            Channel::new(boot_info.framebuffer_blue_mask),
+        ],
+        width: boot_info.framebuffer_width,
+        height: boot_info.framebuffer_height,
+        stride: boot_info.framebuffer_stride,
+        columns: boot_info.framebuffer_width / font.width,
+        rows: boot_info.framebuffer_height / font.height,
+        font,
+        column: 0,
+        row: 0,
+        fg: DEFAULT_FG,
+        bg: DEFAULT_BG,
+        default_fg: DEFAULT_FG,
+        default_bg: DEFAULT_BG,
+        bold: false,
+        escape: Escape::None,
+    };
+
+    if console.columns == 0 || console.rows == 0 {
+        return;
+    }
+
+    console.clear();
+    *CONSOLE.lock() = Some(console);
+}
+
+pub fn is_available() -> bool {
+    CONSOLE.lock().is_some()
+}
+
+pub fn write_str(s: &str) {
+    if let Some(console) = CONSOLE.lock().as_mut() {
+        for c in s.chars() {
+            console.write_char(c);
+        }
+    }
+}
+
+// Repaint the whole screen white on blue for a panic report
+pub fn panic_screen() {
+    if let Some(console) = CONSOLE.lock().as_mut() {
+        console.escape = Escape::None;
+        console.default_fg = PANIC_FG;
+        console.default_bg = PANIC_BG;
+        console.reset_attributes();
+        console.clear();
+    }
+}
+
+// See SpinLock::force_unlock
+pub unsafe fn force_unlock() {
+    CONSOLE.force_unlock();
+}
+
+impl Console {
+    fn write_char(&mut self, c: char) {
+        match self.escape {
+            Escape::None => {}
+            Escape::Esc => {
+                self.escape = if c == '[' {
+                    Escape::Csi {
+                        params: [0; MAX_CSI_PARAMS],
+                        count: 0,
+                    }
+                } else {
+                    Escape::None
+                };
+                return;
+            }
+            Escape::Csi {
+                ref mut params,
+                ref mut count,
+            } => {
+                match c {
+                    '0'..='9' => {
+                        // The first digit opens the first parameter
+                        if *count == 0 {
+                            *count = 1;
+                        }
+                        if let Some(param) = params.get_mut(*count - 1) {
+                            *param = param.saturating_mul(10).saturating_add(c as u16 - b'0' as u16);
+                        }
+                    }
+                    ';' => *count = (*count).max(1) + 1,
+                    // Parameter and intermediate bytes we don't interpret
+                    '\x20'..='\x2F' | '<'..='?' => {}
+                    _ => {
+                        let (params, count) = (*params, (*count).min(MAX_CSI_PARAMS));
+                        self.escape = Escape::None;
+                        if c == 'm' {
+                            self.select_graphic_rendition(&params[..count]);
+                        }
+                    }
+                }
+                return;
+            }
+        }
+
+        match c {
+            '\x1B' => self.escape = Escape::Esc,
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            '\t' => {
+                let next = (self.column / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.column < next.min(self.columns) {
+                    self.put_char(' ');
+                }
+            }
+            '\x08' => self.column = self.column.saturating_sub(1),
+            c if c.is_control() => {}
+            c => self.put_char(c),
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        // "ESC [ m" is a reset
+        if params.is_empty() {
+            self.reset_attributes();
+        }
+
+        for &param in params {
+            match param {
+                0 => self.reset_attributes(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                30..=37 => {
+                    let index = (param - 30) as usize;
+                    self.fg = PALETTE[if self.bold { index + 8 } else { index }];
+                }
+                39 => self.fg = self.default_fg,
+                40..=47 => self.bg = PALETTE[(param - 40) as usize],
+                49 => self.bg = self.default_bg,
+                90..=97 => self.fg = PALETTE[(param - 90) as usize + 8],
+                100..=107 => self.bg = PALETTE[(param - 100) as usize + 8],
+                _ => {}
+            }
+        }
+    }
+
+    fn reset_attributes(&mut self) {
+        self.fg = self.default_fg;
+        self.bg = self.default_bg;
+        self.bold = false;
+    }
+
+    fn put_char(&mut self, c: char) {
+        if self.column >= self.columns {
+            self.new_line();
+        }
+
+        let glyph = self
+            .font
+            .glyph(c)
+            .or_else(|| self.font.glyph('?'))
+            .unwrap_or(&[]);
+        let bytes_per_row = self.font.bytes_per_row();
+        let x0 = self.column * self.font.width;
+        let y0 = self.row * self.font.height;
+
+        for y in 0..self.font.height {
+            let row = glyph.get(y * bytes_per_row..(y + 1) * bytes_per_row);
+            for x in 0..self.font.width {
+                let set = row.is_some_and(|row| row[x / 8] & (0x80 >> (x % 8)) != 0);
+                self.set_pixel(x0 + x, y0 + y, if set { self.fg } else { self.bg });
+            }
+        }
+
+        self.column += 1;
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    // Move every text row up by one and blank the last one
+    fn scroll(&mut self) {
+        let line = self.font.height * self.stride;
+        unsafe {
+            core::ptr::copy(
+                self.framebuffer.add(line),
+                self.framebuffer,
+                (self.rows - 1) * line,
+            );
+        }
+
+        let top = (self.rows - 1) * self.font.height;
+        for y in top..top + self.font.height {
+            for x in 0..self.width {
+                self.set_pixel(x, y, self.bg);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.set_pixel(x, y, self.bg);
+            }
+        }
+        self.column = 0;
+        self.row = 0;
+    }
+
+    fn set_pixel(&self, x: usize, y: usize, color: u32) {
+        let [red, green, blue] = self.channels;
+        let pixel = red.pack(color >> 16 & 0xFF)
+            | green.pack(color >> 8 & 0xFF)
+            | blue.pack(color & 0xFF);
+        unsafe {
+            core::ptr::write_volatile(self.framebuffer.add(y * self.stride + x), pixel);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+// kernel/src/console/tests.rs
+//
+// Packing 0xRRGGBB colours into the pixel formats GOP describes.
+
+use super::*;
+
+fn pack(masks: [u32; 3], color: u32) -> u32 {
+    let [red, green, blue] = masks.map(Channel::new);
+    red.pack(color >> 16 & 0xFF) | green.pack(color >> 8 & 0xFF) | blue.pack(color & 0xFF)
+}
+
+#[test_case]
+fn bgr_and_rgb() {
+    let bgr = [0xFF0000, 0x00FF00, 0x0000FF];
+    let rgb = [0x0000FF, 0x00FF00, 0xFF0000];
+    assert_eq!(pack(bgr, 0x123456), 0x123456);
+    assert_eq!(pack(rgb, 0x123456), 0x563412);
+    assert_eq!(pack(rgb, PANIC_BG), 0xAA0000);
+}
+
+// 10 bits a channel, and 5-6-5 in the low half of the pixel
+#[test_case]
+fn bitmasks() {
+    let wide = [0x3FF0_0000, 0x000F_FC00, 0x0000_03FF];
+    assert_eq!(pack(wide, 0xFF0080), 0x3FC0_0200);
+    let narrow = [0xF800, 0x07E0, 0x001F];
+    assert_eq!(pack(narrow, 0xFFFFFF), 0xFFFF);
+    assert_eq!(pack(narrow, 0x00FF00), 0x07E0);
+}
@@ -0,0 +1,99 @@
+// kernel/src/debug/backtrace.rs
+//
+// Frame-pointer stack walker. The kernel is built with frame pointers, so
+// every frame has a record with the caller's frame pointer and the return
+// address. Each record is checked against the stack bounds before it is read,
+// so a corrupt chain ends the trace instead of faulting.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::symbols::{self, Demangle};
+use crate::mm::{self, PAGE_SIZE};
+use crate::{arch, BootInfo};
+
+const MAX_FRAMES: usize = 64;
+
+// Size of a frame record: saved frame pointer and return address
+const FRAME_RECORD_SIZE: u64 = 16;
+
+// Bounds of the current stack, [bottom, top). Empty until init.
+static STACK_BOTTOM: AtomicU64 = AtomicU64::new(0);
+static STACK_TOP: AtomicU64 = AtomicU64::new(0);
+
+// The kernel still runs on the stack the firmware gave the bootloader, so the
+// bounds are those of the memory map region it lives in
+pub fn init(boot_info: &BootInfo) {
+    let fp = arch::frame_pointer();
+    for desc in mm::memory_map(boot_info) {
+        let end = desc.phys_start + desc.page_count * PAGE_SIZE;
+        if (desc.phys_start..end).contains(&fp) {
+            set_stack_bounds(desc.phys_start, end);
+            return;
+        }
+    }
+}
+
+// Switch the bounds frames are checked against, for code that moves to
+// another stack
+pub fn set_stack_bounds(bottom: u64, top: u64) {
+    STACK_BOTTOM.store(bottom, Ordering::Relaxed);
+    STACK_TOP.store(top, Ordering::Relaxed);
+}
+
+// Call `f` with each return address, innermost first, starting from the frame at `fp`
+pub fn walk(mut fp: u64, mut f: impl FnMut(u64)) {
+    let bottom = STACK_BOTTOM.load(Ordering::Relaxed);
+    let top = STACK_TOP.load(Ordering::Relaxed);
+
+    for _ in 0..MAX_FRAMES {
+        let record = arch::frame_record(fp);
+        if fp == 0 || !record.is_multiple_of(8) || record < bottom || record + FRAME_RECORD_SIZE > top {
+            break;
+        }
+
+        let (caller_fp, return_addr) = unsafe {
+            let record = record as *const u64;
+            (record.read(), record.add(1).read())
+        };
+        if return_addr == 0 {
+            break;
+        }
+        f(return_addr);
+
+        // Callers' frames are further up the stack
+        if caller_fp <= fp {
+            break;
+        }
+        fp = caller_fp;
+    }
+}
+
+// Print one "#n 0xADDR function+0xOFF" line per frame
+pub fn print(out: &mut impl Write, fp: u64) -> fmt::Result {
+    let mut index = 0;
+    let mut result = Ok(());
+
+    walk(fp, |return_addr| {
+        if result.is_err() {
+            return;
+        }
+
+        // The return address may already belong to the next function when
+        // the call was the last instruction, so look up the call itself
+        result = match symbols::lookup(return_addr - 1) {
+            Some(symbol) => writeln!(
+                out,
+                "#{:<2} 0x{:016x} {}+0x{:x}",
+                index,
+                return_addr,
+                Demangle(symbol.name),
+                symbol.offset + 1
+            ),
+            None => writeln!(out, "#{:<2} 0x{:016x} ?", index, return_addr),
+        };
+        index += 1;
+    });
+
+    result
+}
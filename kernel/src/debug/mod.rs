@@ -0,0 +1,7 @@
+// kernel/src/debug/mod.rs
+//
+// Kernel debugging aids
+
+pub mod backtrace;
+pub mod monitor;
+pub mod symbols;
@@ -0,0 +1,204 @@
+// kernel/src/debug/monitor.rs
+//
+// Debugger stub on the serial port, entered on panic with "panic=debug".
+// It polls the UART, so it works with interrupts off and nothing else running.
+// One command per line:
+//
+//   regs             registers at the time of the panic
+//   bt               backtrace into the monitor
+//   mem ADDR [LEN]   hex dump of LEN bytes (default 64) at ADDR
+//   log              replay the kernel log
+//   heap             heap usage per size class
+//   irqs             how many interrupts went unhandled
+//   uptime           time since reset, and timer ticks since boot
+//   date [SECONDS]   show the date, or set it in seconds since the epoch
+//   reboot           reset the machine
+//   setup            reboot into the firmware's setup menu
+//   poweroff         turn the machine off
+//   halt             stop here
+
+use core::fmt::Write;
+use core::time::Duration;
+
+use super::backtrace;
+use crate::mm::heap;
+use crate::time::wall::{self, SystemTime};
+use crate::{arch, efi, interrupts, klog, power, serial, time};
+
+const LINE_MAX: usize = 80;
+const DEFAULT_DUMP_LEN: usize = 64;
+
+// Only the panic handler enters the monitor, and not under `cargo test`
+#[cfg_attr(test, allow(dead_code))]
+pub fn run(registers: &arch::Registers) -> ! {
+    let mut out = serial::Writer;
+    let _ = writeln!(out, "Entering debug monitor, type 'help' for commands");
+
+    let mut line = [0u8; LINE_MAX];
+    loop {
+        let _ = write!(out, "debug> ");
+        let len = read_line(&mut line);
+        let line = core::str::from_utf8(&line[..len]).unwrap_or("");
+        let mut words = line.split_ascii_whitespace();
+
+        match words.next() {
+            None => {}
+            Some("help") => {
+                let _ = writeln!(out, "regs | bt | mem ADDR [LEN] | log | heap | irqs | uptime | date [SECONDS]");
+                let _ = writeln!(out, "reboot | setup | poweroff | halt");
+            }
+            Some("regs") => {
+                let _ = writeln!(out, "{}", registers);
+            }
+            Some("bt") => {
+                let _ = backtrace::print(&mut out, arch::frame_pointer());
+            }
+            Some("mem") => {
+                let addr = words.next().and_then(parse_number);
+                let len = words.next().map_or(Some(DEFAULT_DUMP_LEN as u64), parse_number);
+                match (addr, len) {
+                    (Some(addr), Some(len)) => dump_memory(&mut out, addr as usize, len as usize),
+                    _ => {
+                        let _ = writeln!(out, "usage: mem ADDR [LEN]");
+                    }
+                }
+            }
+            Some("log") => klog::dump(serial::write_bytes),
+            Some("heap") => print_heap_stats(&mut out),
+            Some("irqs") => {
+                let _ = writeln!(out, "{} unhandled", interrupts::unhandled_count());
+            }
+            Some("uptime") => {
+                let uptime = time::Instant::now().as_nanos() / 1_000_000;
+                let _ = writeln!(
+                    out,
+                    "{}.{:03} s, {} ticks",
+                    uptime / 1000,
+                    uptime % 1000,
+                    time::ticks()
+                );
+            }
+            Some("date") => match words.next().map(parse_number) {
+                None => {
+                    let now = SystemTime::now();
+                    let synced = if wall::is_synced() { "" } else { ", not synced" };
+                    let _ = writeln!(out, "{} ({} s{})", now, now.since_epoch().as_secs(), synced);
+                }
+                Some(Some(seconds)) => {
+                    let time = SystemTime::from_unix(Duration::from_secs(seconds));
+                    if let Err(err) = wall::set(time) {
+                        let _ = writeln!(out, "hardware clock not set: {:?}", err);
+                    }
+                }
+                Some(None) => {
+                    let _ = writeln!(out, "usage: date [SECONDS]");
+                }
+            },
+            Some("reboot") => power::reboot(),
+            Some("setup") => match efi::request_firmware_setup() {
+                Ok(()) => power::reboot(),
+                Err(status) => {
+                    let _ = writeln!(out, "firmware setup can't be requested: {:?}", status);
+                }
+            },
+            Some("poweroff") => power::power_off(),
+            Some("halt") => arch::halt(),
+            Some(other) => {
+                let _ = writeln!(out, "unknown command '{}'", other);
+            }
+        }
+    }
+}
+
+// Read one line with echo and backspace, returning its length
+fn read_line(buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        let byte = match serial::read_byte() {
+            Some(byte) => byte,
+            None => {
+                core::hint::spin_loop();
+                continue;
+            }
+        };
+
+        match byte {
+            b'\r' | b'\n' => {
+                serial::write_str("\n");
+                return len;
+            }
+            0x08 | 0x7F if len > 0 => {
+                len -= 1;
+                serial::write_str("\x08 \x08");
+            }
+            0x20..=0x7E if len < buf.len() => {
+                buf[len] = byte;
+                len += 1;
+                serial::write_bytes(&[byte]);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Hex with a 0x prefix, decimal otherwise
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn print_heap_stats(out: &mut serial::Writer) {
+    let stats = match heap::try_stats() {
+        Some(stats) => stats,
+        None => {
+            let _ = writeln!(out, "heap is locked");
+            return;
+        }
+    };
+
+    for class in &stats.classes {
+        let _ = writeln!(
+            out,
+            "{:>5} bytes: {:>9} in use of {:>9}",
+            class.size, class.in_use, class.capacity
+        );
+    }
+    let _ = writeln!(out, "large: {:>9} in use", stats.large_in_use);
+}
+
+// Unreadable bytes (where a probe faults) show as "??"
+fn dump_memory(out: &mut serial::Writer, addr: usize, len: usize) {
+    for line_start in (addr..addr.saturating_add(len)).step_by(16) {
+        let line_len = (addr + len - line_start).min(16);
+        let mut bytes = [None; 16];
+        for (i, byte) in bytes[..line_len].iter_mut().enumerate() {
+            *byte = arch::probe_read((line_start + i) as u64);
+        }
+
+        let _ = write!(out, "{:016x}:", line_start);
+        for byte in &bytes[..line_len] {
+            match byte {
+                Some(byte) => {
+                    let _ = write!(out, " {:02x}", byte);
+                }
+                None => {
+                    let _ = write!(out, " ??");
+                }
+            }
+        }
+        for _ in line_len..16 {
+            let _ = write!(out, "   ");
+        }
+        let _ = write!(out, "  ");
+        for byte in &bytes[..line_len] {
+            let c = match byte {
+                Some(byte) if byte.is_ascii_graphic() => *byte as char,
+                _ => '.',
+            };
+            let _ = write!(out, "{}", c);
+        }
+        let _ = writeln!(out);
+    }
+}
@@ -0,0 +1,166 @@
+// kernel/src/debug/symbols.rs
+//
+// Kernel symbol lookup for backtraces, from the ELF symbol table the
+// bootloader found in the kernel file. Rust names use the legacy mangling
+// (_ZN...E), Demangle turns them back into paths for display.
+
+use core::fmt;
+
+use crate::sync::SpinLock;
+use crate::BootInfo;
+
+// Layout of Elf64_Sym
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElfSymbol {
+    st_name: u32,
+    st_info: u8,
+    st_other: u8,
+    st_shndx: u16,
+    st_value: u64,
+    st_size: u64,
+}
+
+const STT_FUNC: u8 = 2;
+
+#[derive(Clone, Copy)]
+struct SymbolTable {
+    symbols: &'static [ElfSymbol],
+    strings: &'static [u8],
+}
+
+static SYMBOLS: SpinLock<Option<SymbolTable>> = SpinLock::new(None);
+
+pub struct Symbol {
+    pub name: &'static str,
+    // Distance of the looked-up address from the symbol's start
+    pub offset: u64,
+}
+
+pub fn init(boot_info: &BootInfo) {
+    if boot_info.symtab_addr == 0 || boot_info.strtab_addr == 0 {
+        return;
+    }
+
+    let count = boot_info.symtab_size as usize / core::mem::size_of::<ElfSymbol>();
+    *SYMBOLS.lock() = Some(SymbolTable {
+        symbols: unsafe {
+            core::slice::from_raw_parts(boot_info.symtab_addr as *const ElfSymbol, count)
+        },
+        strings: unsafe {
+            core::slice::from_raw_parts(
+                boot_info.strtab_addr as *const u8,
+                boot_info.strtab_size as usize,
+            )
+        },
+    });
+}
+
+// The function containing `addr`. Symbols without a size match anything up
+// to the next symbol.
+pub fn lookup(addr: u64) -> Option<Symbol> {
+    let table = (*SYMBOLS.lock())?;
+
+    let symbol = table
+        .symbols
+        .iter()
+        .filter(|sym| sym.st_info & 0xF == STT_FUNC && sym.st_value <= addr)
+        .filter(|sym| sym.st_size == 0 || addr < sym.st_value + sym.st_size)
+        .max_by_key(|sym| sym.st_value)?;
+
+    let name = table.strings.get(symbol.st_name as usize..)?;
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    Some(Symbol {
+        name: core::str::from_utf8(&name[..len]).unwrap_or("?"),
+        offset: addr - symbol.st_value,
+    })
+}
+
+// Displays a legacy-mangled Rust name as a path without its hash, and any
+// other name as it is
+pub struct Demangle<'a>(pub &'a str);
+
+impl fmt::Display for Demangle<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mangled = match self.0.strip_prefix("_ZN") {
+            Some(rest) if rest.ends_with('E') => &rest[..rest.len() - 1],
+            _ => return f.write_str(self.0),
+        };
+
+        // Split into length-prefixed identifiers first, so a malformed name
+        // is printed unchanged rather than half demangled
+        let mut parts = [""; 32];
+        let mut count = 0;
+        let mut rest = mangled;
+        while !rest.is_empty() {
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            let len: usize = match rest[..digits].parse() {
+                Ok(len) if count < parts.len() && digits + len <= rest.len() => len,
+                _ => return f.write_str(self.0),
+            };
+            parts[count] = &rest[digits..digits + len];
+            count += 1;
+            rest = &rest[digits + len..];
+        }
+
+        // The last identifier is the hash: 'h' and 16 hex digits
+        if count > 1 {
+            let last = parts[count - 1];
+            if last.len() == 17
+                && last.starts_with('h')
+                && last[1..].bytes().all(|b| b.is_ascii_hexdigit())
+            {
+                count -= 1;
+            }
+        }
+
+        for (i, part) in parts[..count].iter().enumerate() {
+            if i > 0 {
+                f.write_str("::")?;
+            }
+            write_identifier(f, part)?;
+        }
+        Ok(())
+    }
+}
+
+// Undo the $..$ escapes and the ".." for "::" in one identifier
+fn write_identifier(f: &mut fmt::Formatter, ident: &str) -> fmt::Result {
+    // A leading '_' only keeps an escape from starting the identifier
+    let mut rest = if ident.starts_with("_$") { &ident[1..] } else { ident };
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(escaped) = rest.strip_prefix('$') {
+            if let Some(end) = escaped.find('$') {
+                let decoded = match &escaped[..end] {
+                    "SP" => Some('@'),
+                    "BP" => Some('*'),
+                    "RF" => Some('&'),
+                    "LT" => Some('<'),
+                    "GT" => Some('>'),
+                    "LP" => Some('('),
+                    "RP" => Some(')'),
+                    "C" => Some(','),
+                    code => code
+                        .strip_prefix('u')
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .and_then(char::from_u32),
+                };
+                if let Some(decoded) = decoded {
+                    write!(f, "{}", decoded)?;
+                    rest = &escaped[end + 1..];
+                    continue;
+                }
+            }
+        }
+
+        if let Some(after) = rest.strip_prefix("..") {
+            f.write_str("::")?;
+            rest = after;
+        } else {
+            write!(f, "{}", c)?;
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    Ok(())
+}
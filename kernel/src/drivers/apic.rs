@@ -0,0 +1,176 @@
+// kernel/src/drivers/apic.rs
+//
+// Local APIC: per-CPU interrupt acceptance, end of interrupt and the APIC
+// timer. Runs in x2APIC mode, where every register is an MSR, when
+// the CPU supports it, otherwise in xAPIC mode with the registers memory
+// mapped at the address in IA32_APIC_BASE.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::arch::x86_64::msr;
+use crate::mm::vmm::{self, MapError};
+use crate::time::Instant;
+
+// IA32_APIC_BASE
+const BASE_X2APIC: u64 = 1 << 10;
+const BASE_ENABLE: u64 = 1 << 11;
+const BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+// CPUID 1 ECX
+const CPUID_X2APIC: u32 = 1 << 21;
+
+// Register offsets in the xAPIC page; x2APIC MSRs are 0x800 + offset / 16
+const ID: u32 = 0x20;
+const TASK_PRIORITY: u32 = 0x80;
+const EOI: u32 = 0xB0;
+const SPURIOUS: u32 = 0xF0;
+const ERROR_STATUS: u32 = 0x280;
+const LVT_TIMER: u32 = 0x320;
+const LVT_LINT0: u32 = 0x350;
+const LVT_LINT1: u32 = 0x360;
+const LVT_ERROR: u32 = 0x370;
+const TIMER_INITIAL: u32 = 0x380;
+const TIMER_CURRENT: u32 = 0x390;
+const TIMER_DIVIDE: u32 = 0x3E0;
+
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+const SPURIOUS_ENABLE: u32 = 1 << 8;
+
+// Local vector table entries
+const LVT_DELIVERY_NMI: u32 = 0b100 << 8;
+const LVT_ACTIVE_LOW: u32 = 1 << 13;
+const LVT_LEVEL: u32 = 1 << 15;
+const LVT_MASKED: u32 = 1 << 16;
+
+// Timer input is the bus clock divided by 16
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+// How long to let the timer run when measuring it
+const CALIBRATION: Duration = Duration::from_millis(10);
+
+static X2APIC: AtomicBool = AtomicBool::new(false);
+static MMIO_BASE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    X2Apic,
+    XApic,
+}
+
+// Enable the calling CPU's local APIC, in x2APIC mode if possible. Every
+// local interrupt source starts out masked; `spurious_vector` is what the
+// APIC delivers when an interrupt goes away before it's accepted.
+pub fn init(spurious_vector: u8) -> Result<Mode, MapError> {
+    #[allow(unused_unsafe)]
+    let features = unsafe { core::arch::x86_64::__cpuid(1) };
+    let base = unsafe { msr::read(msr::IA32_APIC_BASE) };
+
+    let mode = if features.ecx & CPUID_X2APIC != 0 {
+        // x2APIC can only be entered from enabled xAPIC mode
+        unsafe {
+            msr::write(msr::IA32_APIC_BASE, base | BASE_ENABLE);
+            msr::write(msr::IA32_APIC_BASE, base | BASE_ENABLE | BASE_X2APIC);
+        }
+        X2APIC.store(true, Ordering::Relaxed);
+        Mode::X2Apic
+    } else {
+        let address = vmm::map_mmio(base & BASE_ADDRESS_MASK, 0x1000)?;
+        MMIO_BASE.store(address, Ordering::Relaxed);
+        unsafe {
+            msr::write(msr::IA32_APIC_BASE, base | BASE_ENABLE);
+        }
+        Mode::XApic
+    };
+
+    write(TASK_PRIORITY, 0);
+    for lvt in [LVT_TIMER, LVT_LINT0, LVT_LINT1, LVT_ERROR] {
+        write(lvt, LVT_MASKED);
+    }
+    // Writing the error status latches the errors so far, the second
+    // write clears them
+    write(ERROR_STATUS, 0);
+    write(ERROR_STATUS, 0);
+    write(SPURIOUS, SPURIOUS_ENABLE | spurious_vector as u32);
+    // Drop anything the firmware left in service
+    eoi();
+
+    Ok(mode)
+}
+
+pub fn id() -> u32 {
+    if X2APIC.load(Ordering::Relaxed) {
+        read(ID)
+    } else {
+        read(ID) >> 24
+    }
+}
+
+pub fn eoi() {
+    write(EOI, 0);
+}
+
+// Wire LINT0 or LINT1 up as the NMI input, as the MADT describes
+pub fn set_lint_nmi(lint: u8, active_low: bool, level_triggered: bool) {
+    let mut entry = LVT_DELIVERY_NMI;
+    if active_low {
+        entry |= LVT_ACTIVE_LOW;
+    }
+    if level_triggered {
+        entry |= LVT_LEVEL;
+    }
+    match lint {
+        0 => write(LVT_LINT0, entry),
+        1 => write(LVT_LINT1, entry),
+        _ => {}
+    }
+}
+
+// Raise `vector` once after `count` timer ticks
+pub fn start_timer_oneshot(vector: u8, count: u32) {
+    write(TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    write(LVT_TIMER, vector as u32);
+    write(TIMER_INITIAL, count);
+}
+
+pub fn stop_timer() {
+    write(LVT_TIMER, LVT_MASKED);
+    write(TIMER_INITIAL, 0);
+}
+
+// Timer ticks per second, measured against the monotonic clock, so only
+// after its frequency is known. Leaves the timer stopped.
+pub fn measure_timer_frequency() -> u64 {
+    write(TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    write(LVT_TIMER, LVT_MASKED);
+
+    let start = Instant::now();
+    write(TIMER_INITIAL, u32::MAX);
+    while start.elapsed() < CALIBRATION {
+        core::hint::spin_loop();
+    }
+    let ticks = u32::MAX - read(TIMER_CURRENT);
+    let elapsed = start.elapsed();
+    stop_timer();
+
+    (ticks as u128 * 1_000_000_000 / elapsed.as_nanos().max(1)) as u64
+}
+
+fn read(register: u32) -> u32 {
+    if X2APIC.load(Ordering::Relaxed) {
+        unsafe { msr::read(X2APIC_MSR_BASE + (register >> 4)) as u32 }
+    } else {
+        let address = MMIO_BASE.load(Ordering::Relaxed) + register as u64;
+        unsafe { (address as *const u32).read_volatile() }
+    }
+}
+
+fn write(register: u32, value: u32) {
+    if X2APIC.load(Ordering::Relaxed) {
+        unsafe { msr::write(X2APIC_MSR_BASE + (register >> 4), value as u64) }
+    } else {
+        let address = MMIO_BASE.load(Ordering::Relaxed) + register as u64;
+        unsafe { (address as *mut u32).write_volatile(value) }
+    }
+}
@@ -0,0 +1,167 @@
+// kernel/src/drivers/cmos_rtc.rs
+//
+// The PC's CMOS real-time clock (MC146818 compatible). Registers hold a
+// two-digit year, in BCD or binary and in 12 or 24 hour format as status
+// register B says. The century lives in a separate register only when the
+// FADT names one.
+//
+// The clock is read twice until both reads agree, so an update that
+// happens part way through can't produce a torn date.
+
+use core::time::Duration;
+
+use crate::arch::x86_64::port;
+use crate::time::wall::DateTime;
+use crate::time::Deadline;
+
+const INDEX_PORT: u16 = 0x70;
+const DATA_PORT: u16 = 0x71;
+
+// Keeps NMIs disabled while a register is selected
+const NMI_DISABLE: u8 = 0x80;
+
+const SECONDS: u8 = 0x00;
+const MINUTES: u8 = 0x02;
+const HOURS: u8 = 0x04;
+const DAY: u8 = 0x07;
+const MONTH: u8 = 0x08;
+const YEAR: u8 = 0x09;
+const STATUS_A: u8 = 0x0A;
+const STATUS_B: u8 = 0x0B;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+const STATUS_B_SET: u8 = 1 << 7;
+const HOURS_PM: u8 = 1 << 7;
+
+// An update takes under 2 ms. Without the chip the flag reads as always
+// set, and the date as invalid.
+const UPDATE_TIMEOUT: Duration = Duration::from_millis(10);
+
+// Years on a clock with no century register
+const DEFAULT_CENTURY: u16 = 2000;
+
+// Raw register values, before decoding
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Registers {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+// `century` is the FADT's century register index, if it has one. None if
+// the registers don't hold a valid date, as on machines without the chip.
+pub fn read_time(century: Option<u8>) -> Option<DateTime> {
+    let mut registers = read_registers(century);
+    loop {
+        let again = read_registers(century);
+        if again == registers {
+            break;
+        }
+        registers = again;
+    }
+
+    let status = read(STATUS_B);
+    let decode = |value: u8| {
+        if status & STATUS_B_BINARY != 0 {
+            value
+        } else {
+            (value >> 4) * 10 + (value & 0x0F)
+        }
+    };
+
+    let mut hour = decode(registers.hour & !HOURS_PM);
+    if status & STATUS_B_24_HOUR == 0 {
+        // 12 AM is midnight, 12 PM noon
+        hour %= 12;
+        if registers.hour & HOURS_PM != 0 {
+            hour += 12;
+        }
+    }
+    let year = match century {
+        Some(_) => decode(registers.century) as u16 * 100,
+        None => DEFAULT_CENTURY,
+    } + decode(registers.year) as u16;
+
+    let time = DateTime {
+        year,
+        month: decode(registers.month),
+        day: decode(registers.day),
+        hour,
+        minute: decode(registers.minute),
+        second: decode(registers.second),
+        nanosecond: 0,
+    };
+    time.is_valid().then_some(time)
+}
+
+// Set the clock, in whatever format it already uses. The sub-second part
+// is dropped.
+pub fn write_time(time: &DateTime, century: Option<u8>) {
+    let status = read(STATUS_B);
+    let encode = |value: u8| {
+        if status & STATUS_B_BINARY != 0 {
+            value
+        } else {
+            ((value / 10) << 4) | (value % 10)
+        }
+    };
+
+    let hour = if status & STATUS_B_24_HOUR != 0 {
+        encode(time.hour)
+    } else {
+        let pm = if time.hour >= 12 { HOURS_PM } else { 0 };
+        encode(match time.hour % 12 {
+            0 => 12,
+            hour => hour,
+        }) | pm
+    };
+
+    // SET stops updates while the registers are written
+    write(STATUS_B, status | STATUS_B_SET);
+    write(SECONDS, encode(time.second));
+    write(MINUTES, encode(time.minute));
+    write(HOURS, hour);
+    write(DAY, encode(time.day));
+    write(MONTH, encode(time.month));
+    write(YEAR, encode((time.year % 100) as u8));
+    if let Some(register) = century {
+        write(register, encode((time.year / 100) as u8));
+    }
+    write(STATUS_B, status & !STATUS_B_SET);
+}
+
+fn read_registers(century: Option<u8>) -> Registers {
+    let deadline = Deadline::after(UPDATE_TIMEOUT);
+    while read(STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0 && !deadline.has_passed() {
+        core::hint::spin_loop();
+    }
+    Registers {
+        second: read(SECONDS),
+        minute: read(MINUTES),
+        hour: read(HOURS),
+        day: read(DAY),
+        month: read(MONTH),
+        year: read(YEAR),
+        century: century.map_or(0, read),
+    }
+}
+
+fn read(register: u8) -> u8 {
+    unsafe {
+        port::outb(INDEX_PORT, NMI_DISABLE | register);
+        port::inb(DATA_PORT)
+    }
+}
+
+fn write(register: u8, value: u8) {
+    unsafe {
+        port::outb(INDEX_PORT, NMI_DISABLE | register);
+        port::outb(DATA_PORT, value);
+    }
+}
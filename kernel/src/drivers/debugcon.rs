@@ -0,0 +1,12 @@
+// kernel/src/drivers/debugcon.rs
+//
+// QEMU/Bochs debug console: every byte written to port 0xE9 shows up on the
+// host (`-debugcon stdio`). Always present in QEMU, ignored on real hardware.
+
+use crate::arch::x86_64::port;
+
+const DEBUGCON_PORT: u16 = 0xE9;
+
+pub fn write_byte(byte: u8) {
+    unsafe { port::outb(DEBUGCON_PORT, byte) }
+}
@@ -0,0 +1,429 @@
+// kernel/src/drivers/gic.rs
+//
+// ARM Generic Interrupt Controller, versions 2 and 3. Both have a shared
+// distributor for SPIs (INTIDs 32 and up); the per-CPU SGIs (0-15) and PPIs
+// (16-31) are banked in the distributor on GICv2 and live in each CPU's
+// redistributor on GICv3. The CPU interface is memory mapped on GICv2 and
+// made of system registers on GICv3.
+//
+// Only the calling CPU's interface and redistributor are set up, which is
+// all there is until other CPUs are started.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+
+use crate::arch;
+use crate::interrupts::Trigger;
+
+// Distributor
+const GICD_CTLR: u64 = 0x000;
+const GICD_TYPER: u64 = 0x004;
+const GICD_IGROUPR: u64 = 0x080;
+const GICD_ISENABLER: u64 = 0x100;
+const GICD_ICENABLER: u64 = 0x180;
+const GICD_ICPENDR: u64 = 0x280;
+const GICD_IPRIORITYR: u64 = 0x400;
+const GICD_ITARGETSR: u64 = 0x800;
+const GICD_ICFGR: u64 = 0xC00;
+const GICD_SGIR: u64 = 0xF00;
+const GICD_IROUTER: u64 = 0x6000;
+const GICD_PIDR2: u64 = 0xFFE8;
+
+const GICD_CTLR_ENABLE_GRP0: u32 = 1 << 0;
+const GICD_CTLR_ENABLE_GRP1: u32 = 1 << 1;
+const GICD_CTLR_ARE: u32 = 1 << 4;
+const GICD_CTLR_RWP: u32 = 1 << 31;
+
+// GICv2 CPU interface
+const GICC_CTLR: u64 = 0x00;
+const GICC_PMR: u64 = 0x04;
+const GICC_BPR: u64 = 0x08;
+const GICC_IAR: u64 = 0x0C;
+const GICC_EOIR: u64 = 0x10;
+
+const GICC_CTLR_ENABLE: u32 = 0b11;
+
+// GICv3 redistributor: RD frame, then the SGI frame with the banked
+// copies of the distributor registers
+const GICR_CTLR: u64 = 0x0000;
+const GICR_TYPER: u64 = 0x0008;
+const GICR_WAKER: u64 = 0x0014;
+const GICR_SGI_FRAME: u64 = 0x10000;
+
+const GICR_CTLR_RWP: u32 = 1 << 3;
+const GICR_TYPER_VLPIS: u64 = 1 << 1;
+const GICR_TYPER_LAST: u64 = 1 << 4;
+const GICR_WAKER_PROCESSOR_SLEEP: u32 = 1 << 1;
+const GICR_WAKER_CHILDREN_ASLEEP: u32 = 1 << 2;
+
+// RD and SGI frames, plus two more with virtual LPI support (GICv4)
+const GICR_STRIDE: u64 = 0x20000;
+const GICR_STRIDE_VLPIS: u64 = 0x40000;
+
+// ICC_SGI1R_EL1
+const SGI1R_INTID_SHIFT: u64 = 24;
+const SGI1R_ALL_BUT_SELF: u64 = 1 << 40;
+
+// INTIDs 1020-1023 are special, meaning nothing to acknowledge
+const SPURIOUS_INTID: u32 = 1020;
+const SGI_COUNT: u32 = 16;
+const PRIVATE_COUNT: u32 = 32;
+
+// Lowest is highest; halfway leaves room either side
+pub const DEFAULT_PRIORITY: u8 = 0xA0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    V2,
+    V3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SgiTarget {
+    // By MPIDR affinity, as arch::cpu_id() reports it
+    Cpu(u64),
+    Current,
+    AllButCurrent,
+}
+
+// 0 until init_v2/init_v3, then 2 or 3
+static VERSION: AtomicU8 = AtomicU8::new(0);
+static DISTRIBUTOR: AtomicU64 = AtomicU64::new(0);
+static CPU_INTERFACE: AtomicU64 = AtomicU64::new(0);
+// This CPU's redistributor RD frame
+static REDISTRIBUTOR: AtomicU64 = AtomicU64::new(0);
+static LINES: AtomicU32 = AtomicU32::new(0);
+// GICv2 wants the acknowledged value back on EOI, including the source
+// CPU of an SGI
+static LAST_IAR: AtomicU32 = AtomicU32::new(0);
+
+// The architecture version from the distributor's ID registers
+pub unsafe fn detect_version(distributor: u64) -> Option<Version> {
+    match (read32(distributor + GICD_PIDR2) >> 4) & 0xF {
+        1 | 2 => Some(Version::V2),
+        3 | 4 => Some(Version::V3),
+        _ => None,
+    }
+}
+
+// GICv2 with the distributor and CPU interface at `distributor` and
+// `cpu_interface`, both already mapped
+pub unsafe fn init_v2(distributor: u64, cpu_interface: u64) {
+    DISTRIBUTOR.store(distributor, Ordering::Relaxed);
+    CPU_INTERFACE.store(cpu_interface, Ordering::Relaxed);
+    VERSION.store(2, Ordering::Relaxed);
+
+    write32(distributor + GICD_CTLR, 0);
+    init_distributor(distributor);
+    init_private(distributor);
+    write32(distributor + GICD_CTLR, GICD_CTLR_ENABLE_GRP0 | GICD_CTLR_ENABLE_GRP1);
+
+    write32(cpu_interface + GICC_PMR, 0xFF);
+    write32(cpu_interface + GICC_BPR, 0);
+    write32(cpu_interface + GICC_CTLR, GICC_CTLR_ENABLE);
+}
+
+// GICv3 with the distributor at `distributor` and redistributors in the
+// mapped (base, length) `regions`. Returns false if none of them belongs
+// to the calling CPU.
+pub unsafe fn init_v3(distributor: u64, regions: &[(u64, u64)]) -> bool {
+    let Some(redistributor) = find_redistributor(regions, arch::cpu_id()) else {
+        return false;
+    };
+    DISTRIBUTOR.store(distributor, Ordering::Relaxed);
+    REDISTRIBUTOR.store(redistributor, Ordering::Relaxed);
+    VERSION.store(3, Ordering::Relaxed);
+
+    // Affinity routing has to be on before IROUTER means anything
+    write32(distributor + GICD_CTLR, 0);
+    wait_distributor(distributor);
+    write32(distributor + GICD_CTLR, GICD_CTLR_ARE);
+    wait_distributor(distributor);
+    init_distributor(distributor);
+    write32(
+        distributor + GICD_CTLR,
+        GICD_CTLR_ARE | GICD_CTLR_ENABLE_GRP0 | GICD_CTLR_ENABLE_GRP1,
+    );
+    wait_distributor(distributor);
+
+    // Wake the redistributor, then set up the private interrupts in it
+    let waker = read32(redistributor + GICR_WAKER);
+    write32(redistributor + GICR_WAKER, waker & !GICR_WAKER_PROCESSOR_SLEEP);
+    while read32(redistributor + GICR_WAKER) & GICR_WAKER_CHILDREN_ASLEEP != 0 {
+        core::hint::spin_loop();
+    }
+    init_private(redistributor + GICR_SGI_FRAME);
+    while read32(redistributor + GICR_CTLR) & GICR_CTLR_RWP != 0 {
+        core::hint::spin_loop();
+    }
+
+    // System register interface, every priority let through, group 1 on
+    asm!(
+        "mrs {tmp}, S3_0_C12_C12_5",
+        "orr {tmp}, {tmp}, #1",
+        "msr S3_0_C12_C12_5, {tmp}",
+        "isb",
+        "mov {tmp}, #0xFF",
+        "msr S3_0_C4_C6_0, {tmp}",
+        "msr S3_0_C12_C12_3, xzr",
+        "msr S3_0_C12_C12_4, xzr",
+        "mov {tmp}, #1",
+        "msr S3_0_C12_C12_7, {tmp}",
+        "isb",
+        tmp = out(reg) _,
+        options(nostack)
+    );
+    true
+}
+
+pub fn version() -> Option<Version> {
+    match VERSION.load(Ordering::Relaxed) {
+        2 => Some(Version::V2),
+        3 => Some(Version::V3),
+        _ => None,
+    }
+}
+
+// Number of INTIDs the distributor implements, SGIs and PPIs included
+pub fn line_count() -> u32 {
+    LINES.load(Ordering::Relaxed)
+}
+
+// Claim the highest priority pending interrupt
+pub fn acknowledge() -> Option<u32> {
+    let intid = match version()? {
+        Version::V2 => {
+            let iar = unsafe { read32(CPU_INTERFACE.load(Ordering::Relaxed) + GICC_IAR) };
+            LAST_IAR.store(iar, Ordering::Relaxed);
+            iar & 0x3FF
+        }
+        Version::V3 => {
+            let iar: u64;
+            unsafe {
+                asm!("mrs {}, S3_0_C12_C12_0", out(reg) iar, options(nomem, nostack));
+            }
+            iar as u32 & 0xFF_FFFF
+        }
+    };
+    (!(SPURIOUS_INTID..SPURIOUS_INTID + 4).contains(&intid)).then_some(intid)
+}
+
+pub fn end_of_interrupt(intid: u32) {
+    match version() {
+        Some(Version::V2) => {
+            let iar = LAST_IAR.load(Ordering::Relaxed);
+            let value = if iar & 0x3FF == intid { iar } else { intid };
+            unsafe { write32(CPU_INTERFACE.load(Ordering::Relaxed) + GICC_EOIR, value) }
+        }
+        Some(Version::V3) => unsafe {
+            asm!("msr S3_0_C12_C12_1, {}", in(reg) intid as u64, options(nomem, nostack));
+        },
+        None => {}
+    }
+}
+
+pub fn enable(intid: u32) {
+    let (base, index) = register_bank(intid);
+    unsafe { write32(base + GICD_ISENABLER + (index / 32 * 4) as u64, 1 << (index % 32)) }
+}
+
+pub fn disable(intid: u32) {
+    let (base, index) = register_bank(intid);
+    unsafe { write32(base + GICD_ICENABLER + (index / 32 * 4) as u64, 1 << (index % 32)) }
+}
+
+// Lower values preempt higher ones
+pub fn set_priority(intid: u32, priority: u8) {
+    let (base, index) = register_bank(intid);
+    unsafe { ((base + GICD_IPRIORITYR + index as u64) as *mut u8).write_volatile(priority) }
+}
+
+// SGIs are always edge triggered, and PPIs may be fixed either way
+pub fn set_trigger(intid: u32, trigger: Trigger) {
+    if intid < SGI_COUNT {
+        return;
+    }
+    let (base, index) = register_bank(intid);
+    let register = base + GICD_ICFGR + (index / 16 * 4) as u64;
+    let bit = 1 << ((index % 16) * 2 + 1);
+    unsafe {
+        let value = read32(register);
+        write32(
+            register,
+            match trigger {
+                Trigger::Edge => value | bit,
+                Trigger::Level => value & !bit,
+            },
+        );
+    }
+}
+
+// Deliver SPI `intid` to the CPU with MPIDR affinity `cpu`
+pub fn route(intid: u32, cpu: u64) {
+    if intid < PRIVATE_COUNT {
+        return;
+    }
+    let distributor = DISTRIBUTOR.load(Ordering::Relaxed);
+    match version() {
+        Some(Version::V2) => unsafe {
+            let target = (distributor + GICD_ITARGETSR + intid as u64) as *mut u8;
+            target.write_volatile(v2_cpu_mask(cpu));
+        },
+        Some(Version::V3) => unsafe {
+            // Aff3 sits at bits 32-39 as in MPIDR, Interrupt_Routing_Mode 0
+            let router = (distributor + GICD_IROUTER + intid as u64 * 8) as *mut u64;
+            router.write_volatile(cpu & 0xFF_00FF_FFFF);
+        },
+        None => {}
+    }
+}
+
+// Raise software-generated interrupt `sgi` (0-15) on `target`
+pub fn send_sgi(target: SgiTarget, sgi: u8) {
+    let sgi = sgi as u32 & 0xF;
+    match version() {
+        Some(Version::V2) => {
+            let (filter, targets) = match target {
+                SgiTarget::Cpu(cpu) => (0, v2_cpu_mask(cpu) as u32),
+                SgiTarget::AllButCurrent => (1, 0),
+                SgiTarget::Current => (2, 0),
+            };
+            let sgir = DISTRIBUTOR.load(Ordering::Relaxed) + GICD_SGIR;
+            unsafe { write32(sgir, filter << 24 | targets << 16 | sgi) }
+        }
+        Some(Version::V3) => {
+            let value = match target {
+                SgiTarget::Cpu(cpu) => sgi1r_target(cpu),
+                SgiTarget::Current => sgi1r_target(arch::cpu_id()),
+                SgiTarget::AllButCurrent => SGI1R_ALL_BUT_SELF,
+            } | (sgi as u64) << SGI1R_INTID_SHIFT;
+            unsafe {
+                asm!("msr S3_0_C12_C11_5, {}", "isb", in(reg) value, options(nomem, nostack));
+            }
+        }
+        None => {}
+    }
+}
+
+// Mask, clear and default every SPI, delivered to the calling CPU
+unsafe fn init_distributor(distributor: u64) {
+    let lines = ((read32(distributor + GICD_TYPER) & 0x1F) + 1) * 32;
+    LINES.store(lines.min(SPURIOUS_INTID), Ordering::Relaxed);
+    let v3 = VERSION.load(Ordering::Relaxed) == 3;
+
+    for intid in (PRIVATE_COUNT..lines).step_by(32) {
+        let offset = (intid / 32 * 4) as u64;
+        write32(distributor + GICD_ICENABLER + offset, u32::MAX);
+        write32(distributor + GICD_ICPENDR + offset, u32::MAX);
+        if v3 {
+            write32(distributor + GICD_IGROUPR + offset, u32::MAX);
+        }
+    }
+    for intid in PRIVATE_COUNT..lines.min(SPURIOUS_INTID) {
+        let priority = (distributor + GICD_IPRIORITYR + intid as u64) as *mut u8;
+        priority.write_volatile(DEFAULT_PRIORITY);
+    }
+
+    // Everything to this CPU until something routes it elsewhere
+    let cpu = arch::cpu_id();
+    for intid in PRIVATE_COUNT..lines.min(SPURIOUS_INTID) {
+        if v3 {
+            let router = (distributor + GICD_IROUTER + intid as u64 * 8) as *mut u64;
+            router.write_volatile(cpu & 0xFF_00FF_FFFF);
+        } else {
+            let target = (distributor + GICD_ITARGETSR + intid as u64) as *mut u8;
+            target.write_volatile(v2_cpu_mask(cpu));
+        }
+    }
+}
+
+// SGIs enabled, PPIs masked, all at the default priority. `base` is the
+// distributor on GICv2, the SGI frame of the redistributor on GICv3,
+// where the registers sit at the same offsets.
+unsafe fn init_private(base: u64) {
+    if VERSION.load(Ordering::Relaxed) == 3 {
+        write32(base + GICD_IGROUPR, u32::MAX);
+    }
+    write32(base + GICD_ICENABLER, u32::MAX << SGI_COUNT);
+    write32(base + GICD_ISENABLER, (1 << SGI_COUNT) - 1);
+    for intid in 0..PRIVATE_COUNT {
+        ((base + GICD_IPRIORITYR + intid as u64) as *mut u8).write_volatile(DEFAULT_PRIORITY);
+    }
+}
+
+// Where the enable/priority/config bits of `intid` live, and its index
+// there
+fn register_bank(intid: u32) -> (u64, u32) {
+    let redistributor = REDISTRIBUTOR.load(Ordering::Relaxed);
+    if intid < PRIVATE_COUNT && VERSION.load(Ordering::Relaxed) == 3 {
+        (redistributor + GICR_SGI_FRAME, intid)
+    } else {
+        (DISTRIBUTOR.load(Ordering::Relaxed), intid)
+    }
+}
+
+// GICv2 addresses CPUs by interface number, a bit in an 8-bit mask. The
+// banked ITARGETSR0 reads back our own; for others, assume interface n is
+// Aff0 n, as on QEMU's virt machine.
+fn v2_cpu_mask(cpu: u64) -> u8 {
+    if cpu == arch::cpu_id() {
+        let distributor = DISTRIBUTOR.load(Ordering::Relaxed);
+        let own = unsafe { ((distributor + GICD_ITARGETSR) as *const u8).read_volatile() };
+        if own != 0 {
+            return own;
+        }
+    }
+    1 << (cpu & 7)
+}
+
+// ICC_SGI1R_EL1 fields for one CPU: a target list bit within its Aff1-3
+// cluster, with the range selector covering Aff0 above 15
+fn sgi1r_target(cpu: u64) -> u64 {
+    let aff0 = cpu & 0xFF;
+    let aff1 = (cpu >> 8) & 0xFF;
+    let aff2 = (cpu >> 16) & 0xFF;
+    let aff3 = (cpu >> 32) & 0xFF;
+    (1 << (aff0 % 16)) | aff1 << 16 | aff2 << 32 | (aff0 / 16) << 44 | aff3 << 48
+}
+
+// The RD frame whose GICR_TYPER affinity matches `cpu`
+unsafe fn find_redistributor(regions: &[(u64, u64)], cpu: u64) -> Option<u64> {
+    // GICR_TYPER packs Aff3.Aff2.Aff1.Aff0 into bits 32-63
+    let affinity = ((cpu >> 32) & 0xFF) << 24 | (cpu & 0xFF_FFFF);
+    for &(base, length) in regions {
+        let mut frame = base;
+        while frame < base + length {
+            let typer = read64(frame + GICR_TYPER);
+            if typer >> 32 == affinity {
+                return Some(frame);
+            }
+            if typer & GICR_TYPER_LAST != 0 {
+                break;
+            }
+            frame += if typer & GICR_TYPER_VLPIS != 0 {
+                GICR_STRIDE_VLPIS
+            } else {
+                GICR_STRIDE
+            };
+        }
+    }
+    None
+}
+
+unsafe fn wait_distributor(distributor: u64) {
+    while read32(distributor + GICD_CTLR) & GICD_CTLR_RWP != 0 {
+        core::hint::spin_loop();
+    }
+}
+
+unsafe fn read32(address: u64) -> u32 {
+    (address as *const u32).read_volatile()
+}
+
+unsafe fn write32(address: u64, value: u32) {
+    (address as *mut u32).write_volatile(value)
+}
+
+unsafe fn read64(address: u64) -> u64 {
+    (address as *const u64).read_volatile()
+}
@@ -0,0 +1,69 @@
+// kernel/src/drivers/hpet.rs
+//
+// High Precision Event Timer, used only for its main counter: a free
+// running up-counter with a fixed period given in femtoseconds. Some
+// implementations only have 32 counter bits.
+
+// Register offsets
+const CAPABILITIES: u64 = 0x000;
+const CONFIGURATION: u64 = 0x010;
+const MAIN_COUNTER: u64 = 0x0F0;
+
+// Capabilities register
+const COUNTER_64BIT: u64 = 1 << 13;
+
+const CONFIGURATION_ENABLE: u64 = 1 << 0;
+
+const FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+// The specification caps the period at 100 ns
+const MAX_PERIOD_FS: u64 = 100_000_000;
+
+pub struct Hpet {
+    base: u64,
+    period_fs: u64,
+    counter_mask: u64,
+}
+
+impl Hpet {
+    // `base` must already be mapped uncached. None if the period makes no
+    // sense, which is what reading an absent device gives.
+    pub unsafe fn new(base: u64) -> Option<Hpet> {
+        let capabilities = ((base + CAPABILITIES) as *const u64).read_volatile();
+        let period_fs = capabilities >> 32;
+        if period_fs == 0 || period_fs > MAX_PERIOD_FS {
+            return None;
+        }
+
+        Some(Hpet {
+            base,
+            period_fs,
+            counter_mask: if capabilities & COUNTER_64BIT != 0 {
+                u64::MAX
+            } else {
+                u32::MAX as u64
+            },
+        })
+    }
+
+    // Counter ticks per second
+    pub fn frequency(&self) -> u64 {
+        FEMTOSECONDS_PER_SECOND / self.period_fs
+    }
+
+    // Start the main counter if the firmware hasn't
+    pub fn enable(&self) {
+        unsafe {
+            let configuration = (self.base + CONFIGURATION) as *mut u64;
+            configuration.write_volatile(configuration.read_volatile() | CONFIGURATION_ENABLE);
+        }
+    }
+
+    pub fn counter(&self) -> u64 {
+        unsafe { ((self.base + MAIN_COUNTER) as *const u64).read_volatile() & self.counter_mask }
+    }
+
+    // Ticks from `start` to now, across one wrap of a 32-bit counter
+    pub fn ticks_since(&self, start: u64) -> u64 {
+        self.counter().wrapping_sub(start) & self.counter_mask
+    }
+}
@@ -0,0 +1,100 @@
+// kernel/src/drivers/ioapic.rs
+//
+// I/O APIC: turns device interrupt lines into messages to the local APICs.
+// Each one owns a contiguous range of global system interrupts (GSIs)
+// starting at its base, one redirection entry per input. The registers sit
+// behind an index/data window.
+
+// Window offsets
+const REGISTER_SELECT: u64 = 0x00;
+const REGISTER_WINDOW: u64 = 0x10;
+
+// Indirect registers
+const IOAPIC_VERSION: u32 = 0x01;
+const REDIRECTION_TABLE: u32 = 0x10;
+
+// Redirection entry, low half
+const ACTIVE_LOW: u32 = 1 << 13;
+const LEVEL_TRIGGERED: u32 = 1 << 15;
+const MASKED: u32 = 1 << 16;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Redirection {
+    pub vector: u8,
+    // Physical APIC ID of the CPU that takes the interrupt. Only 8 bits fit
+    // without interrupt remapping.
+    pub destination: u8,
+    pub active_low: bool,
+    pub level_triggered: bool,
+    pub masked: bool,
+}
+
+pub struct IoApic {
+    base: u64,
+    gsi_base: u32,
+    inputs: u32,
+}
+
+impl IoApic {
+    // `base` must already be mapped uncached
+    pub unsafe fn new(base: u64, gsi_base: u32) -> IoApic {
+        let mut ioapic = IoApic {
+            base,
+            gsi_base,
+            inputs: 0,
+        };
+        // Bits 16-23: index of the last redirection entry
+        ioapic.inputs = ((ioapic.read(IOAPIC_VERSION) >> 16) & 0xFF) + 1;
+        ioapic
+    }
+
+    pub fn handles(&self, gsi: u32) -> bool {
+        (self.gsi_base..self.gsi_base + self.inputs).contains(&gsi)
+    }
+
+    // Program the entry for `gsi`, which must be one of ours
+    pub fn set_redirection(&mut self, gsi: u32, redirection: Redirection) {
+        let mut low = redirection.vector as u32;
+        if redirection.active_low {
+            low |= ACTIVE_LOW;
+        }
+        if redirection.level_triggered {
+            low |= LEVEL_TRIGGERED;
+        }
+        if redirection.masked {
+            low |= MASKED;
+        }
+
+        // Masked while the halves disagree
+        let register = REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        self.write(register, MASKED);
+        self.write(register + 1, (redirection.destination as u32) << 24);
+        self.write(register, low);
+    }
+
+    pub fn set_masked(&mut self, gsi: u32, masked: bool) {
+        let register = REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        let low = self.read(register);
+        self.write(register, if masked { low | MASKED } else { low & !MASKED });
+    }
+
+    pub fn mask_all(&mut self) {
+        for input in 0..self.inputs {
+            self.set_masked(self.gsi_base + input, true);
+        }
+    }
+
+    fn read(&self, register: u32) -> u32 {
+        unsafe {
+            ((self.base + REGISTER_SELECT) as *mut u32).write_volatile(register);
+            ((self.base + REGISTER_WINDOW) as *const u32).read_volatile()
+        }
+    }
+
+    fn write(&mut self, register: u32, value: u32) {
+        unsafe {
+            ((self.base + REGISTER_SELECT) as *mut u32).write_volatile(register);
+            ((self.base + REGISTER_WINDOW) as *mut u32).write_volatile(value);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+// kernel/src/drivers/mod.rs
+//
+// Device drivers. Each driver only knows how to talk to its device; finding
+// the device and deciding what to use it for happens elsewhere.
+
+#[cfg(target_arch = "x86_64")]
+pub mod apic;
+
+#[cfg(target_arch = "x86_64")]
+pub mod cmos_rtc;
+
+#[cfg(target_arch = "x86_64")]
+pub mod debugcon;
+
+#[cfg(target_arch = "aarch64")]
+pub mod gic;
+
+#[cfg(target_arch = "x86_64")]
+pub mod hpet;
+
+#[cfg(target_arch = "x86_64")]
+pub mod ioapic;
+
+#[cfg(target_arch = "x86_64")]
+pub mod pic8259;
+
+#[cfg(target_arch = "x86_64")]
+pub mod pit;
+
+pub mod pl011;
+
+#[cfg(target_arch = "aarch64")]
+pub mod pl031;
+
+pub mod uart16550;
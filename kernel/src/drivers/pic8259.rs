@@ -0,0 +1,37 @@
+// kernel/src/drivers/pic8259.rs
+//
+// The legacy pair of 8259 PICs. The kernel uses the APICs instead, but the
+// PICs come out of reset raising IRQs on vectors that overlap the CPU
+// exceptions, so they get remapped out of the way and fully masked.
+
+use crate::arch::x86_64::port;
+
+const PRIMARY_COMMAND: u16 = 0x20;
+const PRIMARY_DATA: u16 = 0x21;
+const SECONDARY_COMMAND: u16 = 0xA0;
+const SECONDARY_DATA: u16 = 0xA1;
+
+// ICW1: initialisation, ICW4 follows
+const ICW1_INIT: u8 = 0x11;
+// ICW4: 8086 mode
+const ICW4_8086: u8 = 0x01;
+
+// Reinitialise both PICs with their 16 IRQs on the vectors starting at
+// `vector_base` and mask every IRQ. A spurious IRQ 7 or 15 can still arrive
+// on those vectors afterwards.
+pub fn disable(vector_base: u8) {
+    unsafe {
+        port::outb(PRIMARY_COMMAND, ICW1_INIT);
+        port::outb(SECONDARY_COMMAND, ICW1_INIT);
+        port::outb(PRIMARY_DATA, vector_base);
+        port::outb(SECONDARY_DATA, vector_base + 8);
+        // The secondary hangs off the primary's IRQ 2
+        port::outb(PRIMARY_DATA, 1 << 2);
+        port::outb(SECONDARY_DATA, 2);
+        port::outb(PRIMARY_DATA, ICW4_8086);
+        port::outb(SECONDARY_DATA, ICW4_8086);
+
+        port::outb(PRIMARY_DATA, 0xFF);
+        port::outb(SECONDARY_DATA, 0xFF);
+    }
+}
@@ -0,0 +1,41 @@
+// kernel/src/drivers/pit.rs
+//
+// The 8254 programmable interval timer, used only as a stopwatch: channel
+// 2 is gated through port 0x61 rather than wired to an IRQ, and its OUT
+// line can be read back there, so counting down needs no interrupts.
+
+use crate::arch::x86_64::port;
+
+pub const FREQUENCY: u64 = 1_193_182;
+
+const CHANNEL2_DATA: u16 = 0x42;
+const COMMAND: u16 = 0x43;
+const GATE_PORT: u16 = 0x61;
+
+// Port 0x61 bits
+const GATE2: u8 = 1 << 0;
+const SPEAKER: u8 = 1 << 1;
+const OUT2: u8 = 1 << 5;
+
+// Channel 2, low then high byte, mode 0 (interrupt on terminal count)
+const CHANNEL2_ONESHOT: u8 = 0xB0;
+
+// Busy-wait for `ticks` PIT periods
+pub fn wait(ticks: u16) {
+    unsafe {
+        // Gate low stops the count, and keep the speaker out of it
+        let gate = port::inb(GATE_PORT) & !(GATE2 | SPEAKER);
+        port::outb(GATE_PORT, gate);
+
+        port::outb(COMMAND, CHANNEL2_ONESHOT);
+        port::outb(CHANNEL2_DATA, ticks as u8);
+        port::outb(CHANNEL2_DATA, (ticks >> 8) as u8);
+
+        // Counting starts on the gate's rising edge, OUT goes high at zero
+        port::outb(GATE_PORT, gate | GATE2);
+        while port::inb(GATE_PORT) & OUT2 == 0 {
+            core::hint::spin_loop();
+        }
+        port::outb(GATE_PORT, gate);
+    }
+}
@@ -0,0 +1,62 @@
+// kernel/src/drivers/pl011.rs
+//
+// ARM PrimeCell PL011 UART. The firmware has already programmed the baud
+// rate, so we only make sure the UART and its transmitter are enabled.
+
+// Register offsets
+const DR: usize = 0x000; // Data
+const FR: usize = 0x018; // Flags
+const CR: usize = 0x030; // Control
+
+const FR_RXFE: u32 = 1 << 4; // Receive FIFO empty
+const FR_TXFF: u32 = 1 << 5; // Transmit FIFO full
+
+const CR_UARTEN: u32 = 1 << 0;
+const CR_TXE: u32 = 1 << 8;
+const CR_RXE: u32 = 1 << 9;
+
+// The PrimeCell register page
+const MMIO_SIZE: u64 = 0x1000;
+
+pub struct Pl011 {
+    base: usize,
+}
+
+impl Pl011 {
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    // Physical base and length of the registers
+    pub fn mmio_window(&self) -> (u64, u64) {
+        (self.base as u64, MMIO_SIZE)
+    }
+
+    fn read(&self, register: usize) -> u32 {
+        unsafe { core::ptr::read_volatile((self.base + register) as *const u32) }
+    }
+
+    fn write(&self, register: usize, value: u32) {
+        unsafe { core::ptr::write_volatile((self.base + register) as *mut u32, value) }
+    }
+
+    pub fn init(&self) {
+        let cr = self.read(CR);
+        self.write(CR, cr | CR_UARTEN | CR_TXE | CR_RXE);
+    }
+
+    pub fn write_byte(&self, byte: u8) {
+        while self.read(FR) & FR_TXFF != 0 {
+            core::hint::spin_loop();
+        }
+        self.write(DR, byte as u32);
+    }
+
+    pub fn read_byte(&self) -> Option<u8> {
+        if self.read(FR) & FR_RXFE == 0 {
+            Some(self.read(DR) as u8)
+        } else {
+            None
+        }
+    }
+}
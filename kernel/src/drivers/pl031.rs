@@ -0,0 +1,46 @@
+// kernel/src/drivers/pl031.rs
+//
+// ARM PrimeCell PL031 real-time clock: a 32-bit count of seconds, which
+// everyone (QEMU's virt machine included) treats as seconds since the Unix
+// epoch in UTC.
+
+// Register offsets
+const DR: u64 = 0x000; // Data
+const LR: u64 = 0x008; // Load
+const CR: u64 = 0x00C; // Control
+
+const CR_START: u32 = 1 << 0;
+
+pub struct Pl031 {
+    base: u64,
+}
+
+impl Pl031 {
+    // `base` must already be mapped uncached
+    pub const unsafe fn new(base: u64) -> Pl031 {
+        Pl031 { base }
+    }
+
+    fn read(&self, register: u64) -> u32 {
+        unsafe { core::ptr::read_volatile((self.base + register) as *const u32) }
+    }
+
+    fn write(&self, register: u64, value: u32) {
+        unsafe { core::ptr::write_volatile((self.base + register) as *mut u32, value) }
+    }
+
+    // Start the counter if the firmware hasn't. It can't be stopped again.
+    pub fn init(&self) {
+        if self.read(CR) & CR_START == 0 {
+            self.write(CR, CR_START);
+        }
+    }
+
+    pub fn seconds(&self) -> u32 {
+        self.read(DR)
+    }
+
+    pub fn set_seconds(&self, seconds: u32) {
+        self.write(LR, seconds);
+    }
+}
@@ -0,0 +1,96 @@
+// kernel/src/drivers/uart16550.rs
+//
+// 16550-compatible UART, either behind x86 I/O ports (COM1 at 0x3F8) or
+// memory mapped with byte-wide registers, as the ACPI SPCR table or the
+// device tree may describe it.
+
+// Register offsets
+const DATA: u16 = 0; // RBR/THR, or divisor low with DLAB set
+const INTERRUPT_ENABLE: u16 = 1; // IER, or divisor high with DLAB set
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+const LINE_STATUS_THR_EMPTY: u8 = 1 << 5;
+
+// Divisor for 115200 baud from the standard 1.8432 MHz clock
+const DIVISOR_115200: u16 = 1;
+
+// Bytes of register space when memory mapped
+const MMIO_SIZE: u64 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    #[cfg(target_arch = "x86_64")]
+    Port(u16),
+    Mmio(usize),
+}
+
+pub struct Uart16550 {
+    access: Access,
+}
+
+impl Uart16550 {
+    pub const fn new(access: Access) -> Self {
+        Self { access }
+    }
+
+    // Physical base and length of the registers, if they are memory mapped
+    pub fn mmio_window(&self) -> Option<(u64, u64)> {
+        match self.access {
+            #[cfg(target_arch = "x86_64")]
+            Access::Port(_) => None,
+            Access::Mmio(base) => Some((base as u64, MMIO_SIZE)),
+        }
+    }
+
+    fn read(&self, register: u16) -> u8 {
+        match self.access {
+            #[cfg(target_arch = "x86_64")]
+            Access::Port(base) => unsafe { crate::arch::x86_64::port::inb(base + register) },
+            Access::Mmio(base) => unsafe {
+                core::ptr::read_volatile((base + register as usize) as *const u8)
+            },
+        }
+    }
+
+    fn write(&self, register: u16, value: u8) {
+        match self.access {
+            #[cfg(target_arch = "x86_64")]
+            Access::Port(base) => unsafe {
+                crate::arch::x86_64::port::outb(base + register, value)
+            },
+            Access::Mmio(base) => unsafe {
+                core::ptr::write_volatile((base + register as usize) as *mut u8, value)
+            },
+        }
+    }
+
+    // 115200 baud, 8N1, FIFOs on, interrupts off
+    pub fn init(&self) {
+        self.write(INTERRUPT_ENABLE, 0x00);
+        self.write(LINE_CONTROL, 0x80); // DLAB on
+        self.write(DATA, (DIVISOR_115200 & 0xFF) as u8);
+        self.write(INTERRUPT_ENABLE, (DIVISOR_115200 >> 8) as u8);
+        self.write(LINE_CONTROL, 0x03); // DLAB off, 8 bits, no parity, 1 stop bit
+        self.write(FIFO_CONTROL, 0xC7); // Enable and clear FIFOs, 14 byte threshold
+        self.write(MODEM_CONTROL, 0x03); // DTR and RTS
+    }
+
+    pub fn write_byte(&self, byte: u8) {
+        while self.read(LINE_STATUS) & LINE_STATUS_THR_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.write(DATA, byte);
+    }
+
+    pub fn read_byte(&self) -> Option<u8> {
+        if self.read(LINE_STATUS) & LINE_STATUS_DATA_READY != 0 {
+            Some(self.read(DATA))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,355 @@
+// kernel/src/efi.rs
+//
+// UEFI runtime services preserved by the bootloader across exit_boot_services.
+// The firmware tables are reached through raw pointers from BootInfo, so every
+// call is funnelled through a lock: runtime services are not reentrant.
+
+use crate::sync::SpinLock;
+use crate::BootInfo;
+
+#[cfg(test)]
+mod tests;
+
+// Must match MAX_RUNTIME_REGIONS in the bootloader
+pub const MAX_RUNTIME_REGIONS: usize = 64;
+
+// Version of the descriptor layout passed to SetVirtualAddressMap
+const MEMORY_DESCRIPTOR_VERSION: u32 = 1;
+
+// Layout of EFI_MEMORY_DESCRIPTOR
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MemoryDescriptor {
+    pub ty: u32,
+    pub phys_start: u64,
+    pub virt_start: u64,
+    pub page_count: u64,
+    pub attribute: u64,
+}
+
+// EFI_STATUS, with the error bit set for failures
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status(pub usize);
+
+impl Status {
+    const ERROR_BIT: usize = 1 << (usize::BITS - 1);
+
+    pub const SUCCESS: Status = Status(0);
+    pub const INVALID_PARAMETER: Status = Status(Self::ERROR_BIT | 2);
+    pub const UNSUPPORTED: Status = Status(Self::ERROR_BIT | 3);
+    pub const NOT_FOUND: Status = Status(Self::ERROR_BIT | 14);
+
+    pub fn is_error(self) -> bool {
+        self.0 & Self::ERROR_BIT != 0
+    }
+
+    fn into_result(self) -> Result<(), Status> {
+        if self.is_error() {
+            Err(self)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Guid {
+    pub data1: u32,
+    pub data2: u16,
+    pub data3: u16,
+    pub data4: [u8; 8],
+}
+
+// EFI_GLOBAL_VARIABLE, the vendor GUID of the architectural variables (BootOrder, ...)
+pub const GLOBAL_VARIABLE: Guid = Guid {
+    data1: 0x8BE4_DF61,
+    data2: 0x93CA,
+    data3: 0x11D2,
+    data4: [0xAA, 0x0D, 0x00, 0xE0, 0x98, 0x03, 0x2B, 0x8C],
+};
+
+// Variable attributes
+pub const VARIABLE_NON_VOLATILE: u32 = 0x1;
+pub const VARIABLE_BOOTSERVICE_ACCESS: u32 = 0x2;
+pub const VARIABLE_RUNTIME_ACCESS: u32 = 0x4;
+
+// OsIndications bit asking the firmware to stop in its setup menu
+const OS_INDICATIONS_BOOT_TO_FW_UI: u64 = 0x1;
+
+const OS_INDICATIONS: [u16; 14] = variable_name("OsIndications");
+const OS_INDICATIONS_SUPPORTED: [u16; 23] = variable_name("OsIndicationsSupported");
+
+// Layout of EFI_TIME
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pad1: u8,
+    pub nanosecond: u32,
+    pub time_zone: i16,
+    pub daylight: u8,
+    pad2: u8,
+}
+
+impl Time {
+    // time_zone value for a clock that keeps local time of no stated zone
+    pub const UNSPECIFIED_TIMEZONE: i16 = 0x07FF;
+
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+        nanosecond: u32,
+    ) -> Time {
+        Time {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            nanosecond,
+            time_zone: Self::UNSPECIFIED_TIMEZONE,
+            ..Time::default()
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimeCapabilities {
+    pub resolution: u32,
+    pub accuracy: u32,
+    pub sets_to_zero: bool,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetType {
+    Cold = 0,
+    Shutdown = 2,
+}
+
+#[repr(C)]
+struct TableHeader {
+    signature: u64,
+    revision: u32,
+    header_size: u32,
+    crc32: u32,
+    reserved: u32,
+}
+
+// Layout of EFI_RUNTIME_SERVICES, only the services we call are typed
+#[repr(C)]
+struct RuntimeServicesTable {
+    header: TableHeader,
+    get_time: unsafe extern "efiapi" fn(*mut Time, *mut TimeCapabilities) -> Status,
+    set_time: unsafe extern "efiapi" fn(*const Time) -> Status,
+    get_wakeup_time: usize,
+    set_wakeup_time: usize,
+    set_virtual_address_map:
+        unsafe extern "efiapi" fn(usize, usize, u32, *mut MemoryDescriptor) -> Status,
+    convert_pointer: usize,
+    get_variable:
+        unsafe extern "efiapi" fn(*const u16, *const Guid, *mut u32, *mut usize, *mut u8) -> Status,
+    get_next_variable_name: usize,
+    set_variable:
+        unsafe extern "efiapi" fn(*const u16, *const Guid, u32, usize, *const u8) -> Status,
+    get_next_high_monotonic_count: usize,
+    reset_system: unsafe extern "efiapi" fn(ResetType, Status, usize, *const u8) -> !,
+}
+
+struct RuntimeServices {
+    table: *const RuntimeServicesTable,
+    regions: [MemoryDescriptor; MAX_RUNTIME_REGIONS],
+    region_count: usize,
+    virtual_mode: bool,
+}
+
+// The table lives in firmware memory that stays valid for the kernel's lifetime
+unsafe impl Send for RuntimeServices {}
+
+static RUNTIME: SpinLock<Option<RuntimeServices>> = SpinLock::new(None);
+
+// Pick up the runtime services handed over by the bootloader.
+// Until enter_virtual_mode is called, the firmware runs on its own identity
+// map, or whatever of it the current page tables keep.
+pub fn init(boot_info: &BootInfo) {
+    if boot_info.runtime_services_addr == 0 {
+        return;
+    }
+
+    *RUNTIME.lock() = Some(RuntimeServices {
+        table: boot_info.runtime_services_addr as *const RuntimeServicesTable,
+        regions: boot_info.runtime_regions,
+        region_count: boot_info.runtime_region_count.min(MAX_RUNTIME_REGIONS),
+        virtual_mode: false,
+    });
+}
+
+pub fn is_available() -> bool {
+    RUNTIME.lock().is_some()
+}
+
+// The runtime regions the kernel has to keep mapped for the firmware
+pub fn runtime_regions() -> impl Iterator<Item = MemoryDescriptor> {
+    let (regions, count) = match RUNTIME.lock().as_ref() {
+        Some(rt) => (rt.regions, rt.region_count),
+        None => ([MemoryDescriptor::default(); MAX_RUNTIME_REGIONS], 0),
+    };
+    regions.into_iter().take(count)
+}
+
+// Switch the firmware to the kernel's address space.
+//
+// Call this once the kernel's own page tables are active and map every runtime
+// region both at its physical address and at translate(phys_start). UEFI only
+// allows this once per boot, later calls fail with UNSUPPORTED.
+pub fn enter_virtual_mode(translate: impl Fn(u64) -> u64) -> Result<(), Status> {
+    let mut guard = RUNTIME.lock();
+    let rt = guard.as_mut().ok_or(Status::UNSUPPORTED)?;
+
+    if rt.virtual_mode {
+        return Err(Status::UNSUPPORTED);
+    }
+
+    let mut map = rt.regions;
+    for region in map[..rt.region_count].iter_mut() {
+        region.virt_start = translate(region.phys_start);
+    }
+
+    unsafe {
+        ((*rt.table).set_virtual_address_map)(
+            rt.region_count * core::mem::size_of::<MemoryDescriptor>(),
+            core::mem::size_of::<MemoryDescriptor>(),
+            MEMORY_DESCRIPTOR_VERSION,
+            map.as_mut_ptr(),
+        )
+        .into_result()?;
+    }
+
+    // The firmware has converted its own pointers, we convert ours
+    rt.table = translate(rt.table as u64) as *const RuntimeServicesTable;
+    rt.regions = map;
+    rt.virtual_mode = true;
+    Ok(())
+}
+
+pub fn get_time() -> Result<Time, Status> {
+    let guard = RUNTIME.lock();
+    let rt = guard.as_ref().ok_or(Status::UNSUPPORTED)?;
+
+    let mut time = Time::default();
+    unsafe {
+        ((*rt.table).get_time)(&mut time, core::ptr::null_mut()).into_result()?;
+    }
+    Ok(time)
+}
+
+// Set the firmware's real-time clock
+pub fn set_time(time: &Time) -> Result<(), Status> {
+    let guard = RUNTIME.lock();
+    let rt = guard.as_ref().ok_or(Status::UNSUPPORTED)?;
+
+    unsafe { ((*rt.table).set_time)(time).into_result() }
+}
+
+// Read a variable into buf, returning its size and attributes.
+// `name` must be a NUL-terminated UTF-16 string. If buf is too small the
+// error is BUFFER_TOO_SMALL and nothing is written.
+pub fn get_variable(name: &[u16], vendor: &Guid, buf: &mut [u8]) -> Result<(usize, u32), Status> {
+    if name.last() != Some(&0) {
+        return Err(Status::INVALID_PARAMETER);
+    }
+
+    let guard = RUNTIME.lock();
+    let rt = guard.as_ref().ok_or(Status::UNSUPPORTED)?;
+
+    let mut attributes = 0u32;
+    let mut size = buf.len();
+    unsafe {
+        ((*rt.table).get_variable)(
+            name.as_ptr(),
+            vendor,
+            &mut attributes,
+            &mut size,
+            buf.as_mut_ptr(),
+        )
+        .into_result()?;
+    }
+    Ok((size, attributes))
+}
+
+// Create, update or (with empty data) delete a variable.
+// Variables written after exit_boot_services need VARIABLE_RUNTIME_ACCESS.
+pub fn set_variable(name: &[u16], vendor: &Guid, attributes: u32, data: &[u8]) -> Result<(), Status> {
+    if name.last() != Some(&0) {
+        return Err(Status::INVALID_PARAMETER);
+    }
+
+    let guard = RUNTIME.lock();
+    let rt = guard.as_ref().ok_or(Status::UNSUPPORTED)?;
+
+    unsafe {
+        ((*rt.table).set_variable)(name.as_ptr(), vendor, attributes, data.len(), data.as_ptr())
+            .into_result()
+    }
+}
+
+// Have the firmware stop in its setup menu on the next boot, for
+// reset_system to take the machine there
+pub fn request_firmware_setup() -> Result<(), Status> {
+    let mut supported = [0u8; 8];
+    get_variable(&OS_INDICATIONS_SUPPORTED, &GLOBAL_VARIABLE, &mut supported)?;
+    if u64::from_le_bytes(supported) & OS_INDICATIONS_BOOT_TO_FW_UI == 0 {
+        return Err(Status::UNSUPPORTED);
+    }
+
+    let mut indications = [0u8; 8];
+    match get_variable(&OS_INDICATIONS, &GLOBAL_VARIABLE, &mut indications) {
+        Ok(_) | Err(Status::NOT_FOUND) => {}
+        Err(status) => return Err(status),
+    }
+    let indications = u64::from_le_bytes(indications) | OS_INDICATIONS_BOOT_TO_FW_UI;
+    set_variable(
+        &OS_INDICATIONS,
+        &GLOBAL_VARIABLE,
+        VARIABLE_NON_VOLATILE | VARIABLE_BOOTSERVICE_ACCESS | VARIABLE_RUNTIME_ACCESS,
+        &indications.to_le_bytes(),
+    )
+}
+
+// Reset or power off the machine.
+// Only returns (with UNSUPPORTED) when the firmware gave us no runtime services.
+pub fn reset_system(reset_type: ResetType) -> Status {
+    let guard = RUNTIME.lock();
+    match guard.as_ref() {
+        Some(rt) => unsafe {
+            ((*rt.table).reset_system)(reset_type, Status::SUCCESS, 0, core::ptr::null())
+        },
+        None => Status::UNSUPPORTED,
+    }
+}
+
+// An ASCII variable name as the NUL-terminated UTF-16 the services take
+const fn variable_name<const N: usize>(name: &str) -> [u16; N] {
+    let bytes = name.as_bytes();
+    assert!(bytes.len() + 1 == N);
+    let mut utf16 = [0; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        utf16[i] = bytes[i] as u16;
+        i += 1;
+    }
+    utf16
+}
@@ -0,0 +1,52 @@
+// kernel/src/efi/tests.rs
+//
+// The runtime services after the switch to virtual mode at boot. Nothing
+// here writes a variable, so the tests leave the firmware as they found it.
+
+use super::*;
+
+#[test_case]
+fn virtual_mode() {
+    if !is_available() {
+        return;
+    }
+    assert!(RUNTIME.lock().as_ref().unwrap().virtual_mode);
+    // Once per boot
+    assert_eq!(enter_virtual_mode(|phys| phys), Err(Status::UNSUPPORTED));
+}
+
+// GetTime, through the converted table
+#[test_case]
+fn get_time_in_virtual_mode() {
+    if !is_available() {
+        return;
+    }
+    let time = get_time().unwrap();
+    assert!((1..=12).contains(&time.month), "{:?}", time);
+    assert!((1..=31).contains(&time.day), "{:?}", time);
+    assert!(
+        time.hour < 24 && time.minute < 60 && time.second < 60,
+        "{:?}",
+        time
+    );
+}
+
+// OsIndicationsSupported is a read-only variable every UEFI 2.x firmware has
+#[test_case]
+fn get_variable_in_virtual_mode() {
+    if !is_available() {
+        return;
+    }
+    let mut value = [0u8; 8];
+    let (size, attributes) =
+        get_variable(&OS_INDICATIONS_SUPPORTED, &GLOBAL_VARIABLE, &mut value).unwrap();
+    assert_eq!(size, 8);
+    assert!(attributes & VARIABLE_RUNTIME_ACCESS != 0);
+
+    assert!(get_variable(&OS_INDICATIONS_SUPPORTED, &GLOBAL_VARIABLE, &mut [0; 4]).is_err());
+    let unterminated = &OS_INDICATIONS_SUPPORTED[..22];
+    assert_eq!(
+        get_variable(unterminated, &GLOBAL_VARIABLE, &mut value),
+        Err(Status::INVALID_PARAMETER)
+    );
+}
@@ -0,0 +1,96 @@
+// kernel/src/fdt.rs
+//
+// The firmware's device tree, and the drivers for what it describes. The
+// parser is in firmware::fdt, which builds for the host too, and is
+// re-exported from here.
+//
+// Drivers register the compatible strings they handle, and probe() hands
+// each enabled node to the driver for its most specific compatible.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+pub use kernel::firmware::fdt::*;
+
+use crate::mm::phys_to_virt;
+use crate::sync::SpinLock;
+use crate::BootInfo;
+
+const MAX_DRIVERS: usize = 32;
+
+static DTB_ADDR: AtomicU64 = AtomicU64::new(0);
+
+// A device tree driver. `probe` is given each enabled node whose most
+// specific compatible string is in `compatible`, and returns whether it
+// took the device.
+pub struct Driver {
+    pub name: &'static str,
+    pub compatible: &'static [&'static str],
+    pub probe: fn(node: Node<'static>) -> bool,
+}
+
+static DRIVERS: SpinLock<[Option<&'static Driver>; MAX_DRIVERS]> =
+    SpinLock::new([None; MAX_DRIVERS]);
+
+pub fn init(boot_info: &BootInfo) {
+    DTB_ADDR.store(boot_info.device_tree_addr, Ordering::Relaxed);
+}
+
+// The firmware's device tree, if it passed a valid one
+pub fn get() -> Option<Fdt<'static>> {
+    match DTB_ADDR.load(Ordering::Relaxed) {
+        0 => None,
+        addr => unsafe { blob_at(addr).ok() },
+    }
+}
+
+// The blob at physical address `addr`, sized by its own header
+unsafe fn blob_at(addr: u64) -> Result<Fdt<'static>, Error> {
+    let header = core::slice::from_raw_parts(phys_to_virt(addr) as *const u8, HEADER_SIZE);
+    let total_size = Fdt::total_size(header)?;
+    Fdt::new(core::slice::from_raw_parts(phys_to_virt(addr), total_size))
+}
+
+// Returns false once MAX_DRIVERS are registered. Only aarch64 has device
+// tree drivers so far.
+#[cfg_attr(not(target_arch = "aarch64"), allow(dead_code))]
+pub fn register(driver: &'static Driver) -> bool {
+    let mut drivers = DRIVERS.lock();
+    match drivers.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(driver);
+            true
+        }
+        None => false,
+    }
+}
+
+// Offer every enabled node to the registered drivers, returning how many
+// were taken. Only the driver for the first compatible string that has one
+// is tried, as the strings go from most to least specific.
+pub fn probe() -> usize {
+    let Some(fdt) = get() else {
+        return 0;
+    };
+    // Copied out so probe functions can register drivers themselves
+    let drivers = *DRIVERS.lock();
+
+    let mut bound = 0;
+    for node in fdt.nodes().filter(|node| node.is_enabled()) {
+        let driver = node.compatible().find_map(|compatible| {
+            drivers
+                .iter()
+                .flatten()
+                .find(|driver| driver.compatible.contains(&compatible))
+        });
+        let Some(driver) = driver else {
+            continue;
+        };
+        if (driver.probe)(node) {
+            info!("{}: bound to {}", driver.name, node.name());
+            bound += 1;
+        } else {
+            warn!("{}: failed to probe {}", driver.name, node.name());
+        }
+    }
+    bound
+}
@@ -0,0 +1,104 @@
+// kernel/src/firmware/acpi/aml.rs
+//
+// Just enough AML to find the sleep state packages (\_S0_ to \_S5_) in the
+// DSDT or an SSDT, without an interpreter. They are almost always a plain
+// Name(_S5_, Package() {a, b, ...}) of integer constants; firmware that
+// computes them in a method is out of luck.
+
+use super::HEADER_SIZE;
+
+const NAME_OP: u8 = 0x08;
+const ROOT_PREFIX: u8 = b'\\';
+const PACKAGE_OP: u8 = 0x12;
+
+// Integer constant encodings
+const ZERO_OP: u8 = 0x00;
+const ONE_OP: u8 = 0x01;
+const BYTE_PREFIX: u8 = 0x0A;
+const WORD_PREFIX: u8 = 0x0B;
+const DWORD_PREFIX: u8 = 0x0C;
+const QWORD_PREFIX: u8 = 0x0E;
+const ONES_OP: u8 = 0xFF;
+
+// SLP_TYP values to write to PM1a and PM1b control to enter a sleep state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SleepType {
+    pub a: u8,
+    pub b: u8,
+}
+
+// The SLP_TYP values of sleep state `state` (5 for soft-off) defined in
+// `table`, a whole DSDT or SSDT
+pub fn find_sleep_type(table: &[u8], state: u8) -> Option<SleepType> {
+    let name = [b'_', b'S', b'0' + state, b'_'];
+    let aml = table.get(HEADER_SIZE..)?;
+
+    (1..aml.len().saturating_sub(3)).find_map(|i| {
+        if aml[i..i + 4] != name {
+            return None;
+        }
+        let is_name =
+            aml[i - 1] == NAME_OP || (aml[i - 1] == ROOT_PREFIX && i >= 2 && aml[i - 2] == NAME_OP);
+        if !is_name {
+            return None;
+        }
+        parse_sleep_package(&aml[i + 4..])
+    })
+}
+
+// PackageOp PkgLength NumElements, then SLP_TYPa and SLP_TYPb
+fn parse_sleep_package(bytes: &[u8]) -> Option<SleepType> {
+    if *bytes.first()? != PACKAGE_OP {
+        return None;
+    }
+    let (_, length_size) = pkg_length(bytes.get(1..)?)?;
+    let mut offset = 1 + length_size + 1;
+    let (a, size) = integer(bytes.get(offset..)?)?;
+    offset += size;
+    // Some firmware lists only SLP_TYPa
+    let b = integer(bytes.get(offset..)?).map_or(0, |(b, _)| b);
+    Some(SleepType {
+        a: a as u8,
+        b: b as u8,
+    })
+}
+
+// A PkgLength and how many bytes it takes: the lead byte's top two bits
+// count the bytes that follow, which hold the length above its low nibble
+fn pkg_length(bytes: &[u8]) -> Option<(usize, usize)> {
+    let lead = *bytes.first()?;
+    let follow = (lead >> 6) as usize;
+    if follow == 0 {
+        return Some(((lead & 0x3F) as usize, 1));
+    }
+
+    let mut length = (lead & 0x0F) as usize;
+    for (i, &byte) in bytes.get(1..=follow)?.iter().enumerate() {
+        length |= (byte as usize) << (4 + 8 * i);
+    }
+    Some((length, 1 + follow))
+}
+
+// A constant integer and how many bytes it takes
+fn integer(bytes: &[u8]) -> Option<(u64, usize)> {
+    let le = |size: usize| {
+        let value = bytes.get(1..1 + size)?;
+        Some((
+            value
+                .iter()
+                .rev()
+                .fold(0u64, |acc, &b| (acc << 8) | b as u64),
+            1 + size,
+        ))
+    };
+    match *bytes.first()? {
+        ZERO_OP => Some((0, 1)),
+        ONE_OP => Some((1, 1)),
+        ONES_OP => Some((u64::MAX, 1)),
+        BYTE_PREFIX => le(1),
+        WORD_PREFIX => le(2),
+        DWORD_PREFIX => le(4),
+        QWORD_PREFIX => le(8),
+        _ => None,
+    }
+}
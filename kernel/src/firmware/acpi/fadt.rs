@@ -0,0 +1,127 @@
+// kernel/src/firmware/acpi/fadt.rs
+//
+// Fixed ACPI Description Table ("FACP"): where the DSDT is, the power
+// management register blocks, the reset register and boot architecture
+// flags. The table grew with each ACPI revision, so fields past the end of
+// an older, shorter table read as absent. Each register block has a 32-bit
+// I/O port form and, from ACPI 2.0, a Generic Address form that wins when
+// it's filled in.
+
+use super::{read_u16, read_u32, read_u64, read_u8, GenericAddress, SPACE_SYSTEM_IO};
+
+pub const SIGNATURE: &[u8; 4] = b"FACP";
+
+// Fixed feature flags
+pub const WBINVD: u32 = 1 << 0;
+pub const RESET_REG_SUP: u32 = 1 << 10;
+pub const HW_REDUCED_ACPI: u32 = 1 << 20;
+
+// IA-PC boot architecture flags
+pub const LEGACY_DEVICES: u16 = 1 << 0;
+pub const HAS_8042: u16 = 1 << 1;
+pub const VGA_NOT_PRESENT: u16 = 1 << 2;
+pub const MSI_NOT_SUPPORTED: u16 = 1 << 3;
+pub const CMOS_RTC_NOT_PRESENT: u16 = 1 << 5;
+
+// ARM boot architecture flags
+pub const PSCI_COMPLIANT: u16 = 1 << 0;
+pub const PSCI_USE_HVC: u16 = 1 << 1;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Fadt {
+    pub firmware_ctrl: u64,
+    pub dsdt: u64,
+    pub sci_interrupt: u16,
+    // Port to write acpi_enable to, to hand power management from SMM to
+    // the OS. 0 if the system is always in ACPI mode.
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_event: Option<GenericAddress>,
+    pub pm1b_event: Option<GenericAddress>,
+    pub pm1a_control: Option<GenericAddress>,
+    pub pm1b_control: Option<GenericAddress>,
+    pub pm2_control: Option<GenericAddress>,
+    pub pm_timer: Option<GenericAddress>,
+    pub gpe0: Option<GenericAddress>,
+    pub gpe1: Option<GenericAddress>,
+    // CMOS RAM index of the RTC's century, 0 if there is none
+    pub century: u8,
+    pub iapc_boot_arch: u16,
+    pub flags: u32,
+    // Valid when flags has RESET_REG_SUP
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+    pub arm_boot_arch: u16,
+    // Hardware-reduced ACPI's replacements for the PM1 control and status
+    pub sleep_control: Option<GenericAddress>,
+    pub sleep_status: Option<GenericAddress>,
+}
+
+impl Fadt {
+    pub fn parse(table: &[u8]) -> Option<Fadt> {
+        if table.get(..4)? != SIGNATURE {
+            return None;
+        }
+        let u8_at = |offset| read_u8(table, offset).unwrap_or(0);
+        let u16_at = |offset| read_u16(table, offset).unwrap_or(0);
+        let u32_at = |offset| read_u32(table, offset).unwrap_or(0);
+        let u64_at = |offset| read_u64(table, offset).unwrap_or(0);
+        let address_at =
+            |offset| GenericAddress::parse(table, offset).filter(|gas| gas.address != 0);
+        // The extended form if present, else the legacy port and length
+        let block = |extended: usize, port: usize, length: usize| {
+            address_at(extended).or_else(|| io_block(u32_at(port), u8_at(length)))
+        };
+
+        Some(Fadt {
+            firmware_ctrl: match u64_at(132) {
+                0 => u32_at(36) as u64,
+                address => address,
+            },
+            dsdt: match u64_at(140) {
+                0 => u32_at(40) as u64,
+                address => address,
+            },
+            sci_interrupt: u16_at(46),
+            smi_command: u32_at(48),
+            acpi_enable: u8_at(52),
+            acpi_disable: u8_at(53),
+            pm1a_event: block(148, 56, 88),
+            pm1b_event: block(160, 60, 88),
+            pm1a_control: block(172, 64, 89),
+            pm1b_control: block(184, 68, 89),
+            pm2_control: block(196, 72, 90),
+            pm_timer: block(208, 76, 91),
+            gpe0: block(220, 80, 92),
+            gpe1: block(232, 84, 93),
+            century: u8_at(108),
+            iapc_boot_arch: u16_at(109),
+            flags: u32_at(112),
+            reset_register: address_at(116),
+            reset_value: u8_at(128),
+            arm_boot_arch: u16_at(129),
+            sleep_control: address_at(244),
+            sleep_status: address_at(256),
+        })
+    }
+
+    pub fn is_hardware_reduced(&self) -> bool {
+        self.flags & HW_REDUCED_ACPI != 0
+    }
+
+    pub fn reset_supported(&self) -> bool {
+        self.flags & RESET_REG_SUP != 0 && self.reset_register.is_some()
+    }
+}
+
+// A legacy register block: `length` bytes of I/O ports at `port`
+fn io_block(port: u32, length: u8) -> Option<GenericAddress> {
+    (port != 0).then_some(GenericAddress {
+        space: SPACE_SYSTEM_IO,
+        bit_width: length.saturating_mul(8),
+        bit_offset: 0,
+        access_size: 0,
+        address: port as u64,
+    })
+}
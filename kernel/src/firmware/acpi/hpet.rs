@@ -0,0 +1,32 @@
+// kernel/src/firmware/acpi/hpet.rs
+//
+// HPET description table ("HPET"): where the first event timer block's
+// registers are.
+
+use super::{read_u16, read_u32, read_u8, GenericAddress, HEADER_SIZE};
+
+pub const SIGNATURE: &[u8; 4] = b"HPET";
+
+#[derive(Clone, Copy, Debug)]
+pub struct Hpet {
+    // Copy of the block's capabilities register, low half
+    pub event_timer_block_id: u32,
+    pub base: GenericAddress,
+    pub number: u8,
+    // Smallest periodic tick the block handles without losing interrupts
+    pub minimum_tick: u16,
+}
+
+impl Hpet {
+    pub fn parse(table: &[u8]) -> Option<Hpet> {
+        if table.get(..4)? != SIGNATURE {
+            return None;
+        }
+        Some(Hpet {
+            event_timer_block_id: read_u32(table, HEADER_SIZE)?,
+            base: GenericAddress::parse(table, HEADER_SIZE + 4)?,
+            number: read_u8(table, HEADER_SIZE + 16)?,
+            minimum_tick: read_u16(table, HEADER_SIZE + 17)?,
+        })
+    }
+}
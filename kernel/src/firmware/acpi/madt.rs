@@ -0,0 +1,210 @@
+// kernel/src/firmware/acpi/madt.rs
+//
+// Multiple APIC Description Table ("APIC"): the interrupt controllers and
+// the processors attached to them, as a list of variable-length entries.
+
+use super::{read_u16, read_u32, read_u64, read_u8, HEADER_SIZE};
+
+pub const SIGNATURE: &[u8; 4] = b"APIC";
+
+// MADT flags: the system also has 8259 PICs, which must be masked
+pub const PCAT_COMPAT: u32 = 1 << 0;
+
+// Local APIC and GICC flags
+pub const LAPIC_ENABLED: u32 = 1 << 0;
+pub const LAPIC_ONLINE_CAPABLE: u32 = 1 << 1;
+
+// MPS INTI flags of overrides and NMI entries
+pub const POLARITY_MASK: u16 = 0b11;
+pub const POLARITY_ACTIVE_HIGH: u16 = 0b01;
+pub const POLARITY_ACTIVE_LOW: u16 = 0b11;
+pub const TRIGGER_MASK: u16 = 0b11 << 2;
+pub const TRIGGER_EDGE: u16 = 0b01 << 2;
+pub const TRIGGER_LEVEL: u16 = 0b11 << 2;
+
+// Processor UID meaning every processor in NMI entries (0xFF in the
+// 8-bit local APIC form)
+pub const ALL_PROCESSORS: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Entry {
+    LocalApic {
+        processor_uid: u8,
+        apic_id: u8,
+        flags: u32,
+    },
+    IoApic {
+        id: u8,
+        address: u32,
+        gsi_base: u32,
+    },
+    // ISA IRQ `source` is wired to `gsi` instead of the identity mapping
+    InterruptOverride {
+        bus: u8,
+        source: u8,
+        gsi: u32,
+        flags: u16,
+    },
+    NmiSource {
+        flags: u16,
+        gsi: u32,
+    },
+    LocalApicNmi {
+        processor_uid: u32,
+        flags: u16,
+        lint: u8,
+    },
+    LocalApicAddressOverride {
+        address: u64,
+    },
+    LocalX2Apic {
+        x2apic_id: u32,
+        flags: u32,
+        processor_uid: u32,
+    },
+    // GIC CPU interface. `base` is the GICv2 CPU interface, `gicr_base` the
+    // CPU's GICv3 redistributor when they aren't described as ranges.
+    Gicc {
+        cpu_interface: u32,
+        processor_uid: u32,
+        flags: u32,
+        base: u64,
+        gicr_base: u64,
+        mpidr: u64,
+    },
+    Gicd {
+        id: u32,
+        base: u64,
+        // 0 when the firmware leaves it to the GIC's ID registers
+        version: u8,
+    },
+    GicRedistributor {
+        base: u64,
+        length: u32,
+    },
+    // Anything this kernel has no use for yet
+    Other {
+        kind: u8,
+    },
+}
+
+pub struct Madt<'a> {
+    table: &'a [u8],
+}
+
+impl<'a> Madt<'a> {
+    pub fn parse(table: &'a [u8]) -> Option<Madt<'a>> {
+        if table.get(..4)? != SIGNATURE || table.len() < HEADER_SIZE + 8 {
+            return None;
+        }
+        Some(Madt { table })
+    }
+
+    // Physical address of the local APIC registers, as overridden by a
+    // 64-bit address entry if there is one
+    pub fn local_apic_address(&self) -> u64 {
+        self.entries()
+            .find_map(|entry| match entry {
+                Entry::LocalApicAddressOverride { address } => Some(address),
+                _ => None,
+            })
+            .unwrap_or(read_u32(self.table, HEADER_SIZE).unwrap_or(0) as u64)
+    }
+
+    pub fn flags(&self) -> u32 {
+        read_u32(self.table, HEADER_SIZE + 4).unwrap_or(0)
+    }
+
+    pub fn entries(&self) -> Entries<'a> {
+        Entries {
+            bytes: &self.table[HEADER_SIZE + 8..],
+        }
+    }
+}
+
+pub struct Entries<'a> {
+    bytes: &'a [u8],
+}
+
+impl Iterator for Entries<'_> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        let kind = read_u8(self.bytes, 0)?;
+        let length = read_u8(self.bytes, 1)? as usize;
+        // A zero length would loop forever, a long one runs off the table
+        if length < 2 || length > self.bytes.len() {
+            self.bytes = &[];
+            return None;
+        }
+
+        let entry = &self.bytes[..length];
+        self.bytes = &self.bytes[length..];
+        Some(parse_entry(kind, entry).unwrap_or(Entry::Other { kind }))
+    }
+}
+
+// None when the entry is too short for its type
+fn parse_entry(kind: u8, entry: &[u8]) -> Option<Entry> {
+    Some(match kind {
+        0 => Entry::LocalApic {
+            processor_uid: read_u8(entry, 2)?,
+            apic_id: read_u8(entry, 3)?,
+            flags: read_u32(entry, 4)?,
+        },
+        1 => Entry::IoApic {
+            id: read_u8(entry, 2)?,
+            address: read_u32(entry, 4)?,
+            gsi_base: read_u32(entry, 8)?,
+        },
+        2 => Entry::InterruptOverride {
+            bus: read_u8(entry, 2)?,
+            source: read_u8(entry, 3)?,
+            gsi: read_u32(entry, 4)?,
+            flags: read_u16(entry, 8)?,
+        },
+        3 => Entry::NmiSource {
+            flags: read_u16(entry, 2)?,
+            gsi: read_u32(entry, 4)?,
+        },
+        4 => Entry::LocalApicNmi {
+            processor_uid: match read_u8(entry, 2)? {
+                0xFF => ALL_PROCESSORS,
+                uid => uid as u32,
+            },
+            flags: read_u16(entry, 3)?,
+            lint: read_u8(entry, 5)?,
+        },
+        5 => Entry::LocalApicAddressOverride {
+            address: read_u64(entry, 4)?,
+        },
+        9 => Entry::LocalX2Apic {
+            x2apic_id: read_u32(entry, 4)?,
+            flags: read_u32(entry, 8)?,
+            processor_uid: read_u32(entry, 12)?,
+        },
+        0x0A => Entry::LocalApicNmi {
+            processor_uid: read_u32(entry, 4)?,
+            flags: read_u16(entry, 2)?,
+            lint: read_u8(entry, 8)?,
+        },
+        0x0B => Entry::Gicc {
+            cpu_interface: read_u32(entry, 4)?,
+            processor_uid: read_u32(entry, 8)?,
+            flags: read_u32(entry, 12)?,
+            base: read_u64(entry, 32)?,
+            gicr_base: read_u64(entry, 60)?,
+            mpidr: read_u64(entry, 68)?,
+        },
+        0x0C => Entry::Gicd {
+            id: read_u32(entry, 4)?,
+            base: read_u64(entry, 8)?,
+            version: read_u8(entry, 20)?,
+        },
+        0x0E => Entry::GicRedistributor {
+            base: read_u64(entry, 4)?,
+            length: read_u32(entry, 12)?,
+        },
+        _ => return None,
+    })
+}
@@ -0,0 +1,68 @@
+// kernel/src/firmware/acpi/mcfg.rs
+//
+// PCI Express memory-mapped configuration table ("MCFG"): the ECAM
+// windows, each covering a range of buses in one PCI segment group.
+
+use super::{read_u16, read_u64, read_u8, HEADER_SIZE};
+
+pub const SIGNATURE: &[u8; 4] = b"MCFG";
+
+// Header, then 8 reserved bytes
+const ENTRIES_OFFSET: usize = HEADER_SIZE + 8;
+const ENTRY_SIZE: usize = 16;
+
+// Each bus takes 32 devices * 8 functions * 4 KiB of configuration space
+pub const BUS_SIZE: u64 = 1 << 20;
+
+#[derive(Clone, Copy, Debug)]
+pub struct EcamWindow {
+    // Address of bus 0's configuration space, even when start_bus isn't 0
+    pub base: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl EcamWindow {
+    // Configuration space of `bus`/`device`/`function`, None if the bus
+    // isn't in this window
+    pub fn address(&self, bus: u8, device: u8, function: u8) -> Option<u64> {
+        if bus < self.start_bus || bus > self.end_bus || device >= 32 || function >= 8 {
+            return None;
+        }
+        Some(self.base + ((bus as u64) << 20) + ((device as u64) << 15) + ((function as u64) << 12))
+    }
+
+    // The mapped part of the window, from start_bus to end_bus
+    pub fn range(&self) -> (u64, u64) {
+        let start = self.base + self.start_bus as u64 * BUS_SIZE;
+        let buses = (self.end_bus as u64 + 1).saturating_sub(self.start_bus as u64);
+        (start, buses * BUS_SIZE)
+    }
+}
+
+pub struct Mcfg<'a> {
+    table: &'a [u8],
+}
+
+impl<'a> Mcfg<'a> {
+    pub fn parse(table: &'a [u8]) -> Option<Mcfg<'a>> {
+        if table.get(..4)? != SIGNATURE || table.len() < ENTRIES_OFFSET {
+            return None;
+        }
+        Some(Mcfg { table })
+    }
+
+    pub fn windows(&self) -> impl Iterator<Item = EcamWindow> + 'a {
+        self.table[ENTRIES_OFFSET..]
+            .chunks_exact(ENTRY_SIZE)
+            .filter_map(|entry| {
+                Some(EcamWindow {
+                    base: read_u64(entry, 0)?,
+                    segment: read_u16(entry, 8)?,
+                    start_bus: read_u8(entry, 10)?,
+                    end_bus: read_u8(entry, 11)?,
+                })
+            })
+    }
+}
@@ -0,0 +1,127 @@
+// kernel/src/firmware/acpi/mod.rs
+//
+// ACPI tables as bytes. The RSDP leads to the XSDT (or the RSDT on ACPI
+// 1.0), whose entries point at every other table; walk() follows them
+// through a function that finds a table's bytes from its address, and the
+// parsers for individual tables work on the slices it hands out. None of
+// it touches memory itself, so the same code runs over the firmware's
+// tables in the kernel and over the dumps in testdata/acpi on the host.
+
+pub mod aml;
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+pub mod spcr;
+pub mod srat;
+
+#[cfg(test)]
+mod tests;
+
+// Signature, length, revision, checksum, OEM IDs and creator
+pub const HEADER_SIZE: usize = 36;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+// The ACPI 1.0 part covered by the first checksum
+const RSDP_V1_SIZE: usize = 20;
+pub const RSDP_V2_SIZE: usize = 36;
+
+// The tables the root table behind `rsdp` lists, looked up with `table_at`,
+// skipping any that fail validate()
+pub fn walk<'a, F>(rsdp: &[u8], table_at: F) -> impl Iterator<Item = &'a [u8]>
+where
+    F: Fn(u64) -> Option<&'a [u8]>,
+{
+    let (root, entry_size) = parse_rsdp(rsdp)
+        .and_then(|(root, entry_size)| Some((validate(table_at(root)?)?, entry_size)))
+        .unwrap_or((&[], 8));
+    root[HEADER_SIZE.min(root.len())..]
+        .chunks_exact(entry_size)
+        .filter_map(move |entry| {
+            let addr = match entry_size {
+                8 => read_u64(entry, 0)?,
+                _ => read_u32(entry, 0)? as u64,
+            };
+            validate(table_at(addr)?)
+        })
+}
+
+// The root table's address and the size of its entries. ACPI 2.0+ has a
+// 64-bit XSDT, ACPI 1.0 only the RSDT.
+pub fn parse_rsdp(rsdp: &[u8]) -> Option<(u64, usize)> {
+    if rsdp.get(..8)? != RSDP_SIGNATURE || !checksum_ok(rsdp.get(..RSDP_V1_SIZE)?) {
+        return None;
+    }
+
+    let revision = read_u8(rsdp, 15)?;
+    let extended = rsdp.get(..RSDP_V2_SIZE).filter(|rsdp| checksum_ok(rsdp));
+    match extended {
+        Some(rsdp) if revision >= 2 => Some((read_u64(rsdp, 24)?, 8)),
+        _ => Some((read_u32(rsdp, 16)? as u64, 4)),
+    }
+}
+
+// `table` cut to the length its header gives, None if that's too short or
+// long or the checksum is wrong
+pub fn validate(table: &[u8]) -> Option<&[u8]> {
+    let length = read_u32(table, 4)? as usize;
+    if length < HEADER_SIZE {
+        return None;
+    }
+    let table = table.get(..length)?;
+    checksum_ok(table).then_some(table)
+}
+
+// Generic Address Structure address spaces
+pub const SPACE_SYSTEM_MEMORY: u8 = 0;
+pub const SPACE_SYSTEM_IO: u8 = 1;
+
+// Generic Address Structure: a register in some address space
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub const SIZE: usize = 12;
+
+    pub fn parse(bytes: &[u8], offset: usize) -> Option<GenericAddress> {
+        Some(GenericAddress {
+            space: read_u8(bytes, offset)?,
+            bit_width: read_u8(bytes, offset + 1)?,
+            bit_offset: read_u8(bytes, offset + 2)?,
+            access_size: read_u8(bytes, offset + 3)?,
+            address: read_u64(bytes, offset + 4)?,
+        })
+    }
+}
+
+// Every checksummed ACPI structure sums to zero
+pub fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+// Little-endian fields, None past the end of `bytes`
+fn read<const N: usize>(bytes: &[u8], offset: usize) -> Option<[u8; N]> {
+    bytes.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+pub fn read_u8(bytes: &[u8], offset: usize) -> Option<u8> {
+    bytes.get(offset).copied()
+}
+
+pub fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    read(bytes, offset).map(u16::from_le_bytes)
+}
+
+pub fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    read(bytes, offset).map(u32::from_le_bytes)
+}
+
+pub fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    read(bytes, offset).map(u64::from_le_bytes)
+}
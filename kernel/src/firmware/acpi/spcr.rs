@@ -0,0 +1,70 @@
+// kernel/src/firmware/acpi/spcr.rs
+//
+// Serial Port Console Redirection table ("SPCR"): the UART the firmware
+// used as its console, and how it was set up.
+
+use super::{read_u16, read_u32, read_u8, GenericAddress, HEADER_SIZE};
+
+pub const SIGNATURE: &[u8; 4] = b"SPCR";
+
+// Interface types, from the Debug Port Table 2 specification
+pub const INTERFACE_16550: u8 = 0x00;
+pub const INTERFACE_16450: u8 = 0x01;
+pub const INTERFACE_PL011: u8 = 0x03;
+pub const INTERFACE_SBSA_32BIT: u8 = 0x0D;
+pub const INTERFACE_SBSA: u8 = 0x0E;
+pub const INTERFACE_BCM2835: u8 = 0x10;
+pub const INTERFACE_16550_GAS: u8 = 0x12;
+
+// Interrupt type bits
+pub const INTERRUPT_PIC: u8 = 1 << 0;
+pub const INTERRUPT_IO_APIC: u8 = 1 << 1;
+pub const INTERRUPT_IO_SAPIC: u8 = 1 << 2;
+pub const INTERRUPT_GIC: u8 = 1 << 3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Spcr {
+    pub interface_type: u8,
+    pub base: GenericAddress,
+    pub interrupt_type: u8,
+    // ISA IRQ, when interrupt_type has INTERRUPT_PIC
+    pub irq: u8,
+    pub gsi: u32,
+    // Bits per second, None when the firmware left the rate as it was
+    pub baud_rate: Option<u32>,
+    pub parity: u8,
+    pub stop_bits: u8,
+    pub flow_control: u8,
+    pub terminal_type: u8,
+    // 0xFFFF when the UART isn't a PCI device
+    pub pci_device_id: u16,
+    pub pci_vendor_id: u16,
+}
+
+impl Spcr {
+    pub fn parse(table: &[u8]) -> Option<Spcr> {
+        if table.get(..4)? != SIGNATURE {
+            return None;
+        }
+        Some(Spcr {
+            interface_type: read_u8(table, HEADER_SIZE)?,
+            base: GenericAddress::parse(table, HEADER_SIZE + 4)?,
+            interrupt_type: read_u8(table, HEADER_SIZE + 16)?,
+            irq: read_u8(table, HEADER_SIZE + 17)?,
+            gsi: read_u32(table, HEADER_SIZE + 18)?,
+            baud_rate: match read_u8(table, HEADER_SIZE + 22)? {
+                3 => Some(9600),
+                4 => Some(19200),
+                6 => Some(57600),
+                7 => Some(115200),
+                _ => None,
+            },
+            parity: read_u8(table, HEADER_SIZE + 23)?,
+            stop_bits: read_u8(table, HEADER_SIZE + 24)?,
+            flow_control: read_u8(table, HEADER_SIZE + 25)?,
+            terminal_type: read_u8(table, HEADER_SIZE + 26)?,
+            pci_device_id: read_u16(table, HEADER_SIZE + 28).unwrap_or(0xFFFF),
+            pci_vendor_id: read_u16(table, HEADER_SIZE + 30).unwrap_or(0xFFFF),
+        })
+    }
+}
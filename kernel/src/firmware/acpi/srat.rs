@@ -0,0 +1,115 @@
+// kernel/src/firmware/acpi/srat.rs
+//
+// System Resource Affinity Table ("SRAT"): which NUMA proximity domain
+// each processor and memory range belongs to, as a list of variable-length
+// entries like the MADT's.
+
+use super::{read_u32, read_u64, read_u8, HEADER_SIZE};
+
+pub const SIGNATURE: &[u8; 4] = b"SRAT";
+
+// Header, then a reserved u32 (1) and u64
+const ENTRIES_OFFSET: usize = HEADER_SIZE + 12;
+
+// Flags of every entry type
+pub const ENABLED: u32 = 1 << 0;
+// Memory affinity flags
+pub const HOT_PLUGGABLE: u32 = 1 << 1;
+pub const NON_VOLATILE: u32 = 1 << 2;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Entry {
+    LocalApic {
+        apic_id: u8,
+        proximity_domain: u32,
+        flags: u32,
+    },
+    Memory {
+        base: u64,
+        length: u64,
+        proximity_domain: u32,
+        flags: u32,
+    },
+    LocalX2Apic {
+        x2apic_id: u32,
+        proximity_domain: u32,
+        flags: u32,
+    },
+    // Matches the MADT GICC entry with the same processor UID
+    Gicc {
+        processor_uid: u32,
+        proximity_domain: u32,
+        flags: u32,
+    },
+    Other {
+        kind: u8,
+    },
+}
+
+pub struct Srat<'a> {
+    table: &'a [u8],
+}
+
+impl<'a> Srat<'a> {
+    pub fn parse(table: &'a [u8]) -> Option<Srat<'a>> {
+        if table.get(..4)? != SIGNATURE || table.len() < ENTRIES_OFFSET {
+            return None;
+        }
+        Some(Srat { table })
+    }
+
+    pub fn entries(&self) -> Entries<'a> {
+        Entries {
+            bytes: &self.table[ENTRIES_OFFSET..],
+        }
+    }
+}
+
+pub struct Entries<'a> {
+    bytes: &'a [u8],
+}
+
+impl Iterator for Entries<'_> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        let kind = read_u8(self.bytes, 0)?;
+        let length = read_u8(self.bytes, 1)? as usize;
+        if length < 2 || length > self.bytes.len() {
+            self.bytes = &[];
+            return None;
+        }
+
+        let entry = &self.bytes[..length];
+        self.bytes = &self.bytes[length..];
+        Some(parse_entry(kind, entry).unwrap_or(Entry::Other { kind }))
+    }
+}
+
+fn parse_entry(kind: u8, entry: &[u8]) -> Option<Entry> {
+    Some(match kind {
+        // The domain is split: low byte at 2, the upper three at 9
+        0 => Entry::LocalApic {
+            apic_id: read_u8(entry, 3)?,
+            proximity_domain: read_u8(entry, 2)? as u32 | (read_u32(entry, 8)? & !0xFF),
+            flags: read_u32(entry, 4)?,
+        },
+        1 => Entry::Memory {
+            proximity_domain: read_u32(entry, 2)?,
+            base: read_u64(entry, 8)?,
+            length: read_u64(entry, 16)?,
+            flags: read_u32(entry, 28)?,
+        },
+        2 => Entry::LocalX2Apic {
+            proximity_domain: read_u32(entry, 4)?,
+            x2apic_id: read_u32(entry, 8)?,
+            flags: read_u32(entry, 12)?,
+        },
+        3 => Entry::Gicc {
+            proximity_domain: read_u32(entry, 2)?,
+            processor_uid: read_u32(entry, 6)?,
+            flags: read_u32(entry, 10)?,
+        },
+        _ => return None,
+    })
+}
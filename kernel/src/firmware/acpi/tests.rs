@@ -0,0 +1,237 @@
+// kernel/src/firmware/acpi/tests.rs
+//
+// The walk and the table parsers over the Firecracker tables in
+// testdata/acpi (see the README there). The root tables aren't in the dump,
+// so root() builds an RSDP and an XSDT or RSDT around them.
+
+use super::*;
+
+macro_rules! dat {
+    ($path:literal) => {
+        include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/testdata/acpi/firecracker/",
+            $path
+        ))
+    };
+}
+
+const FACP: &[u8] = dat!("FACP.dat");
+const APIC: &[u8] = dat!("APIC.dat");
+const MCFG: &[u8] = dat!("MCFG.dat");
+const DSDT: &[u8] = dat!("DSDT.dat");
+
+const ROOT_ADDRESS: u64 = 0x1000;
+
+// Where the root table says the tables are
+const TABLES: &[(u64, &[u8])] = &[(0x2000, FACP), (0x3000, APIC), (0x4000, MCFG)];
+
+fn set_checksum(bytes: &mut [u8], offset: usize) {
+    bytes[offset] = 0;
+    let sum = bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    bytes[offset] = sum.wrapping_neg();
+}
+
+// An ACPI 2.0 RSDP and XSDT listing `tables`, or with `xsdt` false an ACPI
+// 1.0 RSDP and RSDT
+fn root(tables: &[(u64, &[u8])], xsdt: bool) -> (Vec<u8>, Vec<u8>) {
+    let entry_size = if xsdt { 8 } else { 4 };
+    let mut root = Vec::new();
+    root.extend_from_slice(if xsdt { b"XSDT" } else { b"RSDT" });
+    let length = HEADER_SIZE + tables.len() * entry_size;
+    root.extend_from_slice(&(length as u32).to_le_bytes());
+    root.push(1);
+    root.push(0);
+    root.extend_from_slice(&APIC[10..HEADER_SIZE]);
+    for &(address, _) in tables {
+        root.extend_from_slice(&address.to_le_bytes()[..entry_size]);
+    }
+    set_checksum(&mut root, 9);
+
+    let mut rsdp = Vec::new();
+    rsdp.extend_from_slice(RSDP_SIGNATURE);
+    rsdp.push(0);
+    rsdp.extend_from_slice(&APIC[10..16]);
+    if xsdt {
+        rsdp.push(2);
+        rsdp.extend_from_slice(&[0; 4]);
+        rsdp.extend_from_slice(&(RSDP_V2_SIZE as u32).to_le_bytes());
+        rsdp.extend_from_slice(&ROOT_ADDRESS.to_le_bytes());
+        rsdp.extend_from_slice(&[0; 4]);
+        set_checksum(&mut rsdp[..RSDP_V1_SIZE], 8);
+        set_checksum(&mut rsdp, 32);
+    } else {
+        rsdp.push(0);
+        rsdp.extend_from_slice(&(ROOT_ADDRESS as u32).to_le_bytes());
+        set_checksum(&mut rsdp, 8);
+    }
+    (rsdp, root)
+}
+
+fn lookup<'a>(root: &'a [u8], tables: &[(u64, &'a [u8])], addr: u64) -> Option<&'a [u8]> {
+    if addr == ROOT_ADDRESS {
+        return Some(root);
+    }
+    tables
+        .iter()
+        .find(|&&(at, _)| at == addr)
+        .map(|&(_, table)| table)
+}
+
+fn signatures<'a>(tables: impl Iterator<Item = &'a [u8]>) -> Vec<&'a [u8]> {
+    tables.map(|table| &table[..4]).collect()
+}
+
+#[test]
+fn dumps_are_valid() {
+    for table in [FACP, APIC, MCFG, DSDT] {
+        assert_eq!(validate(table), Some(table));
+        assert_eq!(&table[10..16], b"FIRECK");
+    }
+}
+
+#[test]
+fn rsdp_revisions() {
+    let (rsdp, _) = root(TABLES, true);
+    assert_eq!(parse_rsdp(&rsdp), Some((ROOT_ADDRESS, 8)));
+    let (rsdp_v1, _) = root(TABLES, false);
+    assert_eq!(parse_rsdp(&rsdp_v1), Some((ROOT_ADDRESS, 4)));
+
+    // A bad extended checksum leaves the ACPI 1.0 part, and its RSDT
+    let mut rsdp = rsdp;
+    rsdp[32] ^= 0xFF;
+    assert_eq!(parse_rsdp(&rsdp), Some((0, 4)));
+    rsdp[8] ^= 0xFF;
+    assert_eq!(parse_rsdp(&rsdp), None);
+}
+
+#[test]
+fn walk_xsdt_and_rsdt() {
+    for xsdt in [true, false] {
+        let (rsdp, root) = root(TABLES, xsdt);
+        let walked = walk(&rsdp, |addr| lookup(&root, TABLES, addr));
+        assert_eq!(signatures(walked), [b"FACP", b"APIC", b"MCFG"]);
+    }
+    assert_eq!(walk(&[], |addr| lookup(&[], TABLES, addr)).count(), 0);
+}
+
+#[test]
+fn bad_checksum() {
+    let mut mcfg = MCFG.to_vec();
+    mcfg[HEADER_SIZE] ^= 1;
+    assert!(validate(&mcfg).is_none());
+
+    // The walk leaves it out and carries on
+    let tables = [(0x2000, FACP), (0x3000, APIC), (0x4000, &mcfg[..])];
+    let (rsdp, root) = root(&tables, true);
+    let walked = walk(&rsdp, |addr| lookup(&root, &tables, addr));
+    assert_eq!(signatures(walked), [b"FACP", b"APIC"]);
+}
+
+#[test]
+fn validate_length() {
+    // Cut to the header's length, which must fit
+    let mut longer = MCFG.to_vec();
+    longer.extend_from_slice(&[0xAA; 8]);
+    assert_eq!(validate(&longer), Some(MCFG));
+    assert_eq!(validate(&MCFG[..MCFG.len() - 1]), None);
+}
+
+#[test]
+fn fadt_firecracker() {
+    let fadt = fadt::Fadt::parse(FACP).unwrap();
+    // Only the 64-bit X_DSDT field is filled in
+    assert_eq!(fadt.dsdt, 0x9_FD30);
+    assert_eq!(fadt.firmware_ctrl, 0);
+    assert!(fadt.is_hardware_reduced());
+    assert_eq!(fadt.iapc_boot_arch, fadt::VGA_NOT_PRESENT);
+    assert_eq!(fadt.century, 0);
+
+    // Hardware-reduced, and without even the sleep registers
+    assert_eq!(fadt.sci_interrupt, 0);
+    assert_eq!(fadt.smi_command, 0);
+    assert!(fadt.pm1a_event.is_none());
+    assert!(fadt.pm1a_control.is_none());
+    assert!(fadt.pm_timer.is_none());
+    assert!(fadt.gpe0.is_none());
+    assert!(fadt.sleep_control.is_none());
+    assert!(!fadt.reset_supported());
+    assert!(fadt::Fadt::parse(APIC).is_none());
+}
+
+#[test]
+fn fadt_acpi_1() {
+    // Fields past the end of an older, shorter table read as absent
+    let fadt = fadt::Fadt::parse(&FACP[..116]).unwrap();
+    assert_eq!(fadt.dsdt, 0);
+    assert!(fadt.is_hardware_reduced());
+    assert_eq!(fadt.iapc_boot_arch, fadt::VGA_NOT_PRESENT);
+}
+
+#[test]
+fn madt_firecracker() {
+    let madt = madt::Madt::parse(APIC).unwrap();
+    assert_eq!(madt.local_apic_address(), 0xFEE0_0000);
+    // No 8259s to mask
+    assert_eq!(madt.flags() & madt::PCAT_COMPAT, 0);
+
+    let entries: Vec<madt::Entry> = madt.entries().collect();
+    assert_eq!(entries.len(), 2);
+    assert!(matches!(
+        entries[0],
+        madt::Entry::IoApic {
+            id: 0,
+            address: 0xFEC0_0000,
+            gsi_base: 0,
+        }
+    ));
+    let madt::Entry::LocalApic {
+        processor_uid,
+        apic_id,
+        flags,
+    } = entries[1]
+    else {
+        panic!("{:?} isn't a local APIC", entries[1]);
+    };
+    assert_eq!((processor_uid, apic_id), (0, 0));
+    assert_ne!(flags & madt::LAPIC_ENABLED, 0);
+}
+
+#[test]
+fn madt_bad_entry_length() {
+    // A zero length ends the entries rather than looping on them
+    let mut apic = APIC.to_vec();
+    apic[HEADER_SIZE + 8 + 12 + 1] = 0;
+    let madt = madt::Madt::parse(&apic).unwrap();
+    assert_eq!(madt.entries().count(), 1);
+}
+
+#[test]
+fn mcfg_firecracker() {
+    let mcfg = mcfg::Mcfg::parse(MCFG).unwrap();
+    let windows: Vec<mcfg::EcamWindow> = mcfg.windows().collect();
+    assert_eq!(windows.len(), 1);
+    let window = windows[0];
+    // One bus, segment 0
+    assert_eq!(
+        (window.segment, window.start_bus, window.end_bus),
+        (0, 0, 0)
+    );
+    assert_eq!(window.range(), (0xEEC0_0000, mcfg::BUS_SIZE));
+    assert_eq!(
+        window.address(0, 2, 3),
+        Some(0xEEC0_0000 + (2 << 15) + (3 << 12))
+    );
+    assert_eq!(window.address(1, 0, 0), None);
+    assert_eq!(window.address(0, 32, 0), None);
+    assert_eq!(window.address(0, 0, 8), None);
+    assert!(mcfg::Mcfg::parse(FACP).is_none());
+}
+
+#[test]
+fn dsdt_without_sleep_states() {
+    // Firecracker's DSDT has no sleep packages for the search to find
+    for state in 0..=5 {
+        assert!(aml::find_sleep_type(DSDT, state).is_none());
+    }
+}
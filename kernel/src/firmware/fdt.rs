@@ -0,0 +1,428 @@
+// kernel/src/firmware/fdt.rs
+//
+// Flattened device tree, read in place. Nodes are found by walking the
+// structure block in order; each Node remembers where its properties start,
+// the #address-cells/#size-cells its parent declared, which is what
+// decoding its `reg` takes, and the interrupt parent it inherits, which is
+// what decoding its `interrupts` takes. Fdt::new works on any byte slice,
+// so the blobs in testdata/fdt parse on the host just as the firmware's
+// does in the kernel.
+
+#[cfg(test)]
+mod tests;
+
+const MAGIC: u32 = 0xD00D_FEED;
+pub const HEADER_SIZE: usize = 40;
+// Oldest layout with the structure block size in the header
+const MIN_VERSION: u32 = 17;
+
+// Structure block tokens
+const BEGIN_NODE: u32 = 1;
+const END_NODE: u32 = 2;
+const PROP: u32 = 3;
+const NOP: u32 = 4;
+
+// Deeper nodes are ignored, real trees are a handful of levels
+const MAX_DEPTH: usize = 16;
+
+// What a node's children get when it doesn't say
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+const DEFAULT_SIZE_CELLS: u32 = 1;
+
+// Interrupt parent of a node that has none
+const NO_PHANDLE: u32 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    BadMagic,
+    UnsupportedVersion,
+    Truncated,
+}
+
+#[derive(Clone, Copy)]
+pub struct Fdt<'a> {
+    structure: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    pub fn new(blob: &'a [u8]) -> Result<Fdt<'a>, Error> {
+        let header = |offset| be32(blob, offset).ok_or(Error::Truncated);
+        let total_size = Fdt::total_size(blob)?;
+        let struct_offset = header(8)? as usize;
+        let strings_offset = header(12)? as usize;
+        let version = header(20)?;
+        let last_compatible_version = header(24)?;
+        let strings_size = header(32)? as usize;
+        let struct_size = header(36)? as usize;
+
+        if version < MIN_VERSION || last_compatible_version > MIN_VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+        let blob = blob.get(..total_size).ok_or(Error::Truncated)?;
+        let section = |offset: usize, size: usize| {
+            offset
+                .checked_add(size)
+                .and_then(|end| blob.get(offset..end))
+                .ok_or(Error::Truncated)
+        };
+        Ok(Fdt {
+            structure: section(struct_offset, struct_size)?,
+            strings: section(strings_offset, strings_size)?,
+        })
+    }
+
+    // The size of the whole blob, from the start of its header
+    pub fn total_size(header: &[u8]) -> Result<usize, Error> {
+        if be32(header, 0).ok_or(Error::Truncated)? != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        Ok(be32(header, 4).ok_or(Error::Truncated)? as usize)
+    }
+
+    // Every node, parents before their children
+    pub fn nodes(&self) -> Nodes<'a> {
+        Nodes {
+            fdt: *self,
+            offset: 0,
+            depth: 0,
+            cells: [(DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS); MAX_DEPTH + 1],
+            interrupt_parents: [NO_PHANDLE; MAX_DEPTH + 1],
+        }
+    }
+
+    // The first node compatible with any of `compatible`
+    pub fn find_compatible(&self, compatible: &[&str]) -> Option<Node<'a>> {
+        self.nodes()
+            .find(|node| compatible.iter().any(|name| node.is_compatible(name)))
+    }
+
+    // The node at an absolute path like "/cpus" or "/soc/uart@10000000".
+    // A component without a unit address matches any node of that name.
+    pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+        let mut components = [""; MAX_DEPTH];
+        let mut count = 0;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            *components.get_mut(count)? = component;
+            count += 1;
+        }
+
+        // How many components the node being walked matches, counted down
+        // again as the walk leaves it
+        let mut matched = 0;
+        for node in self.nodes() {
+            let level = node.depth - 1;
+            if level == 0 {
+                if count == 0 {
+                    return Some(node);
+                }
+                continue;
+            }
+            if level > matched + 1 {
+                continue;
+            }
+            matched = level - 1;
+            if name_matches(node.name, components[matched]) {
+                matched = level;
+                if matched == count {
+                    return Some(node);
+                }
+            }
+        }
+        None
+    }
+
+    // The node with `phandle`, which other nodes' properties refer to it by
+    pub fn find_phandle(&self, phandle: u32) -> Option<Node<'a>> {
+        if phandle == NO_PHANDLE {
+            return None;
+        }
+        self.nodes().find(|node| node.phandle() == Some(phandle))
+    }
+
+    fn token(&self, offset: usize) -> Option<u32> {
+        be32(self.structure, offset)
+    }
+
+    // NUL-terminated string at `offset` in `bytes`
+    fn string(bytes: &'a [u8], offset: usize) -> Option<&'a str> {
+        let bytes = bytes.get(offset..)?;
+        let len = bytes.iter().position(|&b| b == 0)?;
+        core::str::from_utf8(&bytes[..len]).ok()
+    }
+}
+
+pub struct Nodes<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    depth: usize,
+    // (#address-cells, #size-cells) declared by the open node at each depth
+    cells: [(u32, u32); MAX_DEPTH + 1],
+    // Interrupt parent of the open node at each depth, inherited by its
+    // children unless they name their own
+    interrupt_parents: [u32; MAX_DEPTH + 1],
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        loop {
+            match self.fdt.token(self.offset)? {
+                BEGIN_NODE => {
+                    let name = Fdt::string(self.fdt.structure, self.offset + 4)?;
+                    self.offset = align4(self.offset + 4 + name.len() + 1);
+                    self.depth += 1;
+                    if self.depth > MAX_DEPTH {
+                        return None;
+                    }
+
+                    let (address_cells, size_cells) = self.cells[self.depth - 1];
+                    let mut node = Node {
+                        fdt: self.fdt,
+                        name,
+                        properties: self.offset,
+                        depth: self.depth,
+                        address_cells,
+                        size_cells,
+                        interrupt_parent: self.interrupt_parents[self.depth - 1],
+                    };
+                    self.cells[self.depth] = (
+                        node.property_u32("#address-cells")
+                            .unwrap_or(DEFAULT_ADDRESS_CELLS),
+                        node.property_u32("#size-cells")
+                            .unwrap_or(DEFAULT_SIZE_CELLS),
+                    );
+                    if let Some(parent) = node.property_u32("interrupt-parent") {
+                        node.interrupt_parent = parent;
+                    }
+                    self.interrupt_parents[self.depth] = node.interrupt_parent;
+                    return Some(node);
+                }
+                END_NODE => {
+                    self.depth = self.depth.checked_sub(1)?;
+                    self.offset += 4;
+                }
+                PROP => {
+                    let len = be32(self.fdt.structure, self.offset + 4)? as usize;
+                    self.offset = align4(self.offset + 12 + len);
+                }
+                NOP => self.offset += 4,
+                // END, or garbage
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    // Offset of the first token after the node's name
+    properties: usize,
+    // 1 for the root
+    depth: usize,
+    // Cell counts from the parent, for `reg`
+    address_cells: u32,
+    size_cells: u32,
+    // Phandle of the controller its `interrupts` go to, NO_PHANDLE if none
+    interrupt_parent: u32,
+}
+
+impl<'a> Node<'a> {
+    // Including the unit address, e.g. "intc@8000000"; empty for the root
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn properties(&self) -> Properties<'a> {
+        Properties {
+            fdt: self.fdt,
+            offset: self.properties,
+        }
+    }
+
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.properties()
+            .find(|&(prop, _)| prop == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        be32(self.property(name)?, 0)
+    }
+
+    // The `compatible` strings, most specific first
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> + 'a {
+        self.property("compatible")
+            .unwrap_or(&[])
+            .split(|&b| b == 0)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| core::str::from_utf8(entry).ok())
+    }
+
+    pub fn is_compatible(&self, name: &str) -> bool {
+        self.compatible().any(|entry| entry == name)
+    }
+
+    // A missing status means the device is there
+    pub fn is_enabled(&self) -> bool {
+        match self.property("status") {
+            Some(status) => matches!(status, b"okay\0" | b"ok\0"),
+            None => true,
+        }
+    }
+
+    pub fn phandle(&self) -> Option<u32> {
+        self.property_u32("phandle")
+            .or_else(|| self.property_u32("linux,phandle"))
+    }
+
+    // Whether other nodes' interrupts can go to this one
+    pub fn is_interrupt_controller(&self) -> bool {
+        self.property("interrupt-controller").is_some()
+    }
+
+    // The node's interrupts, from `interrupts-extended` if it has one
+    // (each entry naming its controller) or else `interrupts` (all going to
+    // the inherited interrupt parent). What a specifier means is up to the
+    // controller's binding. Interrupt nexus nodes' `interrupt-map` isn't
+    // followed.
+    pub fn interrupts(&self) -> Interrupts<'a> {
+        if let Some(extended) = self.property("interrupts-extended") {
+            return Interrupts {
+                fdt: self.fdt,
+                bytes: extended,
+                parent: None,
+            };
+        }
+        let parent = self
+            .fdt
+            .find_phandle(self.interrupt_parent)
+            .and_then(|controller| Some((controller, controller.interrupt_cells()?)));
+        Interrupts {
+            fdt: self.fdt,
+            bytes: match parent {
+                Some(_) => self.property("interrupts").unwrap_or(&[]),
+                None => &[],
+            },
+            parent,
+        }
+    }
+
+    fn interrupt_cells(&self) -> Option<usize> {
+        match self.property_u32("#interrupt-cells")? {
+            0 => None,
+            cells => Some(cells as usize),
+        }
+    }
+
+    // (address, size) pairs of the `reg` property, in the parent's address
+    // space
+    pub fn reg(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let address_cells = self.address_cells as usize;
+        let size_cells = self.size_cells as usize;
+        let entry_size = (address_cells + size_cells).max(1) * 4;
+        self.property("reg")
+            .unwrap_or(&[])
+            .chunks_exact(entry_size)
+            .map(move |entry| {
+                let (address, size) = entry.split_at(address_cells * 4);
+                (read_cells(address), read_cells(size))
+            })
+    }
+}
+
+// One interrupt: the controller it goes to and the specifier, in cells
+// whose meaning the controller's binding defines
+#[derive(Clone, Copy)]
+pub struct Interrupt<'a> {
+    pub controller: Node<'a>,
+    specifier: &'a [u8],
+}
+
+impl Interrupt<'_> {
+    pub fn cell_count(&self) -> usize {
+        self.specifier.len() / 4
+    }
+
+    pub fn cell(&self, index: usize) -> Option<u32> {
+        be32(self.specifier, index.checked_mul(4)?)
+    }
+}
+
+pub struct Interrupts<'a> {
+    fdt: Fdt<'a>,
+    bytes: &'a [u8],
+    // The controller and its #interrupt-cells for plain `interrupts`, None
+    // for `interrupts-extended`
+    parent: Option<(Node<'a>, usize)>,
+}
+
+impl<'a> Iterator for Interrupts<'a> {
+    type Item = Interrupt<'a>;
+
+    fn next(&mut self) -> Option<Interrupt<'a>> {
+        let (controller, cells) = match self.parent {
+            Some(parent) => parent,
+            None => {
+                let controller = self.fdt.find_phandle(be32(self.bytes, 0)?)?;
+                self.bytes = &self.bytes[4..];
+                (controller, controller.interrupt_cells()?)
+            }
+        };
+        let (specifier, rest) = self.bytes.split_at_checked(cells.checked_mul(4)?)?;
+        self.bytes = rest;
+        Some(Interrupt {
+            controller,
+            specifier,
+        })
+    }
+}
+
+pub struct Properties<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+}
+
+impl<'a> Iterator for Properties<'a> {
+    type Item = (&'a str, &'a [u8]);
+
+    fn next(&mut self) -> Option<(&'a str, &'a [u8])> {
+        loop {
+            match self.fdt.token(self.offset)? {
+                PROP => {
+                    let len = be32(self.fdt.structure, self.offset + 4)? as usize;
+                    let name_offset = be32(self.fdt.structure, self.offset + 8)? as usize;
+                    let start = self.offset + 12;
+                    let value = self.fdt.structure.get(start..start + len)?;
+                    self.offset = align4(start + len);
+                    return Some((Fdt::string(self.fdt.strings, name_offset)?, value));
+                }
+                NOP => self.offset += 4,
+                // Properties come before a node's children
+                _ => return None,
+            }
+        }
+    }
+}
+
+// "uart" matches "uart@10000000", a full name only itself
+fn name_matches(name: &str, component: &str) -> bool {
+    name == component || (!component.contains('@') && name.split('@').next() == Some(component))
+}
+
+fn be32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+// Big-endian cells, at most two of which fit in a u64
+fn read_cells(cells: &[u8]) -> u64 {
+    cells.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64)
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
@@ -0,0 +1,229 @@
+// kernel/src/firmware/fdt/tests.rs
+//
+// The parser over the aarch64 and riscv64 virt trees in testdata/fdt.
+// mkdtb.py there writes them after QEMU's layout; they aren't dumps (see the
+// README there).
+
+use super::*;
+
+const AARCH64: &[u8] = include_bytes!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/testdata/fdt/aarch64-virt.dtb"
+));
+const RISCV64: &[u8] = include_bytes!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/testdata/fdt/riscv64-virt.dtb"
+));
+
+fn aarch64() -> Fdt<'static> {
+    Fdt::new(AARCH64).unwrap()
+}
+
+fn riscv64() -> Fdt<'static> {
+    Fdt::new(RISCV64).unwrap()
+}
+
+fn reg_of(node: Node) -> Vec<(u64, u64)> {
+    node.reg().collect()
+}
+
+// Each interrupt's controller and specifier cells
+fn interrupts_of<'a>(node: Node<'a>) -> Vec<(&'a str, Vec<u32>)> {
+    node.interrupts()
+        .map(|interrupt| {
+            let cells = (0..interrupt.cell_count())
+                .map(|i| interrupt.cell(i).unwrap())
+                .collect();
+            (interrupt.controller.name(), cells)
+        })
+        .collect()
+}
+
+#[test]
+fn header() {
+    assert_eq!(Fdt::total_size(AARCH64), Ok(AARCH64.len()));
+    assert_eq!(
+        Fdt::new(&AARCH64[..AARCH64.len() - 1]).err(),
+        Some(Error::Truncated)
+    );
+    assert_eq!(Fdt::new(&AARCH64[..2]).err(), Some(Error::Truncated));
+
+    let mut blob = AARCH64.to_vec();
+    blob[0] = 0;
+    assert_eq!(Fdt::new(&blob).err(), Some(Error::BadMagic));
+    let mut blob = AARCH64.to_vec();
+    blob[23] = 16;
+    assert_eq!(Fdt::new(&blob).err(), Some(Error::UnsupportedVersion));
+}
+
+#[test]
+fn find_node() {
+    let fdt = riscv64();
+    assert_eq!(fdt.find_node("/").unwrap().name(), "");
+    assert_eq!(fdt.find_node("/cpus").unwrap().name(), "cpus");
+    assert_eq!(fdt.find_node("/cpus/cpu@1").unwrap().name(), "cpu@1");
+    assert_eq!(
+        fdt.find_node("/cpus/cpu@1/interrupt-controller")
+            .unwrap()
+            .phandle(),
+        Some(5)
+    );
+    // A name without its unit address matches the first of that name
+    assert_eq!(
+        fdt.find_node("/soc/serial").unwrap().name(),
+        "serial@10000000"
+    );
+    assert_eq!(fdt.find_node("/cpus/cpu").unwrap().name(), "cpu@0");
+    assert_eq!(
+        fdt.find_node("soc/plic@c000000").unwrap().name(),
+        "plic@c000000"
+    );
+
+    assert!(fdt.find_node("/soc/serial@10000001").is_none());
+    // Only at the path given, not anywhere below it
+    assert!(fdt.find_node("/serial@10000000").is_none());
+    assert!(fdt.find_node("/cpus/interrupt-controller").is_none());
+    assert!(fdt.find_node("/soc/serial@10000000/x").is_none());
+
+    // After a sibling subtree with a deeper node of the same name
+    let fdt = aarch64();
+    assert_eq!(fdt.find_node("/intc/v2m").unwrap().name(), "v2m@8020000");
+    assert_eq!(fdt.find_node("/timer").unwrap().name(), "timer");
+    assert_eq!(fdt.find_node("/chosen").unwrap().name(), "chosen");
+}
+
+#[test]
+fn find_compatible_and_phandle() {
+    let fdt = riscv64();
+    // Nested under /soc, not just the root's children
+    let serial = fdt.find_compatible(&["ns16550a"]).unwrap();
+    assert_eq!(serial.name(), "serial@10000000");
+    assert_eq!(serial.property_u32("clock-frequency"), Some(0x38_4000));
+
+    let test = fdt.find_compatible(&["sifive,test0"]).unwrap();
+    assert!(test.is_compatible("syscon"));
+    assert_eq!(
+        test.compatible().collect::<Vec<_>>(),
+        ["sifive,test1", "sifive,test0", "syscon"]
+    );
+    assert_eq!(fdt.find_phandle(4).unwrap().name(), "test@100000");
+    assert!(fdt.find_phandle(NO_PHANDLE).is_none());
+    assert!(fdt.find_phandle(0x1234).is_none());
+    assert!(fdt.find_compatible(&["arm,pl011"]).is_none());
+
+    let fdt = aarch64();
+    let timer = fdt
+        .find_compatible(&["arm,armv8-timer", "arm,armv7-timer"])
+        .unwrap();
+    assert_eq!(timer.name(), "timer");
+    assert!(timer.property("always-on").is_some());
+    assert!(fdt.find_phandle(0x8001).unwrap().is_interrupt_controller());
+}
+
+#[test]
+fn reg() {
+    let fdt = aarch64();
+    assert_eq!(
+        reg_of(fdt.find_node("/memory").unwrap()),
+        [(0x4000_0000, 0x2000_0000)]
+    );
+    // Distributor, then CPU interface
+    assert_eq!(
+        reg_of(fdt.find_node("/intc").unwrap()),
+        [(0x800_0000, 0x1_0000), (0x801_0000, 0x1_0000)]
+    );
+    assert_eq!(
+        reg_of(fdt.find_node("/intc/v2m").unwrap()),
+        [(0x802_0000, 0x1000)]
+    );
+    // One address cell and no size cells under /cpus
+    assert_eq!(reg_of(fdt.find_node("/cpus/cpu@1").unwrap()), [(1, 0)]);
+    assert!(reg_of(fdt.find_node("/timer").unwrap()).is_empty());
+
+    let fdt = riscv64();
+    assert_eq!(
+        reg_of(fdt.find_node("/soc/serial").unwrap()),
+        [(0x1000_0000, 0x100)]
+    );
+    assert_eq!(
+        reg_of(fdt.find_node("/soc/plic").unwrap()),
+        [(0xC00_0000, 0x60_0000)]
+    );
+}
+
+#[test]
+fn interrupts_inherited() {
+    let fdt = aarch64();
+    // The root's interrupt-parent, the GIC with three cells
+    assert_eq!(
+        interrupts_of(fdt.find_node("/pl011").unwrap()),
+        [("intc@8000000", vec![0, 1, 4])]
+    );
+    let timer = interrupts_of(fdt.find_node("/timer").unwrap());
+    assert_eq!(timer.len(), 4);
+    assert_eq!(timer[1], ("intc@8000000", vec![1, 14, 0x304]));
+    assert!(interrupts_of(fdt.find_node("/memory").unwrap()).is_empty());
+
+    // No interrupt-parent anywhere above
+    let fdt = riscv64();
+    assert!(interrupts_of(fdt.find_node("/poweroff").unwrap()).is_empty());
+}
+
+#[test]
+fn interrupts_parent() {
+    let fdt = riscv64();
+    assert_eq!(
+        interrupts_of(fdt.find_node("/soc/serial").unwrap()),
+        [("plic@c000000", vec![10])]
+    );
+    assert_eq!(
+        interrupts_of(fdt.find_node("/soc/rtc").unwrap()),
+        [("plic@c000000", vec![11])]
+    );
+}
+
+#[test]
+fn interrupts_extended() {
+    let fdt = riscv64();
+    let clint = fdt.find_compatible(&["riscv,clint0"]).unwrap();
+    let interrupts: Vec<(u32, u32)> = clint
+        .interrupts()
+        .map(|interrupt| {
+            assert!(interrupt.controller.is_compatible("riscv,cpu-intc"));
+            (
+                interrupt.controller.phandle().unwrap(),
+                interrupt.cell(0).unwrap(),
+            )
+        })
+        .collect();
+    // Machine software and timer interrupts of each hart
+    assert_eq!(interrupts, [(2, 3), (2, 7), (5, 3), (5, 7)]);
+
+    let plic = fdt.find_node("/soc/plic").unwrap();
+    assert!(plic.is_interrupt_controller());
+    assert_eq!(plic.interrupts().count(), 4);
+    assert_eq!(plic.interrupts().last().unwrap().cell(1), None);
+}
+
+#[test]
+fn properties() {
+    let fdt = riscv64();
+    let cpu = fdt.find_node("/cpus/cpu@0").unwrap();
+    assert!(cpu.is_enabled());
+    assert_eq!(cpu.property("device_type"), Some(&b"cpu\0"[..]));
+    assert_eq!(cpu.property("mmu-type"), Some(&b"riscv,sv57\0"[..]));
+    assert!(cpu.property("status").is_some());
+    assert!(cpu.property("missing").is_none());
+    // Properties only, not the child node's
+    assert!(cpu.properties().all(|(name, _)| name != "#interrupt-cells"));
+    assert_eq!(
+        fdt.find_node("/cpus")
+            .unwrap()
+            .property_u32("timebase-frequency"),
+        Some(10_000_000)
+    );
+
+    let names: Vec<&str> = fdt.nodes().map(|node| node.name()).collect();
+    assert_eq!(names[0], "");
+    assert_eq!(names.len(), 16);
+}
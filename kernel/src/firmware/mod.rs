@@ -0,0 +1,6 @@
+// kernel/src/firmware/mod.rs
+//
+// Parsers for the tables the firmware describes the machine with.
+
+pub mod acpi;
+pub mod fdt;
@@ -0,0 +1,197 @@
+// kernel/src/interrupts/apic.rs
+//
+// x86_64 interrupt controllers, as the ACPI MADT describes them: the 8259s
+// are masked, the local APIC enabled, and every I/O APIC brought up with
+// all its inputs masked. Devices then route their GSI, or their legacy ISA
+// IRQ through the MADT's overrides, to a vector from idt::allocate_vector.
+//
+// Everything is delivered to the BSP until other CPUs are started.
+
+use super::{Controller, Polarity, Trigger};
+use crate::acpi::{self, madt};
+use crate::arch::x86_64::idt;
+use crate::drivers::apic::{self, Mode};
+use crate::drivers::ioapic::{IoApic, Redirection};
+use crate::drivers::pic8259;
+use crate::mm::vmm::{self, MapError};
+use crate::sync::SpinLock;
+
+const MAX_IOAPICS: usize = 8;
+const ISA_IRQS: usize = 16;
+
+// Size of an I/O APIC's register window
+const IOAPIC_WINDOW: u64 = 0x20;
+
+#[derive(Clone, Copy)]
+struct IsaRoute {
+    gsi: u32,
+    trigger: Trigger,
+    polarity: Polarity,
+}
+
+struct Routing {
+    ioapics: [Option<IoApic>; MAX_IOAPICS],
+    // ISA IRQ n is GSI n, edge triggered and active high, unless the MADT
+    // overrides it
+    isa: [IsaRoute; ISA_IRQS],
+}
+
+static ROUTING: SpinLock<Routing> = SpinLock::new(Routing {
+    ioapics: [const { None }; MAX_IOAPICS],
+    isa: [IsaRoute {
+        gsi: 0,
+        trigger: Trigger::Edge,
+        polarity: Polarity::ActiveHigh,
+    }; ISA_IRQS],
+});
+
+pub fn init() -> Result<(), MapError> {
+    let madt = acpi::find_table(madt::SIGNATURE).and_then(madt::Madt::parse);
+    if madt.is_none() {
+        warn!("no ACPI MADT, interrupts from the local APIC only");
+    }
+
+    // Without a MADT there's no telling, and masking absent PICs is harmless
+    if madt.as_ref().is_none_or(|madt| madt.flags() & madt::PCAT_COMPAT != 0) {
+        pic8259::disable(idt::PIC_VECTOR_BASE);
+    }
+
+    let mode = apic::init(idt::SPURIOUS_VECTOR)?;
+    super::set_controller(Controller {
+        acknowledge: || None,
+        end_of_interrupt: |_| apic::eoi(),
+    });
+
+    let apic_id = apic::id();
+    let mut routing = ROUTING.lock();
+    for (irq, route) in routing.isa.iter_mut().enumerate() {
+        route.gsi = irq as u32;
+    }
+
+    let entries = || madt.iter().flat_map(|madt| madt.entries());
+    let mut processor_uid = None;
+    let mut ioapic_count = 0;
+    let mut override_count = 0;
+    for entry in entries() {
+        match entry {
+            madt::Entry::LocalApic {
+                processor_uid: uid,
+                apic_id: id,
+                ..
+            } if id as u32 == apic_id => processor_uid = Some(uid as u32),
+            madt::Entry::LocalX2Apic {
+                processor_uid: uid,
+                x2apic_id,
+                ..
+            } if x2apic_id == apic_id => processor_uid = Some(uid),
+            madt::Entry::IoApic {
+                address, gsi_base, ..
+            } => {
+                if ioapic_count == MAX_IOAPICS {
+                    warn!("ignoring I/O APIC at {:#x}, too many", address);
+                    continue;
+                }
+                let base = vmm::map_mmio(address as u64, IOAPIC_WINDOW)?;
+                let mut ioapic = unsafe { IoApic::new(base, gsi_base) };
+                ioapic.mask_all();
+                routing.ioapics[ioapic_count] = Some(ioapic);
+                ioapic_count += 1;
+            }
+            madt::Entry::InterruptOverride {
+                bus: 0,
+                source,
+                gsi,
+                flags,
+            } if (source as usize) < ISA_IRQS => {
+                routing.isa[source as usize] = IsaRoute {
+                    gsi,
+                    trigger: match flags & madt::TRIGGER_MASK {
+                        madt::TRIGGER_LEVEL => Trigger::Level,
+                        _ => Trigger::Edge,
+                    },
+                    polarity: match flags & madt::POLARITY_MASK {
+                        madt::POLARITY_ACTIVE_LOW => Polarity::ActiveLow,
+                        _ => Polarity::ActiveHigh,
+                    },
+                };
+                override_count += 1;
+            }
+            _ => {}
+        }
+    }
+
+    // NMI wiring of our LINT pins
+    for entry in entries() {
+        if let madt::Entry::LocalApicNmi {
+            processor_uid: uid,
+            flags,
+            lint,
+        } = entry
+        {
+            if uid == madt::ALL_PROCESSORS || Some(uid) == processor_uid {
+                apic::set_lint_nmi(
+                    lint,
+                    flags & madt::POLARITY_MASK == madt::POLARITY_ACTIVE_LOW,
+                    flags & madt::TRIGGER_MASK == madt::TRIGGER_LEVEL,
+                );
+            }
+        }
+    }
+
+    info!(
+        "local APIC {} in {} mode, {} I/O APIC(s), {} ISA override(s)",
+        apic_id,
+        match mode {
+            Mode::X2Apic => "x2APIC",
+            Mode::XApic => "xAPIC",
+        },
+        ioapic_count,
+        override_count
+    );
+    Ok(())
+}
+
+// Deliver `gsi` to the current CPU on `vector`, unmasked. Returns false
+// when no I/O APIC has that input, or when the CPU's x2APIC ID doesn't fit
+// the redirection entry's 8-bit destination (0xFF there is a broadcast),
+// which would take interrupt remapping.
+pub fn route_gsi(gsi: u32, vector: u8, trigger: Trigger, polarity: Polarity) -> bool {
+    let Some(destination) = u8::try_from(apic::id()).ok().filter(|&id| id != 0xFF) else {
+        warn!(
+            "can't route GSI {}: APIC ID {} is out of the I/O APIC's reach",
+            gsi,
+            apic::id()
+        );
+        return false;
+    };
+    let redirection = Redirection {
+        vector,
+        destination,
+        active_low: polarity == Polarity::ActiveLow,
+        level_triggered: trigger == Trigger::Level,
+        masked: false,
+    };
+    with_ioapic(gsi, |ioapic| ioapic.set_redirection(gsi, redirection))
+}
+
+// Route legacy ISA `irq` to `vector`, returning the GSI it arrives on
+pub fn route_isa_irq(irq: u8, vector: u8) -> Option<u32> {
+    let route = *ROUTING.lock().isa.get(irq as usize)?;
+    route_gsi(route.gsi, vector, route.trigger, route.polarity).then_some(route.gsi)
+}
+
+fn with_ioapic(gsi: u32, f: impl FnOnce(&mut IoApic)) -> bool {
+    let mut routing = ROUTING.lock();
+    match routing
+        .ioapics
+        .iter_mut()
+        .flatten()
+        .find(|ioapic| ioapic.handles(gsi))
+    {
+        Some(ioapic) => {
+            f(ioapic);
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,200 @@
+// kernel/src/interrupts/gic.rs
+//
+// aarch64 interrupt controller: a GICv2 or GICv3, found in the ACPI MADT
+// or, failing that, the device tree.
+
+use super::{Controller, Trigger};
+use crate::acpi::{self, madt};
+use crate::arch;
+use crate::drivers::gic::{self, Version};
+use crate::fdt;
+use crate::mm::vmm::{self, MapError};
+
+// Distributor register frame, and the GICv2 CPU interface's
+const DISTRIBUTOR_SIZE: u64 = 0x10000;
+const CPU_INTERFACE_SIZE: u64 = 0x2000;
+
+// GICv3 redistributor regions we keep track of
+const MAX_REGIONS: usize = 8;
+
+// Device tree compatibles, per the arm,gic and arm,gic-v3 bindings
+const GIC_V2_COMPATIBLE: &[&str] = &[
+    "arm,gic-400",
+    "arm,cortex-a15-gic",
+    "arm,cortex-a9-gic",
+    "arm,cortex-a7-gic",
+];
+const GIC_V3_COMPATIBLE: &[&str] = &["arm,gic-v3"];
+
+// Interrupt specifier cells of both bindings: type, number, flags
+const SPECIFIER_SPI: u32 = 0;
+const SPECIFIER_PPI: u32 = 1;
+const SPECIFIER_LEVEL: u32 = 0b1100;
+
+// What the firmware tables say about the GIC
+struct Layout {
+    version: Option<Version>,
+    distributor: u64,
+    cpu_interface: u64,
+    regions: [(u64, u64); MAX_REGIONS],
+    region_count: usize,
+}
+
+impl Layout {
+    fn add_region(&mut self, base: u64, length: u64) {
+        if self.region_count < MAX_REGIONS {
+            self.regions[self.region_count] = (base, length);
+            self.region_count += 1;
+        }
+    }
+}
+
+pub fn init() -> Result<(), MapError> {
+    let Some(mut layout) = from_madt().or_else(from_device_tree) else {
+        warn!("no GIC in the MADT or device tree, interrupts unavailable");
+        return Ok(());
+    };
+
+    let distributor = vmm::map_mmio(layout.distributor, DISTRIBUTOR_SIZE)?;
+    let version = match layout.version {
+        Some(version) => version,
+        None => match unsafe { gic::detect_version(distributor) } {
+            Some(version) => version,
+            None => {
+                warn!("unknown GIC version at {:#x}", layout.distributor);
+                return Ok(());
+            }
+        },
+    };
+
+    match version {
+        Version::V2 => {
+            let cpu_interface = vmm::map_mmio(layout.cpu_interface, CPU_INTERFACE_SIZE)?;
+            unsafe { gic::init_v2(distributor, cpu_interface) };
+        }
+        Version::V3 => {
+            for region in &mut layout.regions[..layout.region_count] {
+                region.0 = vmm::map_mmio(region.0, region.1)?;
+            }
+            if !unsafe { gic::init_v3(distributor, &layout.regions[..layout.region_count]) } {
+                warn!("no GICv3 redistributor for CPU {:#x}", arch::cpu_id());
+                return Ok(());
+            }
+        }
+    }
+
+    super::set_controller(Controller {
+        acknowledge: gic::acknowledge,
+        end_of_interrupt: gic::end_of_interrupt,
+    });
+    info!(
+        "GICv{} distributor at {:#x}, {} interrupt lines",
+        match version {
+            Version::V2 => 2,
+            Version::V3 => 3,
+        },
+        layout.distributor,
+        gic::line_count()
+    );
+    Ok(())
+}
+
+fn from_madt() -> Option<Layout> {
+    let madt = madt::Madt::parse(acpi::find_table(madt::SIGNATURE)?)?;
+    let cpu = arch::cpu_id();
+    let mut layout = Layout {
+        version: None,
+        distributor: 0,
+        cpu_interface: 0,
+        regions: [(0, 0); MAX_REGIONS],
+        region_count: 0,
+    };
+
+    let mut per_cpu_redistributor = 0;
+    for entry in madt.entries() {
+        match entry {
+            madt::Entry::Gicd { base, version, .. } => {
+                layout.distributor = base;
+                layout.version = match version {
+                    1 | 2 => Some(Version::V2),
+                    3 | 4 => Some(Version::V3),
+                    _ => None,
+                };
+            }
+            madt::Entry::GicRedistributor { base, length } => {
+                layout.add_region(base, length as u64);
+            }
+            madt::Entry::Gicc {
+                base,
+                gicr_base,
+                mpidr,
+                ..
+            } if mpidr & 0xFF_00FF_FFFF == cpu => {
+                layout.cpu_interface = base;
+                per_cpu_redistributor = gicr_base;
+            }
+            _ => {}
+        }
+    }
+
+    // Without redistributor ranges, each GICC entry points at its own
+    if layout.region_count == 0 && per_cpu_redistributor != 0 {
+        layout.add_region(per_cpu_redistributor, 0x20000);
+    }
+    (layout.distributor != 0).then_some(layout)
+}
+
+fn from_device_tree() -> Option<Layout> {
+    let fdt = fdt::get()?;
+    let mut layout = Layout {
+        version: None,
+        distributor: 0,
+        cpu_interface: 0,
+        regions: [(0, 0); MAX_REGIONS],
+        region_count: 0,
+    };
+
+    if let Some(node) = fdt.find_compatible(GIC_V3_COMPATIBLE) {
+        // The distributor, then #redistributor-regions ranges
+        let count = node.property_u32("#redistributor-regions").unwrap_or(1) as usize;
+        let mut reg = node.reg();
+        layout.distributor = reg.next()?.0;
+        for (base, length) in reg.take(count) {
+            layout.add_region(base, length);
+        }
+        layout.version = Some(Version::V3);
+    } else {
+        let node = fdt.find_compatible(GIC_V2_COMPATIBLE)?;
+        let mut reg = node.reg();
+        layout.distributor = reg.next()?.0;
+        layout.cpu_interface = reg.next()?.0;
+        layout.version = Some(Version::V2);
+    }
+    Some(layout)
+}
+
+// The INTID and trigger of a device tree interrupt going to the GIC, None
+// for any other controller or a specifier the bindings don't define
+pub fn decode_interrupt(interrupt: &fdt::Interrupt) -> Option<(u32, Trigger)> {
+    let controller = interrupt.controller;
+    if !GIC_V2_COMPATIBLE
+        .iter()
+        .chain(GIC_V3_COMPATIBLE)
+        .any(|name| controller.is_compatible(name))
+        || interrupt.cell_count() < 3
+    {
+        return None;
+    }
+
+    let number = interrupt.cell(1)?;
+    let intid = match interrupt.cell(0)? {
+        SPECIFIER_SPI => number.checked_add(32)?,
+        SPECIFIER_PPI if number < 16 => number + 16,
+        _ => return None,
+    };
+    let trigger = match interrupt.cell(2)? & SPECIFIER_LEVEL {
+        0 => Trigger::Edge,
+        _ => Trigger::Level,
+    };
+    Some((intid, trigger))
+}
@@ -0,0 +1,146 @@
+// kernel/src/interrupts/mod.rs
+//
+// Architecture-neutral interrupt dispatch. The architectures' entry code
+// calls handle() for every external interrupt; the interrupt controller
+// driver (local APIC, GIC) registers how to acknowledge and end one, and
+// drivers register a handler per interrupt number. Numbers are whatever the
+// controller uses: IDT vectors on x86_64, GIC INTIDs on aarch64.
+//
+// Everything here is read from interrupt context, so it lives in atomics
+// rather than behind a SpinLock.
+//
+// The controllers themselves are set up by the submodule for the platform.
+
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use crate::mm::vmm::MapError;
+
+#[cfg(target_arch = "x86_64")]
+pub mod apic;
+
+#[cfg(target_arch = "aarch64")]
+pub mod gic;
+
+// Covers the 256 IDT vectors and the GIC's SGIs, PPIs and SPIs (0-1019)
+pub const MAX_IRQS: usize = 1024;
+
+pub type Handler = fn(irq: u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    Edge,
+    Level,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Clone, Copy)]
+pub struct Controller {
+    // Claim the pending interrupt and return its number, None if it was
+    // spurious. Only called when the entry code doesn't know the number.
+    pub acknowledge: fn() -> Option<u32>,
+    pub end_of_interrupt: fn(irq: u32),
+}
+
+// fn pointers stored as usize, 0 meaning none
+static HANDLERS: [AtomicUsize; MAX_IRQS] = [const { AtomicUsize::new(0) }; MAX_IRQS];
+static ACKNOWLEDGE: AtomicUsize = AtomicUsize::new(0);
+static END_OF_INTERRUPT: AtomicUsize = AtomicUsize::new(0);
+
+// Interrupts with no handler, or that the controller called spurious
+static UNHANDLED: AtomicU64 = AtomicU64::new(0);
+
+// The interrupt handle() is in the middle of, NO_IRQ outside it
+const NO_IRQ: u32 = u32::MAX;
+static IN_SERVICE: AtomicU32 = AtomicU32::new(NO_IRQ);
+
+// Bring up the platform's interrupt controllers, everything masked
+pub fn init() -> Result<(), MapError> {
+    #[cfg(target_arch = "x86_64")]
+    apic::init()?;
+    #[cfg(target_arch = "aarch64")]
+    gic::init()?;
+    Ok(())
+}
+
+pub fn set_controller(controller: Controller) {
+    ACKNOWLEDGE.store(controller.acknowledge as usize, Ordering::Release);
+    END_OF_INTERRUPT.store(controller.end_of_interrupt as usize, Ordering::Release);
+}
+
+// Install the handler for `irq`, returning false if the number is out of
+// range or already taken
+pub fn register(irq: u32, handler: Handler) -> bool {
+    match HANDLERS.get(irq as usize) {
+        Some(slot) => slot
+            .compare_exchange(0, handler as usize, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok(),
+        None => false,
+    }
+}
+
+pub fn unregister(irq: u32) {
+    if let Some(slot) = HANDLERS.get(irq as usize) {
+        slot.store(0, Ordering::Release);
+    }
+}
+
+pub fn unhandled_count() -> u64 {
+    UNHANDLED.load(Ordering::Relaxed)
+}
+
+// Entry point for external interrupts, with interrupts masked. `irq` is the
+// number when the hardware already told the entry code (the IDT vector),
+// None when the controller has to be asked.
+pub fn handle(irq: Option<u32>) {
+    let irq = match irq {
+        Some(irq) => irq,
+        None => match load_fn::<fn() -> Option<u32>>(&ACKNOWLEDGE).and_then(|ack| ack()) {
+            Some(irq) => irq,
+            None => {
+                UNHANDLED.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        },
+    };
+
+    IN_SERVICE.store(irq, Ordering::Relaxed);
+    match HANDLERS.get(irq as usize).and_then(load_fn::<Handler>) {
+        Some(handler) => handler(irq),
+        None => {
+            UNHANDLED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    if let Some(end_of_interrupt) = load_fn::<fn(u32)>(&END_OF_INTERRUPT) {
+        end_of_interrupt(irq);
+    }
+    IN_SERVICE.store(NO_IRQ, Ordering::Relaxed);
+}
+
+// End the interrupt whose handler never returned to handle(), because a
+// panic in it was recovered from by jumping past it (as the test harness
+// does). Otherwise the controller would hold off that interrupt, and on
+// x86_64 everything of lower priority, for good.
+#[cfg(test)]
+pub fn end_abandoned() {
+    let irq = IN_SERVICE.swap(NO_IRQ, Ordering::Relaxed);
+    if irq == NO_IRQ {
+        return;
+    }
+    if let Some(end_of_interrupt) = load_fn::<fn(u32)>(&END_OF_INTERRUPT) {
+        end_of_interrupt(irq);
+    }
+}
+
+// Only ever called with the fn pointer type that was stored in `slot`
+fn load_fn<F: Copy>(slot: &AtomicUsize) -> Option<F> {
+    match slot.load(Ordering::Acquire) {
+        0 => None,
+        value => Some(unsafe { core::mem::transmute_copy::<usize, F>(&value) }),
+    }
+}
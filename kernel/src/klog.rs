@@ -0,0 +1,80 @@
+// kernel/src/klog.rs
+//
+// Kernel log buffer. It begins with the bootloader's trace (ELF and program
+// header dumps included), so the boot history outlives the UEFI console and
+// can be shown later or sent out over serial.
+
+use crate::sync::SpinLock;
+use crate::BootInfo;
+
+pub const KLOG_SIZE: usize = 64 * 1024;
+
+struct LogRing {
+    buffer: [u8; KLOG_SIZE],
+    // Total bytes ever written, the write position is written % KLOG_SIZE
+    written: u64,
+}
+
+impl LogRing {
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.buffer[(self.written % KLOG_SIZE as u64) as usize] = byte;
+            self.written += 1;
+        }
+    }
+}
+
+static KLOG: SpinLock<LogRing> = SpinLock::new(LogRing {
+    buffer: [0; KLOG_SIZE],
+    written: 0,
+});
+
+// Split a ring of `capacity` bytes with `written` total bytes into its
+// contents, oldest first. The second slice is empty until the ring wraps.
+fn ring_chunks(buffer: &[u8], written: u64) -> (&[u8], &[u8]) {
+    let capacity = buffer.len() as u64;
+    if written <= capacity {
+        (&buffer[..written as usize], &[])
+    } else {
+        let head = (written % capacity) as usize;
+        (&buffer[head..], &buffer[..head])
+    }
+}
+
+// Seed the kernel log with the bootloader's trace.
+// Must run before anything reclaims loader memory, the ring lives there.
+pub fn init(boot_info: &BootInfo) {
+    if boot_info.boot_log_addr == 0 || boot_info.boot_log_capacity == 0 {
+        return;
+    }
+
+    let boot_log = unsafe {
+        core::slice::from_raw_parts(
+            boot_info.boot_log_addr as *const u8,
+            boot_info.boot_log_capacity,
+        )
+    };
+
+    let (older, newer) = ring_chunks(boot_log, boot_info.boot_log_written);
+    let mut klog = KLOG.lock();
+    klog.push(older);
+    klog.push(newer);
+}
+
+pub fn write_str(s: &str) {
+    KLOG.lock().push(s.as_bytes());
+}
+
+// See SpinLock::force_unlock
+pub unsafe fn force_unlock() {
+    KLOG.force_unlock();
+}
+
+// Hand the log contents to `f`, oldest first, in at most two pieces.
+// Pieces are raw bytes: the oldest line may be cut off once the ring wraps.
+pub fn dump(mut f: impl FnMut(&[u8])) {
+    let klog = KLOG.lock();
+    let (older, newer) = ring_chunks(&klog.buffer, klog.written);
+    f(older);
+    f(newer);
+}
@@ -0,0 +1,10 @@
+// kernel/src/lib.rs
+//
+// The part of the kernel that is plain code over bytes, built as a library
+// so it builds for the host as well. `cargo test -p kernel --lib` from the
+// workspace root runs its tests there, against the table dumps in
+// testdata/. The kernel binary uses it as the `kernel` crate.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod firmware;
@@ -0,0 +1,66 @@
+// kernel/src/log.rs
+//
+// Kernel print and log facade behind print!/println! and the log macros.
+// Output goes to the kernel log ring, the serial port and the framebuffer
+// console. Nothing here needs initialising, so it works from the first
+// instruction of _start.
+
+use core::fmt::{self, Write};
+
+use crate::sync::SpinLock;
+use crate::{console, klog, serial};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+}
+
+// Keeps lines from different CPUs from interleaving
+static PRINT_LOCK: SpinLock<()> = SpinLock::new(());
+
+// Sends everything written to it to every sink
+pub struct Writer;
+
+impl Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        klog::write_str(s);
+        serial::write_str(s);
+        console::write_str(s);
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    let _guard = PRINT_LOCK.lock();
+    let _ = Writer.write_fmt(args);
+}
+
+// Take every output lock away from whoever holds it, for the panic handler
+pub unsafe fn force_unlock() {
+    PRINT_LOCK.force_unlock();
+    klog::force_unlock();
+    serial::force_unlock();
+    console::force_unlock();
+}
+
+// Same line format as the bootloader's log, so the two read as one
+#[doc(hidden)]
+pub fn _log(level: Level, args: fmt::Arguments) {
+    let _guard = PRINT_LOCK.lock();
+    let _ = writeln!(Writer, "[{:>5}] {}", level.as_str(), args);
+}
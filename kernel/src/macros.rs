@@ -0,0 +1,44 @@
+// kernel/src/macros.rs
+//
+// print!/println! and leveled log macros, see log.rs
+
+#![allow(unused_macros)]
+
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::log::_print(format_args!($($arg)*))
+    };
+}
+
+macro_rules! println {
+    () => {
+        print!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::log::_print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::_log($crate::log::Level::Error, format_args!($($arg)*))
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::log::_log($crate::log::Level::Warn, format_args!($($arg)*))
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::_log($crate::log::Level::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log::_log($crate::log::Level::Debug, format_args!($($arg)*))
+    };
+}
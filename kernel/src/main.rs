@@ -1,13 +1,36 @@
 #![no_std]
 #![no_main]
+#![feature(alloc_error_handler)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::run)]
+#![reexport_test_harness_main = "test_main"]
 
-use core::panic::PanicInfo;
+extern crate alloc;
 
-// Color constants for debugging
-const COLOR_RED:   u32 = 0x00FF0000;
-const COLOR_GREEN: u32 = 0x0000FF00;
-const COLOR_BLUE:  u32 = 0x000000FF;
-const COLOR_WHITE: u32 = 0x00FFFFFF;
+#[macro_use]
+mod macros;
+
+mod acpi;
+mod arch;
+mod cmdline;
+mod console;
+mod debug;
+mod drivers;
+mod efi;
+mod fdt;
+mod interrupts;
+mod klog;
+mod log;
+mod mm;
+mod panic;
+mod power;
+mod serial;
+mod smp;
+mod sync;
+#[cfg(test)]
+mod testing;
+mod time;
+mod timeline;
 
 #[repr(C)]
 pub struct BootInfo {
@@ -18,82 +41,122 @@ pub struct BootInfo {
     framebuffer_width: usize,
     framebuffer_height: usize,
     framebuffer_stride: usize,
-}
-
-// Extremely verbose debugging function
-fn debug_framebuffer(boot_info: &'static BootInfo) {
-    // Log framebuffer details via color patterns
-    let fb = unsafe {
-        core::slice::from_raw_parts_mut(
-            boot_info.framebuffer_addr as *mut u32,
-            boot_info.framebuffer_width * boot_info.framebuffer_height
-        )
-    };
-
-    // Diagnostic color pattern
-    for y in 0..boot_info.framebuffer_height {
-        for x in 0..boot_info.framebuffer_width {
-            let offset = y * boot_info.framebuffer_stride + x;
-            
-            // Create a diagnostic grid
-            let color = match (x / 80, y / 80) {
-                (0, 0) => COLOR_RED,    // Top-left: red
-                (1, 0) => COLOR_GREEN,  // Top-middle: green
-                (2, 0) => COLOR_BLUE,   // Top-right: blue
-                _ => COLOR_WHITE        // Rest: white
-            };
-
-            if offset < fb.len() {
-                fb[offset] = color;
-            }
-        }
-    }
+    // Which bits of a 32-bit pixel hold each colour
+    framebuffer_red_mask: u32,
+    framebuffer_green_mask: u32,
+    framebuffer_blue_mask: u32,
+    // UEFI runtime services, preserved across exit_boot_services
+    system_table_addr: u64,
+    runtime_services_addr: u64,
+    runtime_region_count: usize,
+    runtime_regions: [efi::MemoryDescriptor; efi::MAX_RUNTIME_REGIONS],
+    // Processor topology from the firmware's MP services
+    cpu_count: usize,
+    cpus: [smp::CpuInfo; smp::MAX_CPUS],
+    // Bootloader log ring, `boot_log_written` counts every byte ever written
+    boot_log_addr: u64,
+    boot_log_capacity: usize,
+    boot_log_written: u64,
+    // Boot phase timestamps, the kernel appends its own
+    boot_timeline: timeline::BootTimeline,
+    // Platform description tables, 0 when the firmware doesn't provide one
+    acpi_rsdp_addr: u64,
+    device_tree_addr: u64,
+    // Kernel command line from the boot entry, NUL-terminated
+    cmdline_len: usize,
+    cmdline: [u8; cmdline::MAX_CMDLINE],
+    // Kernel ELF symbol and string tables, inside the LOADER_DATA copy of the file
+    symtab_addr: u64,
+    symtab_size: u64,
+    strtab_addr: u64,
+    strtab_size: u64,
 }
 
 #[no_mangle]
 pub extern "C" fn _start(boot_info: &'static BootInfo) -> ! {
-    // Validate boot info
-    if boot_info.framebuffer_addr == 0 {
-        // Emergency red screen if no framebuffer
-        let emergency_fb = unsafe {
-            core::slice::from_raw_parts_mut(
-                0 as *mut u32,
-                1024 * 768  // Assume standard resolution
-            )
-        };
-        
-        for i in 0..1024*768 {
-            emergency_fb[i] = COLOR_RED;
-        }
-        
-        loop {
-            // Halt with emergency indicator
-            unsafe { core::arch::asm!("hlt"); }
-        }
+    timeline::mark("kernel entry");
+
+    // Start the kernel log with the bootloader's trace
+    klog::init(boot_info);
+    timeline::init(boot_info);
+
+    // Let panics and backtraces name functions
+    debug::symbols::init(boot_info);
+    debug::backtrace::init(boot_info);
+
+    // Firmware tables, read in place
+    acpi::init(boot_info);
+    fdt::init(boot_info);
+
+    // Move to the firmware's console UART and catch it up on the boot so far
+    serial::init();
+    klog::dump(serial::write_bytes);
+
+    // From here on everything printed also shows up on screen
+    console::init(boot_info);
+    info!("kernel entry, boot info at {:p}", boot_info);
+    if !console::is_available() {
+        warn!("no framebuffer, console output on serial only");
     }
 
-    // Perform diagnostic display
-    debug_framebuffer(boot_info);
+    let cmdline = cmdline::init(boot_info);
+    if !cmdline.as_str().is_empty() {
+        info!("command line: {}", cmdline.as_str());
+    }
+    panic::init(&cmdline);
 
-    // Hang forever with debug information visible
-    loop {
-        #[cfg(target_arch = "x86_64")]
-        unsafe {
-            core::arch::asm!("hlt");
-        }
+    // Take over the firmware's runtime services (RTC, reset, variables)
+    efi::init(boot_info);
+
+    // Record which processors exist before anything tries to start them
+    smp::init(boot_info);
+    arch::init_cpu(smp::bsp().hw_id);
+
+    // Physical memory, from the final memory map
+    mm::pmm::init(boot_info);
+    let memory = mm::pmm::stats();
+    info!(
+        "physical memory: {} MiB free of {} MiB",
+        memory.free >> 20,
+        memory.total >> 20
+    );
+
+    // Our own page tables, replacing the firmware's identity map. The heap
+    // maps its pages through them, so `alloc` works from here on.
+    if let Err(err) = mm::vmm::init(boot_info) {
+        panic!("failed to build kernel page tables: {:?}", err);
+    }
 
-        #[cfg(target_arch = "aarch64")]
-        unsafe {
-            core::arch::asm!("wfe");
+    // Those map the firmware's runtime regions where they are, so the
+    // runtime services can move to them without moving anywhere
+    if efi::is_available() {
+        if let Err(status) = efi::enter_virtual_mode(|phys| phys) {
+            warn!("UEFI SetVirtualAddressMap failed: {:?}", status);
         }
     }
-}
 
-// Panic handler with color-coded emergency
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    // Emergency blue screen on panic
-    loop {
-        unsafe { core::arch::asm!("hlt"); }
+    // The firmware's page tables, GDT/IDT or vector table are no longer in
+    // use, so boot services memory can be reused
+    let reclaimed = unsafe { mm::pmm::reclaim_boot_memory(boot_info) };
+    info!("reclaimed {} KiB of boot services memory", reclaimed >> 10);
+
+    // Interrupt controllers, then the clock and its event timer
+    if let Err(err) = interrupts::init() {
+        panic!("failed to set up interrupt controllers: {:?}", err);
     }
-}
\ No newline at end of file
+    time::init();
+    power::init();
+    arch::enable_interrupts();
+
+    // Devices the device tree describes
+    fdt::probe();
+    timeline::mark("kernel init");
+    timeline::log();
+
+    // Under `cargo test`, run the #[test_case]s and exit QEMU
+    #[cfg(test)]
+    test_main();
+
+    // Nothing left to do yet
+    arch::halt();
+}
@@ -0,0 +1,313 @@
+// kernel/src/mm/heap.rs
+//
+// Kernel heap behind #[global_allocator], so `alloc` (Box, Vec, BTreeMap)
+// works once vmm::init has run. Requests of up to 2 KiB come from slab
+// caches, one per power-of-two size class: pages of the heap window cut into
+// equal objects, with free objects linked through themselves. Larger
+// requests get their own run of pages in the window, each backed by a
+// separate frame, so they never need contiguous physical memory.
+//
+// Slab pages stay with their cache once carved; freed objects are reused but
+// their pages don't go back to pmm.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+
+use super::vmm::{self, MapFlags, PageSize};
+use super::{pmm, PAGE_SIZE};
+use crate::sync::SpinLock;
+
+#[cfg(test)]
+mod tests;
+
+// Virtual window for the heap, high in the lower half above the identity
+// map of physical memory
+const HEAP_START: u64 = 0x7000_0000_0000;
+const HEAP_END: u64 = 0x7800_0000_0000;
+
+// Object sizes of the slab caches. Objects are aligned to their size.
+pub const SIZE_CLASSES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+
+// Freed page runs kept for reuse. When the table is full, a freed run's
+// frames still go back to pmm but its addresses are not reused.
+const MAX_FREE_RANGES: usize = 64;
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+struct Cache {
+    free: *mut FreeObject,
+    // Objects handed out and not yet freed
+    allocated: usize,
+    // Objects carved out of the cache's pages
+    capacity: usize,
+}
+
+struct Heap {
+    caches: [Cache; SIZE_CLASSES.len()],
+    // Window addresses below this have been handed out at some point
+    top: u64,
+    // Freed runs of pages, as (start, pages)
+    free_ranges: [(u64, u64); MAX_FREE_RANGES],
+    free_range_count: usize,
+    // Bytes of large allocations, rounded up to pages
+    large_in_use: u64,
+}
+
+// The heap is only touched with its lock held
+unsafe impl Send for Heap {}
+
+static HEAP: SpinLock<Heap> = SpinLock::new(Heap::new());
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClassStats {
+    pub size: usize,
+    // Bytes in objects handed out
+    pub in_use: usize,
+    // Bytes in slab pages, used or free
+    pub capacity: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HeapStats {
+    pub classes: [ClassStats; SIZE_CLASSES.len()],
+    pub large_in_use: u64,
+}
+
+// For the debug monitor, which may have stopped the holder of the lock
+pub fn try_stats() -> Option<HeapStats> {
+    HEAP.try_lock().map(|heap| heap.stats())
+}
+
+struct KernelAllocator;
+
+#[global_allocator]
+static ALLOCATOR: KernelAllocator = KernelAllocator;
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = HEAP.lock();
+        let ptr = match size_class(layout) {
+            Some(class) => heap.alloc_object(class),
+            None => heap.alloc_pages(layout),
+        };
+        ptr.unwrap_or(ptr::null_mut())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut heap = HEAP.lock();
+        match size_class(layout) {
+            Some(class) => heap.free_object(class, ptr),
+            None => heap.free_pages(ptr as u64, page_count(layout.size())),
+        }
+    }
+
+    // Growing or shrinking within a size class or within the same number of
+    // pages needs no copy
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let same_block = match (size_class(layout), size_class(new_layout)) {
+            (Some(old), Some(new)) => old == new,
+            (None, None) => page_count(layout.size()) == page_count(new_size),
+            _ => false,
+        };
+        if same_block {
+            return ptr;
+        }
+
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    panic!(
+        "kernel heap exhausted: allocation of {} bytes (align {}) failed",
+        layout.size(),
+        layout.align()
+    );
+}
+
+impl Heap {
+    const fn new() -> Heap {
+        const EMPTY: Cache = Cache {
+            free: ptr::null_mut(),
+            allocated: 0,
+            capacity: 0,
+        };
+        Heap {
+            caches: [EMPTY; SIZE_CLASSES.len()],
+            top: HEAP_START,
+            free_ranges: [(0, 0); MAX_FREE_RANGES],
+            free_range_count: 0,
+            large_in_use: 0,
+        }
+    }
+
+    fn stats(&self) -> HeapStats {
+        let mut classes = [ClassStats::default(); SIZE_CLASSES.len()];
+        for ((stats, cache), &size) in classes.iter_mut().zip(&self.caches).zip(&SIZE_CLASSES) {
+            *stats = ClassStats {
+                size,
+                in_use: cache.allocated * size,
+                capacity: cache.capacity * size,
+            };
+        }
+        HeapStats {
+            classes,
+            large_in_use: self.large_in_use,
+        }
+    }
+
+    unsafe fn alloc_object(&mut self, class: usize) -> Option<*mut u8> {
+        if self.caches[class].free.is_null() {
+            self.grow(class)?;
+        }
+
+        let cache = &mut self.caches[class];
+        let object = cache.free;
+        cache.free = (*object).next;
+        cache.allocated += 1;
+        Some(object as *mut u8)
+    }
+
+    unsafe fn free_object(&mut self, class: usize, ptr: *mut u8) {
+        let cache = &mut self.caches[class];
+        let object = ptr as *mut FreeObject;
+        (*object).next = cache.free;
+        cache.free = object;
+        cache.allocated -= 1;
+    }
+
+    // Carve one more page into objects for `class`
+    unsafe fn grow(&mut self, class: usize) -> Option<()> {
+        let page = self.map_pages(1, PAGE_SIZE)?;
+        let size = SIZE_CLASSES[class];
+        let count = PAGE_SIZE as usize / size;
+
+        let cache = &mut self.caches[class];
+        for i in (0..count).rev() {
+            let object = (page + (i * size) as u64) as *mut FreeObject;
+            (*object).next = cache.free;
+            cache.free = object;
+        }
+        cache.capacity += count;
+        Some(())
+    }
+
+    unsafe fn alloc_pages(&mut self, layout: Layout) -> Option<*mut u8> {
+        let pages = page_count(layout.size());
+        let start = self.map_pages(pages, (layout.align() as u64).max(PAGE_SIZE))?;
+        self.large_in_use += pages * PAGE_SIZE;
+        Some(start as *mut u8)
+    }
+
+    unsafe fn free_pages(&mut self, start: u64, pages: u64) {
+        self.unmap_pages(start, pages);
+        self.release_range(start, pages);
+        self.large_in_use -= pages * PAGE_SIZE;
+    }
+
+    // Back `pages` pages of the window with fresh frames
+    fn map_pages(&mut self, pages: u64, align: u64) -> Option<u64> {
+        let start = self.reserve_range(pages, align)?;
+        for i in 0..pages {
+            let virt = start + i * PAGE_SIZE;
+            let mapped = pmm::alloc_frame().and_then(|frame| {
+                match vmm::map(virt, frame, PageSize::Size4K, MapFlags::KERNEL_DATA) {
+                    Ok(()) => Some(()),
+                    Err(_) => {
+                        pmm::free_frame(frame);
+                        None
+                    }
+                }
+            });
+
+            if mapped.is_none() {
+                self.unmap_pages(start, i);
+                self.release_range(start, pages);
+                return None;
+            }
+        }
+        Some(start)
+    }
+
+    fn unmap_pages(&mut self, start: u64, pages: u64) {
+        for i in 0..pages {
+            let virt = start + i * PAGE_SIZE;
+            if let Some((frame, _)) = vmm::translate(virt) {
+                let _ = vmm::unmap(virt, PAGE_SIZE);
+                pmm::free_frame(frame);
+            }
+        }
+    }
+
+    // Find `pages` unused pages of the window, the first aligned to `align`
+    fn reserve_range(&mut self, pages: u64, align: u64) -> Option<u64> {
+        for i in 0..self.free_range_count {
+            let (start, count) = self.free_ranges[i];
+            if count < pages || !start.is_multiple_of(align) {
+                continue;
+            }
+
+            if count == pages {
+                self.free_range_count -= 1;
+                self.free_ranges[i] = self.free_ranges[self.free_range_count];
+            } else {
+                self.free_ranges[i] = (start + pages * PAGE_SIZE, count - pages);
+            }
+            return Some(start);
+        }
+
+        let start = self.top.next_multiple_of(align);
+        let end = start.checked_add(pages * PAGE_SIZE)?;
+        if end > HEAP_END {
+            return None;
+        }
+        // Alignment padding is given up rather than tracked
+        self.top = end;
+        Some(start)
+    }
+
+    // Return a run of pages to the window, merging it with its neighbours
+    fn release_range(&mut self, start: u64, pages: u64) {
+        let (mut start, mut end) = (start, start + pages * PAGE_SIZE);
+
+        let mut i = 0;
+        while i < self.free_range_count {
+            let (other_start, other_pages) = self.free_ranges[i];
+            let other_end = other_start + other_pages * PAGE_SIZE;
+            if other_end == start || other_start == end {
+                start = start.min(other_start);
+                end = end.max(other_end);
+                self.free_range_count -= 1;
+                self.free_ranges[i] = self.free_ranges[self.free_range_count];
+            } else {
+                i += 1;
+            }
+        }
+
+        if end == self.top {
+            self.top = start;
+        } else if self.free_range_count < MAX_FREE_RANGES {
+            self.free_ranges[self.free_range_count] = (start, (end - start) / PAGE_SIZE);
+            self.free_range_count += 1;
+        }
+    }
+}
+
+// Slab cache for `layout`, None when it needs whole pages
+fn size_class(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+    SIZE_CLASSES.iter().position(|&class| class >= size)
+}
+
+fn page_count(size: usize) -> u64 {
+    (size as u64).div_ceil(PAGE_SIZE).max(1)
+}
@@ -0,0 +1,79 @@
+// kernel/src/mm/heap/tests.rs
+//
+// The kernel heap through `alloc`, and what it takes from the slab caches
+// and the page window.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use super::*;
+
+#[test_case]
+fn slab_alloc_free() {
+    // 8 bytes, from the 16-byte cache
+    let before = HEAP.lock().stats().classes[0].in_use;
+    let boxed = Box::new(0xABCD_u64);
+    assert_eq!(*boxed, 0xABCD);
+    assert!((&*boxed as *const u64 as u64).is_multiple_of(16));
+    assert_eq!(HEAP.lock().stats().classes[0].in_use, before + 16);
+    drop(boxed);
+    assert_eq!(HEAP.lock().stats().classes[0].in_use, before);
+
+    // The freed object is the next one handed out
+    let first = Box::new(1_u64);
+    let address = &*first as *const u64;
+    drop(first);
+    let second = Box::new(2_u64);
+    assert_eq!(&*second as *const u64, address);
+}
+
+#[test_case]
+fn large_alloc_free() {
+    let before = HEAP.lock().stats().large_in_use;
+    let mut buffer: Vec<u8> = Vec::with_capacity(3 * PAGE_SIZE as usize);
+    buffer.resize(buffer.capacity(), 0x5A);
+    assert_eq!(HEAP.lock().stats().large_in_use, before + 3 * PAGE_SIZE);
+
+    // Each page of it is mapped writable, to frames of its own
+    let start = buffer.as_ptr() as u64;
+    assert!(start.is_multiple_of(PAGE_SIZE) && (HEAP_START..HEAP_END).contains(&start));
+    let mut frames = [0; 3];
+    for (i, frame) in frames.iter_mut().enumerate() {
+        let (phys, flags) = vmm::translate(start + i as u64 * PAGE_SIZE).unwrap();
+        assert_eq!(flags, MapFlags::KERNEL_DATA);
+        *frame = phys;
+    }
+    assert!(frames[0] != frames[1] && frames[1] != frames[2]);
+    assert!(buffer.iter().all(|&byte| byte == 0x5A));
+
+    drop(buffer);
+    assert_eq!(HEAP.lock().stats().large_in_use, before);
+    assert_eq!(vmm::translate(start), None);
+}
+
+#[test_case]
+fn alignment() {
+    for (size, align) in [(8, 64), (100, 128), (2048, 2048), (64, 4096), (5000, 8192)] {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        unsafe {
+            let ptr = alloc::alloc::alloc(layout);
+            assert!(!ptr.is_null());
+            assert!((ptr as usize).is_multiple_of(align), "{:?}", layout);
+            ptr.write_bytes(0xFF, size);
+            alloc::alloc::dealloc(ptr, layout);
+        }
+    }
+}
+
+// Growing keeps the contents, from slab objects into pages
+#[test_case]
+fn realloc() {
+    let mut values: Vec<u32> = Vec::new();
+    for i in 0..5000 {
+        values.push(i);
+    }
+    assert!(values.iter().copied().eq(0..5000));
+    values.truncate(10);
+    values.shrink_to_fit();
+    assert!(values.iter().copied().eq(0..10));
+}
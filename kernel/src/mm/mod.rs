@@ -0,0 +1,71 @@
+// kernel/src/mm/mod.rs
+//
+// Memory management: physical frames (pmm), page tables (vmm) and the
+// kernel heap
+
+pub mod heap;
+pub mod pmm;
+pub mod vmm;
+
+use crate::efi::MemoryDescriptor;
+use crate::BootInfo;
+
+pub const PAGE_SIZE: u64 = 4096;
+
+// UEFI memory types, as found in MemoryDescriptor::ty
+pub const MEMORY_RESERVED: u32 = 0;
+pub const MEMORY_LOADER_CODE: u32 = 1;
+pub const MEMORY_LOADER_DATA: u32 = 2;
+pub const MEMORY_BOOT_SERVICES_CODE: u32 = 3;
+pub const MEMORY_BOOT_SERVICES_DATA: u32 = 4;
+pub const MEMORY_RUNTIME_CODE: u32 = 5;
+pub const MEMORY_CONVENTIONAL: u32 = 7;
+pub const MEMORY_UNUSABLE: u32 = 8;
+pub const MEMORY_MMIO: u32 = 11;
+pub const MEMORY_MMIO_PORT_SPACE: u32 = 12;
+pub const MEMORY_PAL_CODE: u32 = 13;
+
+extern "C" {
+    // Defined by the linker script, page aligned
+    static __kernel_start: u8;
+    static __text_end: u8;
+    static __rodata_end: u8;
+    static __kernel_end: u8;
+}
+
+// Physical address range of the loaded kernel image
+pub fn kernel_image() -> (u64, u64) {
+    unsafe {
+        (
+            &__kernel_start as *const u8 as u64,
+            &__kernel_end as *const u8 as u64,
+        )
+    }
+}
+
+// Ends of the kernel's text and of its read-only data. Writable data and
+// bss run from there to the end of the image.
+pub fn kernel_sections() -> (u64, u64) {
+    unsafe {
+        (
+            &__text_end as *const u8 as u64,
+            &__rodata_end as *const u8 as u64,
+        )
+    }
+}
+
+// Pointer through which the kernel reaches physical memory. The firmware's
+// identity map is still in use, so that is the physical address itself.
+pub fn phys_to_virt(phys: u64) -> *mut u8 {
+    phys as *mut u8
+}
+
+// The final memory map the bootloader got from exit_boot_services
+pub fn memory_map(boot_info: &BootInfo) -> impl Iterator<Item = &MemoryDescriptor> {
+    let entry_size = boot_info.memory_map_entry_size;
+    let count = boot_info.memory_map_size.checked_div(entry_size).unwrap_or(0);
+
+    (0..count).map(move |i| unsafe {
+        &*((boot_info.memory_map_addr as usize + i * entry_size) as *const MemoryDescriptor)
+    })
+}
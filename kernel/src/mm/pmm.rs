@@ -0,0 +1,408 @@
+// kernel/src/mm/pmm.rs
+//
+// Physical frame allocator, a binary buddy allocator over 4 KiB frames.
+// Free blocks of 2^order frames sit on one list per order, linked through
+// the free memory itself. One state byte per frame records which frames head
+// a free block and of which order, so a freed block finds its buddy in O(1).
+//
+// At boot only conventional memory is handed out. Boot services and loader
+// memory still hold the firmware's page tables, the boot stack and BootInfo,
+// so they only join through reclaim_boot_memory.
+
+use super::{
+    kernel_image, memory_map, phys_to_virt, MEMORY_BOOT_SERVICES_CODE, MEMORY_BOOT_SERVICES_DATA,
+    MEMORY_CONVENTIONAL, MEMORY_LOADER_CODE, MEMORY_LOADER_DATA, PAGE_SIZE,
+};
+use crate::sync::SpinLock;
+use crate::{arch, BootInfo};
+
+#[cfg(test)]
+mod tests;
+
+// Largest block, 2^18 frames or 1 GiB
+pub const MAX_ORDER: usize = 18;
+
+const MAX_BLOCK_SIZE: u64 = PAGE_SIZE << MAX_ORDER;
+
+// State of every frame that doesn't head a free block
+const FRAME_USED: u8 = 0xFF;
+
+const MAX_RESERVED: usize = 16;
+
+// Stored at the start of each free block. 0 ends a list, frame 0 is never free.
+#[repr(C)]
+struct FreeBlock {
+    next: u64,
+    prev: u64,
+}
+
+struct FrameAllocator {
+    // Physical address of state[0], aligned to MAX_BLOCK_SIZE so that block
+    // indices and physical addresses agree on alignment
+    base: u64,
+    // FRAME_USED, or the order of the free block the frame heads
+    states: &'static mut [u8],
+    free_lists: [u64; MAX_ORDER + 1],
+    total_frames: usize,
+    free_frames: usize,
+}
+
+// The state array is only touched with the allocator lock held
+unsafe impl Send for FrameAllocator {}
+
+static PMM: SpinLock<Option<FrameAllocator>> = SpinLock::new(None);
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryStats {
+    // Bytes under the allocator's control, allocated or not
+    pub total: u64,
+    pub free: u64,
+}
+
+// Physical ranges that must never be handed out
+struct Reserved {
+    ranges: [(u64, u64); MAX_RESERVED],
+    count: usize,
+}
+
+impl Reserved {
+    // Dropping a range would let the allocator hand out memory still in use,
+    // so running out of slots is fatal
+    fn add(&mut self, start: u64, end: u64) {
+        if start >= end {
+            return;
+        }
+        assert!(
+            self.count < MAX_RESERVED,
+            "more than {} reserved ranges, {:#x}-{:#x} can't be kept",
+            MAX_RESERVED,
+            start,
+            end
+        );
+        self.ranges[self.count] = (start, end);
+        self.count += 1;
+    }
+
+    // Call `f` with the pieces of [start, end) outside every reserved range
+    fn subtract(&self, start: u64, end: u64, f: &mut impl FnMut(u64, u64)) {
+        if start >= end {
+            return;
+        }
+
+        let overlap = self.ranges[..self.count]
+            .iter()
+            .find(|&&(r_start, r_end)| r_start < end && start < r_end);
+
+        match overlap {
+            Some(&(r_start, r_end)) => {
+                self.subtract(start, r_start.min(end), f);
+                self.subtract(r_end.max(start), end, f);
+            }
+            None => f(start, end),
+        }
+    }
+}
+
+// Everything the kernel still needs from boot, plus frame 0 so that 0 can
+// mean "none" (and on x86_64 all of low memory, kept for the AP trampoline)
+fn reserved_ranges(boot_info: &BootInfo) -> Reserved {
+    let mut reserved = Reserved {
+        ranges: [(0, 0); MAX_RESERVED],
+        count: 0,
+    };
+
+    #[cfg(target_arch = "x86_64")]
+    reserved.add(0, 0x10_0000);
+    #[cfg(not(target_arch = "x86_64"))]
+    reserved.add(0, PAGE_SIZE);
+
+    let (kernel_start, kernel_end) = kernel_image();
+    reserved.add(kernel_start, kernel_end);
+
+    let boot_info_addr = boot_info as *const BootInfo as u64;
+    reserved.add(boot_info_addr, boot_info_addr + core::mem::size_of::<BootInfo>() as u64);
+
+    reserved.add(
+        boot_info.memory_map_addr,
+        boot_info.memory_map_addr + boot_info.memory_map_size as u64,
+    );
+
+    // Four bytes per pixel
+    reserved.add(
+        boot_info.framebuffer_addr,
+        boot_info.framebuffer_addr
+            + (boot_info.framebuffer_stride * boot_info.framebuffer_height * 4) as u64,
+    );
+
+    reserved.add(boot_info.symtab_addr, boot_info.symtab_addr + boot_info.symtab_size);
+    reserved.add(boot_info.strtab_addr, boot_info.strtab_addr + boot_info.strtab_size);
+
+    // The device tree header holds its size, big-endian, at offset 4
+    if boot_info.device_tree_addr != 0 {
+        let size = u32::from_be(unsafe {
+            ((boot_info.device_tree_addr + 4) as *const u32).read_unaligned()
+        });
+        reserved.add(boot_info.device_tree_addr, boot_info.device_tree_addr + size as u64);
+    }
+
+    // We are still running on the stack the firmware gave the bootloader
+    let sp = arch::frame_pointer();
+    if let Some(stack) = memory_map(boot_info).find(|desc| {
+        (desc.phys_start..desc.phys_start + desc.page_count * PAGE_SIZE).contains(&sp)
+    }) {
+        reserved.add(stack.phys_start, stack.phys_start + stack.page_count * PAGE_SIZE);
+    }
+
+    reserved
+}
+
+fn is_boot_memory(ty: u32) -> bool {
+    matches!(
+        ty,
+        MEMORY_LOADER_CODE
+            | MEMORY_LOADER_DATA
+            | MEMORY_BOOT_SERVICES_CODE
+            | MEMORY_BOOT_SERVICES_DATA
+    )
+}
+
+pub fn init(boot_info: &BootInfo) {
+    // Cover all RAM, including what reclaim_boot_memory adds later
+    let usable = || {
+        memory_map(boot_info)
+            .filter(|desc| desc.ty == MEMORY_CONVENTIONAL || is_boot_memory(desc.ty))
+    };
+    let low = match usable().map(|desc| desc.phys_start).min() {
+        Some(low) => low,
+        None => return,
+    };
+    let high = usable()
+        .map(|desc| desc.phys_start + desc.page_count * PAGE_SIZE)
+        .max()
+        .unwrap_or(low);
+
+    let base = low & !(MAX_BLOCK_SIZE - 1);
+    let frame_count = ((high - base) / PAGE_SIZE) as usize;
+    let states_size = (frame_count as u64).div_ceil(PAGE_SIZE) * PAGE_SIZE;
+
+    // The state array comes out of the first conventional memory big enough
+    let mut reserved = reserved_ranges(boot_info);
+    let mut states_addr = None;
+    for desc in memory_map(boot_info).filter(|desc| desc.ty == MEMORY_CONVENTIONAL) {
+        let end = desc.phys_start + desc.page_count * PAGE_SIZE;
+        reserved.subtract(desc.phys_start, end, &mut |start, end| {
+            let start = start.next_multiple_of(PAGE_SIZE);
+            if states_addr.is_none() && start + states_size <= end {
+                states_addr = Some(start);
+            }
+        });
+    }
+    let states_addr = match states_addr {
+        Some(addr) => addr,
+        None => return,
+    };
+    reserved.add(states_addr, states_addr + states_size);
+
+    let states = unsafe { core::slice::from_raw_parts_mut(phys_to_virt(states_addr), frame_count) };
+    states.fill(FRAME_USED);
+
+    let mut allocator = FrameAllocator {
+        base,
+        states,
+        free_lists: [0; MAX_ORDER + 1],
+        total_frames: 0,
+        free_frames: 0,
+    };
+
+    for desc in memory_map(boot_info).filter(|desc| desc.ty == MEMORY_CONVENTIONAL) {
+        let end = desc.phys_start + desc.page_count * PAGE_SIZE;
+        reserved.subtract(desc.phys_start, end, &mut |start, end| {
+            allocator.add_range(start, end)
+        });
+    }
+
+    *PMM.lock() = Some(allocator);
+}
+
+// Hand boot services and loader memory to the allocator, keeping what the
+// kernel still uses from boot. Returns the number of bytes added.
+//
+// Safety: the kernel must run on its own page tables, the firmware's live in
+// boot services memory. Call at most once.
+pub unsafe fn reclaim_boot_memory(boot_info: &BootInfo) -> u64 {
+    let mut guard = PMM.lock();
+    let allocator = match guard.as_mut() {
+        Some(allocator) => allocator,
+        None => return 0,
+    };
+
+    let mut reserved = reserved_ranges(boot_info);
+    let states_addr = allocator.states.as_ptr() as u64;
+    reserved.add(states_addr, states_addr + allocator.states.len() as u64);
+
+    let before = allocator.total_frames;
+    for desc in memory_map(boot_info).filter(|desc| is_boot_memory(desc.ty)) {
+        let end = desc.phys_start + desc.page_count * PAGE_SIZE;
+        reserved.subtract(desc.phys_start, end, &mut |start, end| {
+            allocator.add_range(start, end)
+        });
+    }
+
+    (allocator.total_frames - before) as u64 * PAGE_SIZE
+}
+
+pub fn alloc_frame() -> Option<u64> {
+    alloc_frames(1, PAGE_SIZE)
+}
+
+// `count` physically contiguous frames, the first aligned to `align` (a power
+// of two, e.g. 2 MiB for a huge page). The block is rounded up to a power of
+// two internally, the unused tail goes straight back.
+pub fn alloc_frames(count: usize, align: u64) -> Option<u64> {
+    if count == 0 || !align.is_power_of_two() {
+        return None;
+    }
+
+    let align_frames = (align / PAGE_SIZE).max(1) as usize;
+    let order = count.max(align_frames).next_power_of_two().trailing_zeros() as usize;
+    if order > MAX_ORDER {
+        return None;
+    }
+
+    let mut guard = PMM.lock();
+    let allocator = guard.as_mut()?;
+    let index = allocator.alloc_block(order)?;
+    allocator.free_range(index + count, (1 << order) - count);
+    Some(allocator.address(index))
+}
+
+pub fn free_frame(addr: u64) {
+    free_frames(addr, 1);
+}
+
+// Return frames from alloc_frames. Any run of allocated frames may be freed,
+// not just whole allocations.
+pub fn free_frames(addr: u64, count: usize) {
+    if let Some(allocator) = PMM.lock().as_mut() {
+        assert!(addr.is_multiple_of(PAGE_SIZE) && addr >= allocator.base, "bad frame address 0x{:x}", addr);
+        let index = allocator.index(addr);
+        assert!(index + count <= allocator.states.len(), "bad frame address 0x{:x}", addr);
+        allocator.free_range(index, count);
+    }
+}
+
+pub fn stats() -> MemoryStats {
+    match PMM.lock().as_ref() {
+        Some(allocator) => MemoryStats {
+            total: allocator.total_frames as u64 * PAGE_SIZE,
+            free: allocator.free_frames as u64 * PAGE_SIZE,
+        },
+        None => MemoryStats::default(),
+    }
+}
+
+impl FrameAllocator {
+    fn address(&self, index: usize) -> u64 {
+        self.base + index as u64 * PAGE_SIZE
+    }
+
+    fn index(&self, addr: u64) -> usize {
+        ((addr - self.base) / PAGE_SIZE) as usize
+    }
+
+    fn block(&self, index: usize) -> *mut FreeBlock {
+        phys_to_virt(self.address(index)) as *mut FreeBlock
+    }
+
+    // Add never-before-seen memory, trimmed to whole frames
+    fn add_range(&mut self, start: u64, end: u64) {
+        let covered_end = self.address(self.states.len());
+        let start = start.next_multiple_of(PAGE_SIZE).max(self.base);
+        let end = (end & !(PAGE_SIZE - 1)).min(covered_end);
+        if start >= end {
+            return;
+        }
+
+        let count = ((end - start) / PAGE_SIZE) as usize;
+        self.total_frames += count;
+        self.free_range(self.index(start), count);
+    }
+
+    // Free [index, index + count) as the largest aligned blocks that fit
+    fn free_range(&mut self, mut index: usize, count: usize) {
+        let end = index + count;
+        while index < end {
+            let mut order = (index.trailing_zeros() as usize).min(MAX_ORDER);
+            while index + (1 << order) > end {
+                order -= 1;
+            }
+            self.free_block(index, order);
+            index += 1 << order;
+        }
+    }
+
+    fn free_block(&mut self, mut index: usize, mut order: usize) {
+        assert!(
+            self.states[index] == FRAME_USED,
+            "double free of frame 0x{:x}",
+            self.address(index)
+        );
+        self.free_frames += 1 << order;
+
+        // Merge with the buddy for as long as it is free and whole
+        while order < MAX_ORDER {
+            let buddy = index ^ (1 << order);
+            if buddy >= self.states.len() || self.states[buddy] != order as u8 {
+                break;
+            }
+            self.unlink(buddy, order);
+            index = index.min(buddy);
+            order += 1;
+        }
+
+        self.push(index, order);
+    }
+
+    fn alloc_block(&mut self, order: usize) -> Option<usize> {
+        let mut current = (order..=MAX_ORDER).find(|&o| self.free_lists[o] != 0)?;
+        let index = self.index(self.free_lists[current]);
+        self.unlink(index, current);
+
+        // Put the upper halves back until the block is the right size
+        while current > order {
+            current -= 1;
+            self.push(index + (1 << current), current);
+        }
+
+        self.free_frames -= 1 << order;
+        Some(index)
+    }
+
+    fn push(&mut self, index: usize, order: usize) {
+        let addr = self.address(index);
+        let head = self.free_lists[order];
+        unsafe {
+            self.block(index).write(FreeBlock { next: head, prev: 0 });
+            if head != 0 {
+                (*self.block(self.index(head))).prev = addr;
+            }
+        }
+        self.free_lists[order] = addr;
+        self.states[index] = order as u8;
+    }
+
+    fn unlink(&mut self, index: usize, order: usize) {
+        let FreeBlock { next, prev } = unsafe { self.block(index).read() };
+        unsafe {
+            if prev != 0 {
+                (*self.block(self.index(prev))).next = next;
+            } else {
+                self.free_lists[order] = next;
+            }
+            if next != 0 {
+                (*self.block(self.index(next))).prev = prev;
+            }
+        }
+        self.states[index] = FRAME_USED;
+    }
+}
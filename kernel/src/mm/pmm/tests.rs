@@ -0,0 +1,80 @@
+// kernel/src/mm/pmm/tests.rs
+//
+// The frame allocator, as the kernel set it up from the firmware's memory
+// map.
+
+use super::*;
+
+// Order of the free block `addr` is in, None if it is allocated
+fn free_order(addr: u64) -> Option<usize> {
+    let guard = PMM.lock();
+    let allocator = guard.as_ref().unwrap();
+    let index = allocator.index(addr);
+    (0..=MAX_ORDER).find(|&order| {
+        let head = index & !((1 << order) - 1);
+        allocator.states[head] == order as u8
+    })
+}
+
+#[test_case]
+fn alloc_free() {
+    let before = stats();
+    assert!(before.free > 0 && before.free <= before.total);
+
+    let frame = alloc_frame().unwrap();
+    assert!(frame != 0 && frame.is_multiple_of(PAGE_SIZE));
+    assert_eq!(free_order(frame), None);
+    assert_eq!(stats().free, before.free - PAGE_SIZE);
+    // The frame is usable memory
+    unsafe {
+        let page = phys_to_virt(frame) as *mut u64;
+        page.write_volatile(0x5A5A_5A5A);
+        assert_eq!(page.read_volatile(), 0x5A5A_5A5A);
+    }
+
+    let other = alloc_frame().unwrap();
+    assert!(other != frame);
+    free_frame(frame);
+    free_frame(other);
+    assert_eq!(stats().free, before.free);
+}
+
+#[test_case]
+fn alloc_aligned() {
+    let before = stats();
+    let align = 2 << 20;
+    let frames = alloc_frames(3, align).unwrap();
+    assert!(frames.is_multiple_of(align));
+    // Only the frames asked for, the rest of the block went straight back
+    assert_eq!(stats().free, before.free - 3 * PAGE_SIZE);
+    free_frames(frames, 3);
+    assert_eq!(stats().free, before.free);
+
+    assert_eq!(alloc_frames(0, PAGE_SIZE), None);
+    assert_eq!(alloc_frames(1, 3 * PAGE_SIZE), None);
+    assert_eq!(alloc_frames(1 << (MAX_ORDER + 1), PAGE_SIZE), None);
+}
+
+// Freed frames merge with their buddies back into the block they came from
+#[test_case]
+fn buddy_merge() {
+    let block = alloc_frames(4, 4 * PAGE_SIZE).unwrap();
+    let frame = |i: u64| block + i * PAGE_SIZE;
+
+    free_frame(frame(0));
+    assert_eq!(free_order(frame(0)), Some(0));
+    free_frame(frame(1));
+    assert_eq!(free_order(frame(0)), Some(1));
+    assert_eq!(free_order(frame(1)), Some(1));
+
+    // Its buddy is still half allocated
+    free_frame(frame(3));
+    assert_eq!(free_order(frame(3)), Some(0));
+    assert_eq!(free_order(frame(2)), None);
+
+    // Whole again, and maybe merged further up
+    free_frame(frame(2));
+    for i in 0..4 {
+        assert!(free_order(frame(i)).unwrap() >= 2);
+    }
+}
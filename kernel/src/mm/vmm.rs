@@ -0,0 +1,496 @@
+// kernel/src/mm/vmm.rs
+//
+// Virtual memory: 4-level page tables with 4 KiB, 2 MiB and 1 GiB pages. The
+// walker is architecture neutral, arch supplies the entry format (x86_64
+// PML4, aarch64 4 KiB granule), which both use 512-entry tables and 9
+// address bits per level. Only the lower half, below 2^47, is managed.
+//
+// Tables are pmm frames reached through phys_to_virt. Large pages are split
+// when only part of one is unmapped, protected or replaced, but never merged
+// back. After a change to an active address space the TLB is flushed; only the
+// boot CPU runs, so there are no other TLBs to shoot down.
+
+use crate::arch;
+use crate::efi;
+use crate::mm::{
+    self, pmm, MEMORY_MMIO, MEMORY_MMIO_PORT_SPACE, MEMORY_PAL_CODE, MEMORY_RESERVED,
+    MEMORY_RUNTIME_CODE, MEMORY_UNUSABLE, PAGE_SIZE,
+};
+use crate::serial;
+use crate::sync::SpinLock;
+use crate::BootInfo;
+
+#[cfg(test)]
+mod tests;
+
+const LEVELS: usize = 4;
+const ENTRIES: usize = 512;
+
+// End of the lower half
+const ADDRESS_LIMIT: u64 = 1 << 47;
+
+// Beyond this many pages one full flush is cheaper than a flush per page
+const FLUSH_ALL_THRESHOLD: u64 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheMode {
+    WriteBack,
+    // Uncached, but writes may be buffered and merged
+    WriteCombining,
+    // Device memory: uncached, unbuffered, never speculated
+    Uncached,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MapFlags {
+    pub writable: bool,
+    pub executable: bool,
+    pub user: bool,
+    pub cache: CacheMode,
+}
+
+impl MapFlags {
+    pub const KERNEL_CODE: MapFlags = MapFlags {
+        writable: false,
+        executable: true,
+        user: false,
+        cache: CacheMode::WriteBack,
+    };
+
+    pub const KERNEL_RODATA: MapFlags = MapFlags {
+        writable: false,
+        ..MapFlags::KERNEL_CODE
+    };
+
+    pub const KERNEL_DATA: MapFlags = MapFlags {
+        writable: true,
+        executable: false,
+        ..MapFlags::KERNEL_CODE
+    };
+
+    pub const FRAMEBUFFER: MapFlags = MapFlags {
+        cache: CacheMode::WriteCombining,
+        ..MapFlags::KERNEL_DATA
+    };
+
+    pub const MMIO: MapFlags = MapFlags {
+        cache: CacheMode::Uncached,
+        ..MapFlags::KERNEL_DATA
+    };
+
+    // UEFI runtime images keep code and data in one region
+    const RUNTIME_CODE: MapFlags = MapFlags {
+        executable: true,
+        ..MapFlags::KERNEL_DATA
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageSize {
+    Size4K,
+    Size2M,
+    Size1G,
+}
+
+impl PageSize {
+    pub fn bytes(self) -> u64 {
+        page_bytes(self.level())
+    }
+
+    fn level(self) -> usize {
+        match self {
+            PageSize::Size4K => 0,
+            PageSize::Size2M => 1,
+            PageSize::Size1G => 2,
+        }
+    }
+}
+
+// A decoded page table entry, see Arch::decode_entry
+pub enum Entry {
+    Empty,
+    // Physical address of the next-level table
+    Table(u64),
+    // Physical address and flags of a page of the entry's level
+    Leaf(u64, MapFlags),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapError {
+    // Address or length not aligned to the page size
+    Misaligned,
+    // Outside the lower half
+    InvalidAddress,
+    // The CPU can't map pages of this size
+    UnsupportedPageSize,
+    AlreadyMapped,
+    NotMapped,
+    OutOfMemory,
+    // The kernel address space doesn't exist yet
+    Uninitialized,
+}
+
+static KERNEL_SPACE: SpinLock<Option<AddressSpace>> = SpinLock::new(None);
+
+pub struct AddressSpace {
+    root: u64,
+}
+
+impl AddressSpace {
+    // An empty address space
+    pub fn new() -> Result<AddressSpace, MapError> {
+        Ok(AddressSpace {
+            root: alloc_table()?,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        arch::current_page_table() == self.root
+    }
+
+    // Map one page of `size`. Both addresses must be aligned to it.
+    pub fn map(&mut self, virt: u64, phys: u64, size: PageSize, flags: MapFlags) -> Result<(), MapError> {
+        if size.level() > arch::max_leaf_level() {
+            return Err(MapError::UnsupportedPageSize);
+        }
+        check_range(virt, size.bytes())?;
+        if !phys.is_multiple_of(size.bytes()) {
+            return Err(MapError::Misaligned);
+        }
+
+        self.set_leaf(virt, phys, size.level(), flags, false)
+    }
+
+    // Map `len` bytes, using the largest pages the alignment of both
+    // addresses allows. Whatever is mapped in the range already is replaced.
+    // On failure the pages mapped so far stay mapped.
+    pub fn remap_range(&mut self, virt: u64, phys: u64, len: u64, flags: MapFlags) -> Result<(), MapError> {
+        check_range(virt, len)?;
+        if !phys.is_multiple_of(PAGE_SIZE) {
+            return Err(MapError::Misaligned);
+        }
+
+        let mut offset = 0;
+        let mut result = Ok(());
+        while offset < len {
+            let size = largest_page(virt + offset, phys + offset, len - offset);
+            result = self.set_leaf(virt + offset, phys + offset, size.level(), flags, true);
+            if result.is_err() {
+                break;
+            }
+            offset += size.bytes();
+        }
+        self.flush(virt, offset);
+        result
+    }
+
+    // Unmap every page in the range; holes are skipped. The memory itself
+    // belongs to whoever mapped it and is not freed.
+    pub fn unmap(&mut self, virt: u64, len: u64) -> Result<(), MapError> {
+        self.update_range(virt, len, None)
+    }
+
+    // Change the flags of every page in the range, which must be fully mapped
+    pub fn protect(&mut self, virt: u64, len: u64, flags: MapFlags) -> Result<(), MapError> {
+        self.update_range(virt, len, Some(flags))
+    }
+
+    // Physical address and flags `virt` is mapped to
+    pub fn translate(&self, virt: u64) -> Option<(u64, MapFlags)> {
+        if virt >= ADDRESS_LIMIT {
+            return None;
+        }
+
+        let (entry, level) = self.find(virt);
+        match arch::decode_entry(unsafe { *entry }, level) {
+            Entry::Leaf(phys, flags) => Some((phys + (virt & (page_bytes(level) - 1)), flags)),
+            Entry::Empty | Entry::Table(_) => None,
+        }
+    }
+
+    // Point the entry for `virt` at `level` to a page at `phys`, creating
+    // tables on the way. With `replace`, larger pages in the way are split
+    // and smaller ones (with their tables) dropped.
+    fn set_leaf(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        level: usize,
+        flags: MapFlags,
+        replace: bool,
+    ) -> Result<(), MapError> {
+        let mut table = self.root;
+        for current in (level + 1..LEVELS).rev() {
+            let entry = unsafe { entry_ptr(table, index(virt, current)) };
+            table = match arch::decode_entry(unsafe { *entry }, current) {
+                Entry::Table(next) => next,
+                Entry::Empty => {
+                    let next = alloc_table()?;
+                    unsafe { *entry = arch::table_entry(next) };
+                    next
+                }
+                Entry::Leaf(..) if !replace => return Err(MapError::AlreadyMapped),
+                Entry::Leaf(leaf_phys, leaf_flags) => unsafe {
+                    split(entry, current, leaf_phys, leaf_flags)?
+                },
+            };
+        }
+
+        let entry = unsafe { entry_ptr(table, index(virt, level)) };
+        match arch::decode_entry(unsafe { *entry }, level) {
+            Entry::Empty => {}
+            _ if !replace => return Err(MapError::AlreadyMapped),
+            Entry::Table(child) if level > 0 => unsafe { free_table(child, level - 1) },
+            _ => {}
+        }
+        unsafe { *entry = arch::leaf_entry(phys, flags, level) };
+        Ok(())
+    }
+
+    // Unmap (`flags` None) or reprotect every page in the range, splitting
+    // large pages that straddle either end
+    fn update_range(&mut self, virt: u64, len: u64, flags: Option<MapFlags>) -> Result<(), MapError> {
+        check_range(virt, len)?;
+
+        let end = virt + len;
+        let mut addr = virt;
+        let mut result = Ok(());
+        while addr < end {
+            let (entry, level) = self.find(addr);
+            let size = page_bytes(level);
+
+            match arch::decode_entry(unsafe { *entry }, level) {
+                Entry::Leaf(phys, leaf_flags) => {
+                    if !addr.is_multiple_of(size) || end - addr < size {
+                        if let Err(err) = unsafe { split(entry, level, phys, leaf_flags) } {
+                            result = Err(err);
+                            break;
+                        }
+                        continue;
+                    }
+                    unsafe {
+                        *entry = match flags {
+                            Some(flags) => arch::leaf_entry(phys, flags, level),
+                            None => 0,
+                        };
+                    }
+                }
+                Entry::Empty | Entry::Table(_) => {
+                    if flags.is_some() {
+                        result = Err(MapError::NotMapped);
+                        break;
+                    }
+                }
+            }
+            addr = (addr & !(size - 1)) + size;
+        }
+
+        // Whatever was changed before an error still needs flushing
+        self.flush(virt, addr.min(end) - virt);
+        result
+    }
+
+    // The entry that maps `virt`: a leaf, or the empty entry where the walk
+    // ends, and its level
+    fn find(&self, virt: u64) -> (*mut u64, usize) {
+        let mut table = self.root;
+        let mut level = LEVELS - 1;
+        loop {
+            let entry = unsafe { entry_ptr(table, index(virt, level)) };
+            match arch::decode_entry(unsafe { *entry }, level) {
+                Entry::Table(next) if level > 0 => {
+                    table = next;
+                    level -= 1;
+                }
+                _ => return (entry, level),
+            }
+        }
+    }
+
+    fn flush(&self, virt: u64, len: u64) {
+        if len == 0 || !self.is_active() {
+            return;
+        }
+
+        if len / PAGE_SIZE > FLUSH_ALL_THRESHOLD {
+            arch::flush_tlb_all();
+        } else {
+            for page in (virt..virt + len).step_by(PAGE_SIZE as usize) {
+                arch::flush_tlb_page(page);
+            }
+        }
+    }
+}
+
+// Frees the tables, not the memory they map
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        unsafe { free_table(self.root, LEVELS - 1) };
+    }
+}
+
+// Build the kernel's page tables and switch to them. What the kernel uses of
+// the firmware's identity map stays at the same address, with the memory
+// types and permissions the kernel wants:
+//
+// - every region of the memory map write-back and non-executable, except
+//   MMIO (uncached), UEFI runtime code (executable, services are called in
+//   place) and reserved regions, which are left out unless the firmware
+//   needs them for runtime services, then uncached
+// - the kernel's text read-only and executable, rodata read-only, data and
+//   bss writable
+// - the framebuffer write-combining
+// - the serial port's registers, if it has any, uncached
+//
+// Page 0 is left out so that null pointers fault. Other device registers are
+// mapped by their drivers with map_mmio().
+pub fn init(boot_info: &BootInfo) -> Result<(), MapError> {
+    let mut space = AddressSpace::new()?;
+
+    let regions = mm::memory_map(boot_info)
+        .filter_map(|descriptor| Some((*descriptor, region_flags(descriptor.ty)?)))
+        .chain(efi::runtime_regions().map(|descriptor| {
+            (descriptor, region_flags(descriptor.ty).unwrap_or(MapFlags::MMIO))
+        }));
+    for (descriptor, flags) in regions {
+        let start = descriptor.phys_start.max(PAGE_SIZE);
+        let end = descriptor.phys_start + descriptor.page_count * PAGE_SIZE;
+        if end > start {
+            space.remap_range(start, start, end - start, flags)?;
+        }
+    }
+
+    // The whole image writable first, so that gaps between its segments
+    // can't leave the text and rodata unmapped
+    let (kernel_start, kernel_end) = mm::kernel_image();
+    let (text_end, rodata_end) = mm::kernel_sections();
+    space.remap_range(kernel_start, kernel_start, kernel_end - kernel_start, MapFlags::KERNEL_DATA)?;
+    space.protect(kernel_start, text_end - kernel_start, MapFlags::KERNEL_CODE)?;
+    space.protect(text_end, rodata_end - text_end, MapFlags::KERNEL_RODATA)?;
+
+    if boot_info.framebuffer_addr != 0 {
+        let size = (boot_info.framebuffer_stride * boot_info.framebuffer_height * 4) as u64;
+        let (start, len) = pages(boot_info.framebuffer_addr, size);
+        space.remap_range(start, start, len, MapFlags::FRAMEBUFFER)?;
+    }
+
+    if let Some((base, size)) = serial::mmio_window() {
+        let (start, len) = pages(base, size);
+        space.remap_range(start, start, len, MapFlags::MMIO)?;
+    }
+
+    unsafe { arch::activate_page_table(space.root) };
+    *KERNEL_SPACE.lock() = Some(space);
+    Ok(())
+}
+
+// How a memory map region of type `ty` is mapped, None for the kinds that
+// are left out
+fn region_flags(ty: u32) -> Option<MapFlags> {
+    match ty {
+        MEMORY_RESERVED | MEMORY_UNUSABLE | MEMORY_PAL_CODE => None,
+        MEMORY_MMIO | MEMORY_MMIO_PORT_SPACE => Some(MapFlags::MMIO),
+        MEMORY_RUNTIME_CODE => Some(MapFlags::RUNTIME_CODE),
+        _ => Some(MapFlags::KERNEL_DATA),
+    }
+}
+
+// The kernel address space, see AddressSpace for the operations
+
+pub fn map(virt: u64, phys: u64, size: PageSize, flags: MapFlags) -> Result<(), MapError> {
+    with_kernel_space(|space| space.map(virt, phys, size, flags))
+}
+
+pub fn unmap(virt: u64, len: u64) -> Result<(), MapError> {
+    with_kernel_space(|space| space.unmap(virt, len))
+}
+
+pub fn translate(virt: u64) -> Option<(u64, MapFlags)> {
+    KERNEL_SPACE.lock().as_ref()?.translate(virt)
+}
+
+// Identity-map device registers uncached, replacing any existing mapping.
+// `phys` and `len` needn't be page aligned. Returns the address to use.
+pub fn map_mmio(phys: u64, len: u64) -> Result<u64, MapError> {
+    let (start, len) = pages(phys, len);
+    with_kernel_space(|space| space.remap_range(start, start, len, MapFlags::MMIO))?;
+    Ok(phys)
+}
+
+// The whole pages `len` bytes at `addr` touch, as their start and length
+fn pages(addr: u64, len: u64) -> (u64, u64) {
+    let start = addr & !(PAGE_SIZE - 1);
+    (start, (addr + len).next_multiple_of(PAGE_SIZE) - start)
+}
+
+fn with_kernel_space<T>(
+    f: impl FnOnce(&mut AddressSpace) -> Result<T, MapError>,
+) -> Result<T, MapError> {
+    match KERNEL_SPACE.lock().as_mut() {
+        Some(space) => f(space),
+        None => Err(MapError::Uninitialized),
+    }
+}
+
+fn check_range(virt: u64, len: u64) -> Result<(), MapError> {
+    if !virt.is_multiple_of(PAGE_SIZE) || !len.is_multiple_of(PAGE_SIZE) {
+        return Err(MapError::Misaligned);
+    }
+    match virt.checked_add(len) {
+        Some(end) if end <= ADDRESS_LIMIT => Ok(()),
+        _ => Err(MapError::InvalidAddress),
+    }
+}
+
+fn page_bytes(level: usize) -> u64 {
+    PAGE_SIZE << (9 * level)
+}
+
+fn index(virt: u64, level: usize) -> usize {
+    ((virt >> (12 + 9 * level)) as usize) % ENTRIES
+}
+
+// Largest page the CPU supports that fits at both addresses and in `len`
+fn largest_page(virt: u64, phys: u64, len: u64) -> PageSize {
+    [PageSize::Size1G, PageSize::Size2M]
+        .into_iter()
+        .filter(|size| size.level() <= arch::max_leaf_level())
+        .find(|size| (virt | phys).is_multiple_of(size.bytes()) && len >= size.bytes())
+        .unwrap_or(PageSize::Size4K)
+}
+
+fn alloc_table() -> Result<u64, MapError> {
+    let table = pmm::alloc_frame().ok_or(MapError::OutOfMemory)?;
+    unsafe { core::ptr::write_bytes(mm::phys_to_virt(table), 0, PAGE_SIZE as usize) };
+    Ok(table)
+}
+
+unsafe fn entry_ptr(table: u64, index: usize) -> *mut u64 {
+    (mm::phys_to_virt(table) as *mut u64).add(index)
+}
+
+// Replace the leaf at `entry` with a table of pages one level down mapping
+// the same memory with the same flags, returning the new table. On aarch64
+// this skips break-before-make; the old and new translations agree, and the
+// caller flushes the range it goes on to change.
+unsafe fn split(entry: *mut u64, level: usize, phys: u64, flags: MapFlags) -> Result<u64, MapError> {
+    let table = alloc_table()?;
+    let child_size = page_bytes(level - 1);
+    for i in 0..ENTRIES {
+        *entry_ptr(table, i) = arch::leaf_entry(phys + i as u64 * child_size, flags, level - 1);
+    }
+    *entry = arch::table_entry(table);
+    Ok(table)
+}
+
+// Free a table and every table below it
+unsafe fn free_table(table: u64, level: usize) {
+    if level > 0 {
+        for i in 0..ENTRIES {
+            if let Entry::Table(child) = arch::decode_entry(*entry_ptr(table, i), level) {
+                free_table(child, level - 1);
+            }
+        }
+    }
+    pmm::free_frame(table);
+}
@@ -0,0 +1,135 @@
+// kernel/src/mm/vmm/tests.rs
+//
+// Mapping, translating and unmapping, in the kernel's address space and in
+// a fresh one that is never activated.
+
+use super::*;
+use crate::testing::{Ending, ShouldFail};
+
+// Below the heap window and far above the identity map of physical memory
+const TEST_WINDOW: u64 = 0x6000_0000_0000;
+
+#[test_case]
+fn map_translate_unmap() {
+    let frame = pmm::alloc_frame().unwrap();
+    let virt = TEST_WINDOW;
+    assert_eq!(translate(virt), None);
+
+    map(virt, frame, PageSize::Size4K, MapFlags::KERNEL_DATA).unwrap();
+    assert_eq!(translate(virt), Some((frame, MapFlags::KERNEL_DATA)));
+    assert_eq!(
+        translate(virt + 0x123),
+        Some((frame + 0x123, MapFlags::KERNEL_DATA))
+    );
+    assert_eq!(translate(virt + PAGE_SIZE), None);
+
+    // Written through the new mapping, read through the identity map
+    unsafe {
+        (virt as *mut u64).write_volatile(0x1234_5678);
+        assert_eq!(
+            (mm::phys_to_virt(frame) as *const u64).read_volatile(),
+            0x1234_5678
+        );
+    }
+
+    assert_eq!(
+        map(virt, frame, PageSize::Size4K, MapFlags::KERNEL_DATA),
+        Err(MapError::AlreadyMapped)
+    );
+    unmap(virt, PAGE_SIZE).unwrap();
+    assert_eq!(translate(virt), None);
+    // Holes are skipped
+    unmap(virt, PAGE_SIZE).unwrap();
+    pmm::free_frame(frame);
+}
+
+#[test_case]
+fn bad_ranges() {
+    let flags = MapFlags::KERNEL_DATA;
+    assert_eq!(
+        map(TEST_WINDOW + 1, 0, PageSize::Size4K, flags),
+        Err(MapError::Misaligned)
+    );
+    assert_eq!(
+        map(TEST_WINDOW, 1, PageSize::Size4K, flags),
+        Err(MapError::Misaligned)
+    );
+    assert_eq!(
+        map(ADDRESS_LIMIT, 0, PageSize::Size4K, flags),
+        Err(MapError::InvalidAddress)
+    );
+    assert_eq!(unmap(TEST_WINDOW, 1), Err(MapError::Misaligned));
+    assert_eq!(translate(ADDRESS_LIMIT), None);
+}
+
+// Large pages, and splitting them, in an address space of its own
+#[test_case]
+fn remap_range_and_split() {
+    let before = pmm::stats();
+    let len = 2 << 20;
+    let frames = pmm::alloc_frames(512, len).unwrap();
+    let flags = MapFlags::KERNEL_DATA;
+    {
+        let mut space = AddressSpace::new().unwrap();
+        assert!(!space.is_active());
+        space.remap_range(TEST_WINDOW, frames, len, flags).unwrap();
+        for offset in [0, PAGE_SIZE, len - 8] {
+            assert_eq!(
+                space.translate(TEST_WINDOW + offset),
+                Some((frames + offset, flags))
+            );
+        }
+        assert_eq!(space.translate(TEST_WINDOW + len), None);
+        assert_eq!(
+            space.map(TEST_WINDOW, frames, PageSize::Size4K, flags),
+            Err(MapError::AlreadyMapped)
+        );
+
+        // One page out of the middle of the large one
+        let hole = TEST_WINDOW + 7 * PAGE_SIZE;
+        space.unmap(hole, PAGE_SIZE).unwrap();
+        assert_eq!(space.translate(hole), None);
+        assert_eq!(
+            space.translate(hole - PAGE_SIZE),
+            Some((frames + 6 * PAGE_SIZE, flags))
+        );
+        assert_eq!(
+            space.translate(hole + PAGE_SIZE),
+            Some((frames + 8 * PAGE_SIZE, flags))
+        );
+        assert_eq!(
+            space.protect(hole, PAGE_SIZE, MapFlags::KERNEL_RODATA),
+            Err(MapError::NotMapped)
+        );
+
+        space
+            .protect(hole + PAGE_SIZE, PAGE_SIZE, MapFlags::KERNEL_RODATA)
+            .unwrap();
+        assert_eq!(
+            space.translate(hole + PAGE_SIZE),
+            Some((frames + 8 * PAGE_SIZE, MapFlags::KERNEL_RODATA))
+        );
+    }
+
+    // Dropping the space freed its tables
+    pmm::free_frames(frames, 512);
+    assert_eq!(pmm::stats().free, before.free);
+}
+
+// Nothing maps page 0, so null pointers fault
+#[test_case]
+fn page_zero_unmapped() {
+    assert_eq!(translate(0), None);
+    assert_eq!(translate(PAGE_SIZE - 1), None);
+}
+
+#[test_case]
+static NULL_READ_FAULTS: ShouldFail = ShouldFail {
+    name: concat!(module_path!(), "::null_read_faults"),
+    ending: Ending::Panicked,
+    test: || {
+        // In page 0 but not null, which read_volatile's debug checks refuse
+        let pointer = core::hint::black_box(8usize) as *const u64;
+        unsafe { pointer.read_volatile() };
+    },
+};
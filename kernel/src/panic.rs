@@ -0,0 +1,164 @@
+// kernel/src/panic.rs
+//
+// Panic handling. The first CPU to panic owns the report: it takes the output
+// locks from whoever holds them, paints the console blue and prints the
+// message, location, CPU, registers and a backtrace to the screen, serial and
+// kernel log. What happens next comes from the command line:
+//
+//   panic=halt         stop (the default)
+//   panic=reboot[:N]   reboot after N seconds, 10 if not given
+//   panic=debug        enter the debug monitor on the serial port
+//
+// Under `cargo test` a panic in a test fails only that test (see testing/),
+// and any other panic fails the whole run.
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::cmdline::CommandLine;
+#[cfg(test)]
+use crate::testing;
+use crate::{arch, console, debug, log, power, serial, timeline};
+
+const DEFAULT_REBOOT_DELAY: u64 = 10;
+
+// PANIC_CPU value while nobody is panicking
+const NO_CPU: u64 = u64::MAX;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    Halt = 0,
+    Reboot = 1,
+    Debug = 2,
+}
+
+// Hardware ID of the CPU reporting a panic
+static PANIC_CPU: AtomicU64 = AtomicU64::new(NO_CPU);
+
+// Plain atomics so the handler never needs a lock to read them
+static ACTION: AtomicU8 = AtomicU8::new(Action::Halt as u8);
+static REBOOT_DELAY: AtomicU64 = AtomicU64::new(DEFAULT_REBOOT_DELAY);
+
+pub fn init(cmdline: &CommandLine) {
+    let value = match cmdline.value("panic") {
+        Some(value) => value,
+        None => return,
+    };
+
+    let (action, delay) = match value {
+        "halt" => (Action::Halt, DEFAULT_REBOOT_DELAY),
+        "debug" => (Action::Debug, DEFAULT_REBOOT_DELAY),
+        "reboot" => (Action::Reboot, DEFAULT_REBOOT_DELAY),
+        _ => match value.strip_prefix("reboot:").map(str::parse) {
+            Some(Ok(seconds)) => (Action::Reboot, seconds),
+            _ => {
+                warn!("unknown panic action '{}', panics will halt", value);
+                return;
+            }
+        },
+    };
+
+    ACTION.store(action as u8, Ordering::Relaxed);
+    REBOOT_DELAY.store(delay, Ordering::Relaxed);
+}
+
+#[cfg_attr(test, allow(dead_code))]
+fn action() -> Action {
+    match ACTION.load(Ordering::Relaxed) {
+        1 => Action::Reboot,
+        2 => Action::Debug,
+        _ => Action::Halt,
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    // A failing test, which the harness recovers from
+    #[cfg(test)]
+    testing::panicked(info);
+
+    let registers = arch::capture_registers();
+    arch::disable_interrupts();
+    let cpu = arch::cpu_id();
+
+    match PANIC_CPU.compare_exchange(NO_CPU, cpu, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => {}
+        // Panicking inside the report, so the report itself is broken
+        Err(owner) if owner == cpu => nested_panic(info),
+        // Another CPU is already reporting, stay out of its way
+        Err(_) => arch::halt(),
+    }
+
+    // Only the BSP runs kernel code so far. Once other CPUs are started they
+    // have to be stopped with an IPI here, before we take their locks away.
+
+    // Whoever held the output locks is not going to release them
+    unsafe { log::force_unlock() };
+    console::panic_screen();
+
+    let mut out = log::Writer;
+    let _ = writeln!(out, "\n*** KERNEL PANIC on CPU {} ***\n", cpu);
+    let _ = writeln!(out, "{}", info.message());
+    if let Some(location) = info.location() {
+        let _ = writeln!(
+            out,
+            "at {}:{}:{}",
+            location.file(),
+            location.line(),
+            location.column()
+        );
+    }
+    let _ = writeln!(out, "\nRegisters:\n{}\n", registers);
+    let _ = writeln!(out, "Backtrace:");
+    let _ = debug::backtrace::print(&mut out, arch::frame_pointer());
+    let _ = writeln!(out);
+
+    // Outside any test, so the whole run fails
+    #[cfg(test)]
+    testing::exit(testing::Outcome::Failure);
+
+    #[cfg(not(test))]
+    match action() {
+        Action::Halt => {
+            let _ = writeln!(out, "System halted.");
+            arch::halt()
+        }
+        Action::Reboot => reboot(&mut out),
+        Action::Debug => debug::monitor::run(&registers),
+    }
+}
+
+// Serial only and as short as possible: whatever the first report was
+// doing when it panicked may be what is broken
+fn nested_panic(info: &PanicInfo) -> ! {
+    unsafe { serial::force_unlock() };
+
+    let mut out = serial::Writer;
+    let _ = write!(out, "\n*** nested panic: {}", info.message());
+    if let Some(location) = info.location() {
+        let _ = write!(out, " at {}:{}", location.file(), location.line());
+    }
+    let _ = writeln!(out, " ***");
+    arch::halt()
+}
+
+#[cfg_attr(test, allow(dead_code))]
+fn reboot(out: &mut log::Writer) -> ! {
+    let frequency = timeline::counter_frequency();
+
+    // Without a calibrated counter we can't count seconds, so don't wait
+    if frequency != 0 {
+        for remaining in (1..=REBOOT_DELAY.load(Ordering::Relaxed)).rev() {
+            let _ = write!(out, "\rRebooting in {} s ", remaining);
+            let start = arch::read_cycle_counter();
+            while arch::read_cycle_counter().wrapping_sub(start) < frequency {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    let _ = writeln!(out, "\nRebooting");
+    power::reboot()
+}
@@ -0,0 +1,49 @@
+// kernel/src/power/aarch64.rs
+//
+// PSCI SYSTEM_OFF and SYSTEM_RESET, through whichever conduit the FADT or
+// the device tree's /psci node names. Without either, PSCI isn't tried at
+// all: the wrong conduit would be an undefined instruction.
+
+use crate::acpi::{self, fadt};
+use crate::arch::aarch64::psci::{self, Conduit};
+use crate::fdt;
+
+const PSCI_COMPATIBLE: &[&str] = &["arm,psci-1.0", "arm,psci-0.2"];
+
+// The power button is a GPIO or generic event device interrupt here,
+// which nothing handles yet
+pub fn init() {}
+
+pub fn power_off() {
+    if let Some(conduit) = conduit() {
+        let result = unsafe { psci::call(conduit, psci::SYSTEM_OFF, 0, 0, 0) };
+        warn!("PSCI SYSTEM_OFF returned {}", result);
+    }
+}
+
+pub fn reboot() {
+    if let Some(conduit) = conduit() {
+        let result = unsafe { psci::call(conduit, psci::SYSTEM_RESET, 0, 0, 0) };
+        warn!("PSCI SYSTEM_RESET returned {}", result);
+    }
+}
+
+fn conduit() -> Option<Conduit> {
+    if let Some(fadt) = acpi::find_table(fadt::SIGNATURE).and_then(fadt::Fadt::parse) {
+        if fadt.arm_boot_arch & fadt::PSCI_COMPLIANT != 0 {
+            return Some(match fadt.arm_boot_arch & fadt::PSCI_USE_HVC {
+                0 => Conduit::Smc,
+                _ => Conduit::Hvc,
+            });
+        }
+    }
+
+    match fdt::get()?
+        .find_compatible(PSCI_COMPATIBLE)?
+        .property("method")?
+    {
+        b"smc\0" => Some(Conduit::Smc),
+        b"hvc\0" => Some(Conduit::Hvc),
+        _ => None,
+    }
+}
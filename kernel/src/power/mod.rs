@@ -0,0 +1,64 @@
+// kernel/src/power/mod.rs
+//
+// Power off and reboot. Each platform tries its own mechanisms first (ACPI
+// and the keyboard controller on x86_64, PSCI on aarch64), then the
+// firmware's ResetSystem if it left us its runtime services. If nothing
+// works the CPU halts. On x86_64 the ACPI power button powers off too.
+
+use core::time::Duration;
+
+use crate::{arch, efi, time, timeline};
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+#[cfg(target_arch = "x86_64")]
+use x86_64 as platform;
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(target_arch = "aarch64")]
+use aarch64 as platform;
+
+// How long a reset or power-off request gets to take effect before the
+// next mechanism is tried
+const SETTLE_MS: u64 = 100;
+
+// Listen for the power button, where the platform can
+pub fn init() {
+    platform::init();
+}
+
+pub fn power_off() -> ! {
+    info!("powering off");
+    arch::disable_interrupts();
+    platform::power_off();
+
+    let status = efi::reset_system(efi::ResetType::Shutdown);
+    error!("power off failed ({:?}), system halted", status);
+    arch::halt()
+}
+
+pub fn reboot() -> ! {
+    info!("rebooting");
+    arch::disable_interrupts();
+    platform::reboot();
+
+    let status = efi::reset_system(efi::ResetType::Cold);
+    error!("reboot failed ({:?}), system halted", status);
+    arch::halt()
+}
+
+// Give a request that doesn't take effect immediately its chance. This
+// also runs from panics before time::init, when only the bootloader's
+// measure of the cycle counter is there to go by.
+fn settle() {
+    if time::frequency() != 0 {
+        time::sleep(Duration::from_millis(SETTLE_MS));
+        return;
+    }
+    let ticks = timeline::counter_frequency() * SETTLE_MS / 1000;
+    let start = arch::read_cycle_counter();
+    while arch::read_cycle_counter().wrapping_sub(start) < ticks {
+        core::hint::spin_loop();
+    }
+}
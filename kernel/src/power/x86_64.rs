@@ -0,0 +1,277 @@
+// kernel/src/power/x86_64.rs
+//
+// Power off by entering ACPI sleep state S5: the SLP_TYP values come from
+// the \_S5_ package in the DSDT or an SSDT and are written with SLP_EN to
+// the PM1 control registers (or the sleep control register on
+// hardware-reduced ACPI). Reboot through the FADT's reset register, then
+// by pulsing the CPU reset line from the 8042 keyboard controller.
+//
+// Pressing the power button raises the SCI, which powers the machine off.
+// The button is the only ACPI event enabled; GPEs stay off, as nothing
+// here can run their AML handlers.
+
+use crate::acpi::fadt::{self, Fadt};
+use crate::acpi::{self, aml, GenericAddress, SPACE_SYSTEM_IO, SPACE_SYSTEM_MEMORY};
+use crate::arch::x86_64::{idt, port};
+use crate::interrupts::{self, apic};
+use crate::mm::vmm;
+
+const S5: u8 = 5;
+
+// PM1 control register
+const PM1_SCI_EN: u16 = 1 << 0;
+const PM1_SLP_TYP_SHIFT: u16 = 10;
+const PM1_SLP_TYP_MASK: u16 = 0b111 << PM1_SLP_TYP_SHIFT;
+const PM1_SLP_EN: u16 = 1 << 13;
+
+// PM1 status and enable registers
+const PM1_PWRBTN: u16 = 1 << 8;
+
+// Hardware-reduced sleep control register
+const SLEEP_SLP_TYP_SHIFT: u8 = 2;
+const SLEEP_SLP_EN: u8 = 1 << 5;
+
+// How many settle periods to wait for SCI_EN after asking the SMI
+// handler for ACPI mode
+const ACPI_ENABLE_POLLS: usize = 30;
+
+const KBC_STATUS: u16 = 0x64;
+const KBC_COMMAND: u16 = 0x64;
+const KBC_INPUT_FULL: u8 = 1 << 1;
+const KBC_PULSE_RESET: u8 = 0xFE;
+
+// Route the SCI to a vector of our own and enable the power button. Skipped
+// on hardware-reduced ACPI, where the button is a GPIO or generic event
+// device with no fixed register.
+pub fn init() {
+    let Some(fadt) = acpi::find_table(fadt::SIGNATURE).and_then(Fadt::parse) else {
+        return;
+    };
+    if fadt.is_hardware_reduced() || fadt.pm1a_event.is_none() {
+        return;
+    }
+
+    enable_acpi_mode(&fadt);
+    for block in [fadt.pm1a_event, fadt.pm1b_event].into_iter().flatten() {
+        let (status, enable) = pm1_registers(&block);
+        write_register(&enable, PM1_PWRBTN as u64);
+        // Write-one-to-clear, for a press from before we were listening
+        write_register(&status, PM1_PWRBTN as u64);
+    }
+    for block in [fadt.gpe0, fadt.gpe1].into_iter().flatten() {
+        // Status bytes, then as many enable bytes
+        let half = block.bit_width as u64 / 16;
+        for offset in half..half * 2 {
+            let enable = GenericAddress {
+                bit_width: 8,
+                access_size: 0,
+                address: block.address + offset,
+                ..block
+            };
+            write_register(&enable, 0);
+        }
+    }
+
+    let Some(vector) = idt::allocate_vector() else {
+        warn!("no vector for the ACPI SCI");
+        return;
+    };
+    if !interrupts::register(vector as u32, sci_interrupt) {
+        idt::free_vector(vector);
+        return;
+    }
+    // Firmware describes the SCI with an ISA interrupt override (level
+    // triggered) when it's one of the ISA IRQs, as on QEMU and OVMF
+    if apic::route_isa_irq(fadt.sci_interrupt as u8, vector).is_none() {
+        warn!("can't route the ACPI SCI (IRQ {})", fadt.sci_interrupt);
+        interrupts::unregister(vector as u32);
+        idt::free_vector(vector);
+    }
+}
+
+fn sci_interrupt(_vector: u32) {
+    let Some(fadt) = acpi::find_table(fadt::SIGNATURE).and_then(Fadt::parse) else {
+        return;
+    };
+    let mut pressed = false;
+    for block in [fadt.pm1a_event, fadt.pm1b_event].into_iter().flatten() {
+        let (status, _) = pm1_registers(&block);
+        if read_register(&status).is_some_and(|value| value as u16 & PM1_PWRBTN != 0) {
+            write_register(&status, PM1_PWRBTN as u64);
+            pressed = true;
+        }
+    }
+    if pressed {
+        info!("power button pressed");
+        super::power_off();
+    }
+}
+
+pub fn power_off() {
+    let Some(fadt) = acpi::find_table(fadt::SIGNATURE).and_then(Fadt::parse) else {
+        return;
+    };
+    let Some(sleep_type) = find_s5() else {
+        warn!("no \\_S5_ package in the ACPI tables");
+        return;
+    };
+
+    if fadt.is_hardware_reduced() {
+        if let Some(control) = fadt.sleep_control {
+            let value = ((sleep_type.a & 0b111) << SLEEP_SLP_TYP_SHIFT) | SLEEP_SLP_EN;
+            write_register(&control, value as u64);
+        }
+        super::settle();
+        return;
+    }
+
+    enable_acpi_mode(&fadt);
+    let controls = [
+        (fadt.pm1a_control, sleep_type.a),
+        (fadt.pm1b_control, sleep_type.b),
+    ];
+    for (control, slp_typ) in controls {
+        let Some(control) = control else {
+            continue;
+        };
+        let value = read_register(&control).unwrap_or(0) as u16;
+        let value = value & !(PM1_SLP_TYP_MASK | PM1_SLP_EN)
+            | ((slp_typ & 0b111) as u16) << PM1_SLP_TYP_SHIFT;
+        write_register(&control, value as u64);
+    }
+    // SLP_EN goes to both registers at once, as far as software can
+    for control in [fadt.pm1a_control, fadt.pm1b_control].into_iter().flatten() {
+        let value = read_register(&control).unwrap_or(0) as u16;
+        write_register(&control, (value | PM1_SLP_EN) as u64);
+    }
+    super::settle();
+}
+
+pub fn reboot() {
+    if let Some(fadt) = acpi::find_table(fadt::SIGNATURE).and_then(Fadt::parse) {
+        if let (true, Some(register)) = (fadt.reset_supported(), fadt.reset_register) {
+            write_register(&register, fadt.reset_value as u64);
+            super::settle();
+        }
+    }
+
+    unsafe {
+        for _ in 0..0x10000 {
+            if port::inb(KBC_STATUS) & KBC_INPUT_FULL == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        port::outb(KBC_COMMAND, KBC_PULSE_RESET);
+    }
+    super::settle();
+}
+
+// The DSDT normally has \_S5_, some machines put it in an SSDT
+fn find_s5() -> Option<aml::SleepType> {
+    acpi::dsdt()
+        .into_iter()
+        .chain(acpi::tables().filter(|table| &table[..4] == b"SSDT"))
+        .find_map(|table| aml::find_sleep_type(table, S5))
+}
+
+// Firmware that boots in legacy mode hands the PM registers over when
+// asked through the SMI command port
+fn enable_acpi_mode(fadt: &Fadt) {
+    let Some(control) = fadt.pm1a_control else {
+        return;
+    };
+    let enabled = || read_register(&control).is_some_and(|value| value as u16 & PM1_SCI_EN != 0);
+    if enabled() || fadt.smi_command == 0 || fadt.acpi_enable == 0 {
+        return;
+    }
+
+    unsafe { port::outb(fadt.smi_command as u16, fadt.acpi_enable) };
+    for _ in 0..ACPI_ENABLE_POLLS {
+        if enabled() {
+            return;
+        }
+        super::settle();
+    }
+}
+
+// A PM1 event block is its status register followed by its enable register,
+// each half the block
+fn pm1_registers(block: &GenericAddress) -> (GenericAddress, GenericAddress) {
+    let status = GenericAddress {
+        bit_width: 16,
+        access_size: 0,
+        ..*block
+    };
+    let enable = GenericAddress {
+        address: block.address + block.bit_width as u64 / 16,
+        ..status
+    };
+    (status, enable)
+}
+
+// Registers in I/O or memory space, of the width the address gives (16
+// bits, the PM1 registers' width, when it doesn't)
+fn read_register(register: &GenericAddress) -> Option<u64> {
+    match register.space {
+        SPACE_SYSTEM_IO => {
+            let port = register.address as u16;
+            Some(unsafe {
+                match register_width(register) {
+                    8 => port::inb(port) as u64,
+                    32 | 64 => port::inl(port) as u64,
+                    _ => port::inw(port) as u64,
+                }
+            })
+        }
+        SPACE_SYSTEM_MEMORY => {
+            let address = vmm::map_mmio(register.address, 8).ok()?;
+            Some(unsafe {
+                match register_width(register) {
+                    8 => (address as *const u8).read_volatile() as u64,
+                    32 => (address as *const u32).read_volatile() as u64,
+                    64 => (address as *const u64).read_volatile(),
+                    _ => (address as *const u16).read_volatile() as u64,
+                }
+            })
+        }
+        _ => None,
+    }
+}
+
+fn write_register(register: &GenericAddress, value: u64) {
+    match register.space {
+        SPACE_SYSTEM_IO => {
+            let port = register.address as u16;
+            unsafe {
+                match register_width(register) {
+                    8 => port::outb(port, value as u8),
+                    32 | 64 => port::outl(port, value as u32),
+                    _ => port::outw(port, value as u16),
+                }
+            }
+        }
+        SPACE_SYSTEM_MEMORY => {
+            let Ok(address) = vmm::map_mmio(register.address, 8) else {
+                return;
+            };
+            unsafe {
+                match register_width(register) {
+                    8 => (address as *mut u8).write_volatile(value as u8),
+                    32 => (address as *mut u32).write_volatile(value as u32),
+                    64 => (address as *mut u64).write_volatile(value),
+                    _ => (address as *mut u16).write_volatile(value as u16),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Access size 1-4 is 8 to 64 bits, 0 leaves it to the register width
+fn register_width(register: &GenericAddress) -> u8 {
+    match register.access_size {
+        1..=4 => 8 << (register.access_size - 1),
+        _ => register.bit_width,
+    }
+}
@@ -0,0 +1,168 @@
+// kernel/src/serial.rs
+//
+// Serial console for early kernel output. Every architecture starts with a
+// port that works without any setup (COM1 and the 0xE9 debug console on
+// x86_64, the QEMU virt PL011 on aarch64), so output works from the first
+// instruction of _start. init() then switches to
+// the UART the firmware describes in the ACPI SPCR table or the device tree.
+
+use core::fmt;
+
+use crate::acpi::{self, spcr, spcr::Spcr};
+#[cfg(target_arch = "x86_64")]
+use crate::drivers::debugcon;
+use crate::drivers::pl011::Pl011;
+use crate::drivers::uart16550::{Access, Uart16550};
+use crate::fdt;
+use crate::sync::SpinLock;
+
+// COM1, set up by the firmware on every PC we care about
+#[cfg(target_arch = "x86_64")]
+const COM1_PORT: u16 = 0x3F8;
+
+// PL011 of the QEMU virt machine, used until the firmware tables are read
+#[cfg(target_arch = "aarch64")]
+const DEFAULT_PL011_BASE: usize = 0x0900_0000;
+
+const UART_COMPATIBLE: &[&str] = &["arm,pl011", "ns16550a"];
+
+enum Port {
+    Uart16550(Uart16550),
+    Pl011(Pl011),
+}
+
+impl Port {
+    const fn early() -> Self {
+        #[cfg(target_arch = "x86_64")]
+        return Port::Uart16550(Uart16550::new(Access::Port(COM1_PORT)));
+        #[cfg(target_arch = "aarch64")]
+        return Port::Pl011(Pl011::new(DEFAULT_PL011_BASE));
+    }
+
+    fn write_byte(&self, byte: u8) {
+        match self {
+            Port::Uart16550(uart) => uart.write_byte(byte),
+            Port::Pl011(uart) => uart.write_byte(byte),
+        }
+    }
+
+    fn mmio_window(&self) -> Option<(u64, u64)> {
+        match self {
+            Port::Uart16550(uart) => uart.mmio_window(),
+            Port::Pl011(uart) => Some(uart.mmio_window()),
+        }
+    }
+
+    fn read_byte(&self) -> Option<u8> {
+        match self {
+            Port::Uart16550(uart) => uart.read_byte(),
+            Port::Pl011(uart) => uart.read_byte(),
+        }
+    }
+}
+
+static SERIAL: SpinLock<Port> = SpinLock::new(Port::early());
+
+// Switch to the console UART described by the firmware, if there is one.
+// Needs acpi::init() and fdt::init().
+pub fn init() {
+    let port = spcr_port().or_else(device_tree_port);
+
+    let mut serial = SERIAL.lock();
+    if let Some(port) = port {
+        *serial = port;
+    }
+
+    match &*serial {
+        Port::Uart16550(uart) => uart.init(),
+        Port::Pl011(uart) => uart.init(),
+    }
+}
+
+// Where the current port's registers are, if they are memory mapped, as a
+// physical base and length. vmm::init() keeps them mapped.
+pub fn mmio_window() -> Option<(u64, u64)> {
+    SERIAL.lock().mmio_window()
+}
+
+// Write raw bytes, turning "\n" into "\r\n" for terminals
+pub fn write_bytes(bytes: &[u8]) {
+    let serial = SERIAL.lock();
+    for &byte in bytes {
+        if byte == b'\n' {
+            write_byte(&serial, b'\r');
+        }
+        write_byte(&serial, byte);
+    }
+}
+
+pub fn write_str(s: &str) {
+    write_bytes(s.as_bytes());
+}
+
+// Poll for a received byte
+pub fn read_byte() -> Option<u8> {
+    SERIAL.lock().read_byte()
+}
+
+// See SpinLock::force_unlock
+pub unsafe fn force_unlock() {
+    SERIAL.force_unlock();
+}
+
+// Serial only, for output that shouldn't reach the screen or the kernel log
+pub struct Writer;
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_str(s);
+        Ok(())
+    }
+}
+
+fn write_byte(port: &Port, byte: u8) {
+    port.write_byte(byte);
+
+    #[cfg(target_arch = "x86_64")]
+    debugcon::write_byte(byte);
+}
+
+// The UART the ACPI SPCR table names
+fn spcr_port() -> Option<Port> {
+    let spcr = acpi::find_table(spcr::SIGNATURE).and_then(Spcr::parse)?;
+    let address = spcr.base.address;
+    if address == 0 {
+        return None;
+    }
+
+    let is_16550 = matches!(
+        spcr.interface_type,
+        spcr::INTERFACE_16550 | spcr::INTERFACE_16450
+    );
+    let is_pl011 = matches!(
+        spcr.interface_type,
+        spcr::INTERFACE_PL011 | spcr::INTERFACE_SBSA
+    );
+    match spcr.base.space {
+        #[cfg(target_arch = "x86_64")]
+        acpi::SPACE_SYSTEM_IO if is_16550 => Some(Port::Uart16550(Uart16550::new(Access::Port(
+            address as u16,
+        )))),
+        acpi::SPACE_SYSTEM_MEMORY if is_16550 => Some(Port::Uart16550(Uart16550::new(
+            Access::Mmio(address as usize),
+        ))),
+        acpi::SPACE_SYSTEM_MEMORY if is_pl011 => Some(Port::Pl011(Pl011::new(address as usize))),
+        _ => None,
+    }
+}
+
+// The first PL011 or 16550 in the device tree, wherever it sits
+fn device_tree_port() -> Option<Port> {
+    let node = fdt::get()?.find_compatible(UART_COMPATIBLE)?;
+    let (base, _) = node.reg().next()?;
+    let base = base as usize;
+    match node.is_compatible("arm,pl011") {
+        true => Some(Port::Pl011(Pl011::new(base))),
+        false => Some(Port::Uart16550(Uart16550::new(Access::Mmio(base)))),
+    }
+}
@@ -0,0 +1,82 @@
+// kernel/src/smp.rs
+//
+// Processor topology reported by the bootloader from EFI_MP_SERVICES_PROTOCOL.
+
+use crate::sync::SpinLock;
+use crate::BootInfo;
+
+// Must match MAX_CPUS in the bootloader
+pub const MAX_CPUS: usize = 64;
+
+pub const CPU_FLAG_BSP: u32 = 1 << 0;
+pub const CPU_FLAG_ENABLED: u32 = 1 << 1;
+pub const CPU_FLAG_HEALTHY: u32 = 1 << 2;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct CpuInfo {
+    // Local APIC ID on x86_64, MPIDR affinity bits on aarch64
+    pub hw_id: u64,
+    pub package: u32,
+    pub core: u32,
+    pub thread: u32,
+    pub flags: u32,
+}
+
+impl CpuInfo {
+    pub fn is_bsp(&self) -> bool {
+        self.flags & CPU_FLAG_BSP != 0
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.flags & CPU_FLAG_ENABLED != 0
+    }
+}
+
+struct Topology {
+    cpus: [CpuInfo; MAX_CPUS],
+    count: usize,
+}
+
+static TOPOLOGY: SpinLock<Topology> = SpinLock::new(Topology {
+    cpus: [CpuInfo { hw_id: 0, package: 0, core: 0, thread: 0, flags: 0 }; MAX_CPUS],
+    count: 0,
+});
+
+pub fn init(boot_info: &BootInfo) {
+    let mut topology = TOPOLOGY.lock();
+    topology.count = boot_info.cpu_count.min(MAX_CPUS);
+    topology.cpus = boot_info.cpus;
+
+    // Older bootloaders pass no topology, we still run on the BSP
+    if topology.count == 0 {
+        topology.cpus[0].flags = CPU_FLAG_BSP | CPU_FLAG_ENABLED | CPU_FLAG_HEALTHY;
+        topology.count = 1;
+    }
+
+    // Nothing starts the other CPUs yet, so the topology is only reported
+    let cpus = &topology.cpus[..topology.count];
+    let enabled = cpus.iter().filter(|cpu| cpu.is_enabled()).count();
+    info!("{} processors, {} enabled", cpus.len(), enabled);
+    for (index, cpu) in cpus.iter().enumerate() {
+        debug!(
+            "cpu {}: id {:#x}, package {} core {} thread {}{}{}",
+            index,
+            cpu.hw_id,
+            cpu.package,
+            cpu.core,
+            cpu.thread,
+            if cpu.is_bsp() { ", bsp" } else { "" },
+            if cpu.is_enabled() { "" } else { ", disabled" },
+        );
+    }
+}
+
+pub fn bsp() -> CpuInfo {
+    let topology = TOPOLOGY.lock();
+    topology.cpus[..topology.count]
+        .iter()
+        .copied()
+        .find(CpuInfo::is_bsp)
+        .unwrap_or(topology.cpus[0])
+}
@@ -0,0 +1,97 @@
+// kernel/src/testing/aarch64.rs
+//
+// Semihosting SYS_EXIT, which QEMU turns into its own exit status when
+// started with -semihosting. Without that option the HLT is an undefined
+// instruction, so it's only used when the command line says `semihosting`;
+// otherwise the caller falls back to a PSCI power off, which always exits
+// with 0.
+
+use core::arch::{asm, global_asm};
+
+use super::Outcome;
+use crate::cmdline;
+
+const SYS_EXIT: u64 = 0x18;
+// ADP_Stopped_ApplicationExit: the second word is the exit status
+const APPLICATION_EXIT: u64 = 0x20026;
+
+// X19-X30, SP and D8-D15
+#[repr(C)]
+pub struct Context([u64; 21]);
+
+impl Context {
+    pub const fn new() -> Context {
+        Context([0; 21])
+    }
+}
+
+pub fn exit(outcome: Outcome) {
+    if !cmdline::get().flag("semihosting") {
+        return;
+    }
+    let status = match outcome {
+        Outcome::Success => 0,
+        Outcome::Failure => 1,
+    };
+    let block = [APPLICATION_EXIT, status];
+    unsafe {
+        asm!(
+            "hlt #0xf000",
+            inlateout("x0") SYS_EXIT => _,
+            in("x1") block.as_ptr(),
+            options(nostack)
+        );
+    }
+}
+
+extern "C" {
+    // Save the callee-saved registers to `context` and call `f(arg)`.
+    // Returns true when `f` returns, false when abort_guarded is called on
+    // the same context from anywhere below it.
+    pub fn run_guarded(context: *mut Context, f: extern "C" fn(usize), arg: usize) -> bool;
+    pub fn abort_guarded(context: *const Context) -> !;
+}
+
+global_asm!(
+    ".section .text.run_guarded, \"ax\"",
+    ".global run_guarded",
+    ".global abort_guarded",
+    "run_guarded:",
+    "    stp x19, x20, [x0, #0]",
+    "    stp x21, x22, [x0, #16]",
+    "    stp x23, x24, [x0, #32]",
+    "    stp x25, x26, [x0, #48]",
+    "    stp x27, x28, [x0, #64]",
+    "    stp x29, x30, [x0, #80]",
+    "    mov x9, sp",
+    "    str x9, [x0, #96]",
+    "    stp d8, d9, [x0, #104]",
+    "    stp d10, d11, [x0, #120]",
+    "    stp d12, d13, [x0, #136]",
+    "    stp d14, d15, [x0, #152]",
+    "    stp x29, x30, [sp, #-16]!",
+    "    mov x29, sp",
+    "    mov x9, x1",
+    "    mov x0, x2",
+    "    blr x9",
+    "    ldp x29, x30, [sp], #16",
+    "    mov w0, #1",
+    "    ret",
+    // X30 comes back as run_guarded's return address
+    "abort_guarded:",
+    "    ldp x19, x20, [x0, #0]",
+    "    ldp x21, x22, [x0, #16]",
+    "    ldp x23, x24, [x0, #32]",
+    "    ldp x25, x26, [x0, #48]",
+    "    ldp x27, x28, [x0, #64]",
+    "    ldp x29, x30, [x0, #80]",
+    "    ldr x9, [x0, #96]",
+    "    mov sp, x9",
+    "    ldp d8, d9, [x0, #104]",
+    "    ldp d10, d11, [x0, #120]",
+    "    ldp d12, d13, [x0, #136]",
+    "    ldp d14, d15, [x0, #152]",
+    "    mov w0, #0",
+    "    ret",
+    ".previous",
+);
@@ -0,0 +1,242 @@
+// kernel/src/testing/mod.rs
+//
+// In-kernel test harness for `cargo test`. The custom test framework
+// collects every #[test_case] function and the kernel calls run() with them
+// once it is up. Tests run one at a time with their results on the serial
+// port, then QEMU is told to exit with a status that test-runner.bat turns
+// into pass or fail.
+//
+// Each test runs guarded: the platform's run_guarded saves the callee-saved
+// registers and the stack pointer, and a panic, in the test or in an
+// interrupt taken while it ran, jumps back there instead of stopping the
+// kernel, failing only that test. Whatever the test was in the middle of
+// is abandoned as is, so locks it held stay held. A timer panics any test
+// still running after TIMEOUT; without an event timer there are no
+// timeouts.
+//
+// A test can also be expected to panic or to time out, see ShouldFail. It
+// passes by failing that way, which is how the harness tests itself.
+
+use core::any;
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::time::Duration;
+
+use crate::time::{timers, Instant};
+use crate::{arch, interrupts, power, serial};
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+#[cfg(target_arch = "x86_64")]
+use x86_64 as platform;
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(target_arch = "aarch64")]
+use aarch64 as platform;
+
+mod tests;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// How a test ended, or has to end to pass
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ending {
+    Returned,
+    Panicked,
+    TimedOut,
+}
+
+pub trait Test {
+    fn name(&self) -> &'static str;
+    fn run(&self);
+
+    fn expected(&self) -> Ending {
+        Ending::Returned
+    }
+}
+
+impl<T: Fn()> Test for T {
+    fn name(&self) -> &'static str {
+        any::type_name::<T>()
+    }
+
+    fn run(&self) {
+        self()
+    }
+}
+
+// A test that passes only by panicking or timing out. Its name is spelled
+// out, as a static has no type of its own to take it from:
+//
+//     #[test_case]
+//     static PANICS: ShouldFail = ShouldFail {
+//         name: concat!(module_path!(), "::panics"),
+//         ending: Ending::Panicked,
+//         test: || panic!("on purpose"),
+//     };
+pub struct ShouldFail {
+    pub name: &'static str,
+    pub ending: Ending,
+    pub test: fn(),
+}
+
+impl Test for ShouldFail {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn run(&self) {
+        (self.test)()
+    }
+
+    fn expected(&self) -> Ending {
+        self.ending
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+// Whether a test is running under GUARD, so a panic goes back to it
+static RUNNING: AtomicBool = AtomicBool::new(false);
+// How the running test should end, as an Ending, and whether its timeout
+// fired, for the panic handler
+static EXPECTED: AtomicU8 = AtomicU8::new(0);
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+// Only touched by run_guarded and abort_guarded
+static mut GUARD: platform::Context = platform::Context::new();
+
+pub fn run(tests: &[&dyn Test]) -> ! {
+    let mut out = serial::Writer;
+    let _ = writeln!(out, "\nrunning {} tests", tests.len());
+
+    let mut failed = 0;
+    for &test in tests {
+        let _ = write!(out, "test {} ... ", test.name());
+        let start = Instant::now();
+        let ending = run_guarded(test);
+        if ending != test.expected() {
+            failed += 1;
+        }
+        // After a panic the handler has already given the verdict
+        match (ending, test.expected()) {
+            (Ending::Returned, Ending::Returned) => {
+                let _ = writeln!(out, "ok ({} ms)", start.elapsed().as_millis());
+            }
+            (Ending::Returned, Ending::Panicked) => {
+                let _ = writeln!(out, "FAILED\n    returned, but should have panicked");
+            }
+            (Ending::Returned, Ending::TimedOut) => {
+                let _ = writeln!(out, "FAILED\n    returned, but should have timed out");
+            }
+            _ => {}
+        }
+    }
+
+    let _ = writeln!(
+        out,
+        "\ntest result: {}. {} passed; {} failed",
+        if failed == 0 { "ok" } else { "FAILED" },
+        tests.len() - failed,
+        failed
+    );
+    exit(match failed {
+        0 => Outcome::Success,
+        _ => Outcome::Failure,
+    })
+}
+
+// Run `test`, returning how it ended
+fn run_guarded(test: &dyn Test) -> Ending {
+    EXPECTED.store(test.expected() as u8, Ordering::Relaxed);
+    TIMED_OUT.store(false, Ordering::Relaxed);
+    let timeout = timers::set_timeout(TIMEOUT, timed_out, 0);
+    RUNNING.store(true, Ordering::Release);
+    let completed = unsafe {
+        platform::run_guarded(
+            addr_of_mut!(GUARD),
+            trampoline,
+            &test as *const &dyn Test as usize,
+        )
+    };
+
+    // Interrupts are off either way, so the timeout can't fire from here
+    timers::cancel(timeout);
+    if !completed {
+        // The panic may have come from an interrupt handler, the timeout's
+        // included, which never got to finish
+        interrupts::end_abandoned();
+        timers::rearm();
+    }
+    arch::enable_interrupts();
+    match (completed, TIMED_OUT.load(Ordering::Relaxed)) {
+        (true, _) => Ending::Returned,
+        (false, false) => Ending::Panicked,
+        (false, true) => Ending::TimedOut,
+    }
+}
+
+extern "C" fn trampoline(test: usize) {
+    let test = unsafe { *(test as *const &dyn Test) };
+    test.run();
+    arch::disable_interrupts();
+    RUNNING.store(false, Ordering::Release);
+}
+
+fn timed_out(_arg: usize) {
+    if RUNNING.load(Ordering::Acquire) {
+        TIMED_OUT.store(true, Ordering::Relaxed);
+        panic!("timed out after {} s", TIMEOUT.as_secs());
+    }
+}
+
+// Called first by the panic handler. Ends the running test, failing it
+// unless it should have ended this way, and resumes run() after it; returns
+// if no test is running, leaving the handler to report the panic as usual.
+pub fn panicked(info: &PanicInfo) {
+    arch::disable_interrupts();
+    if !RUNNING.swap(false, Ordering::AcqRel) {
+        return;
+    }
+
+    // The test may have panicked while printing
+    unsafe { serial::force_unlock() };
+    let ending = match TIMED_OUT.load(Ordering::Relaxed) {
+        true => Ending::TimedOut,
+        false => Ending::Panicked,
+    };
+    let verdict = match ending == expected_ending() {
+        true => "ok, failed as expected",
+        false => "FAILED",
+    };
+    let mut out = serial::Writer;
+    let _ = write!(out, "{}\n    {}", verdict, info.message());
+    if let Some(location) = info.location() {
+        let _ = write!(out, " at {}:{}", location.file(), location.line());
+    }
+    let _ = writeln!(out);
+
+    unsafe { platform::abort_guarded(addr_of!(GUARD)) }
+}
+
+fn expected_ending() -> Ending {
+    match EXPECTED.load(Ordering::Relaxed) {
+        1 => Ending::Panicked,
+        2 => Ending::TimedOut,
+        _ => Ending::Returned,
+    }
+}
+
+// Tell QEMU to exit with `outcome`. Powers off if the platform has no way
+// to, leaving the serial log as the only record of the result.
+pub fn exit(outcome: Outcome) -> ! {
+    platform::exit(outcome);
+    power::power_off()
+}
@@ -0,0 +1,29 @@
+// kernel/src/testing/tests.rs
+//
+// The harness itself: a test that passes, one that panics and one that
+// hangs. The last two only pass if the harness catches them and carries
+// on with the next test.
+
+use super::{Ending, ShouldFail};
+
+#[test_case]
+fn passes() {
+    assert_eq!(core::hint::black_box(1) + 1, 2);
+}
+
+#[test_case]
+static PANICS: ShouldFail = ShouldFail {
+    name: concat!(module_path!(), "::panics"),
+    ending: Ending::Panicked,
+    test: || panic!("on purpose"),
+};
+
+// Takes TIMEOUT to run
+#[test_case]
+static HANGS: ShouldFail = ShouldFail {
+    name: concat!(module_path!(), "::hangs"),
+    ending: Ending::TimedOut,
+    test: || loop {
+        core::hint::spin_loop();
+    },
+};
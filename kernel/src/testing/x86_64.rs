@@ -0,0 +1,75 @@
+// kernel/src/testing/x86_64.rs
+//
+// QEMU's isa-debug-exit device (-device isa-debug-exit,iobase=0xf4,
+// iosize=0x04) exits with status (value << 1) | 1 for whatever is written
+// to it: 33 for success, 35 for failure. Without the device the write does
+// nothing.
+
+use core::arch::global_asm;
+
+use super::Outcome;
+use crate::arch::x86_64::port;
+
+const DEBUG_EXIT_PORT: u16 = 0xF4;
+const EXIT_SUCCESS: u32 = 0x10;
+const EXIT_FAILURE: u32 = 0x11;
+
+// RBX, RBP, R12-R15 and RSP
+#[repr(C)]
+pub struct Context([u64; 7]);
+
+impl Context {
+    pub const fn new() -> Context {
+        Context([0; 7])
+    }
+}
+
+pub fn exit(outcome: Outcome) {
+    let value = match outcome {
+        Outcome::Success => EXIT_SUCCESS,
+        Outcome::Failure => EXIT_FAILURE,
+    };
+    unsafe { port::outl(DEBUG_EXIT_PORT, value) };
+}
+
+extern "C" {
+    // Save the callee-saved registers to `context` and call `f(arg)`.
+    // Returns true when `f` returns, false when abort_guarded is called on
+    // the same context from anywhere below it.
+    pub fn run_guarded(context: *mut Context, f: extern "C" fn(usize), arg: usize) -> bool;
+    pub fn abort_guarded(context: *const Context) -> !;
+}
+
+global_asm!(
+    ".section .text.run_guarded, \"ax\"",
+    ".global run_guarded",
+    ".global abort_guarded",
+    "run_guarded:",
+    "    mov [rdi], rbx",
+    "    mov [rdi + 8], rbp",
+    "    mov [rdi + 16], r12",
+    "    mov [rdi + 24], r13",
+    "    mov [rdi + 32], r14",
+    "    mov [rdi + 40], r15",
+    "    mov [rdi + 48], rsp",
+    "    mov rax, rsi",
+    "    mov rdi, rdx",
+    // Keep the stack 16-byte aligned at the call
+    "    sub rsp, 8",
+    "    call rax",
+    "    add rsp, 8",
+    "    mov eax, 1",
+    "    ret",
+    // RSP goes back to pointing at run_guarded's return address
+    "abort_guarded:",
+    "    mov rbx, [rdi]",
+    "    mov rbp, [rdi + 8]",
+    "    mov r12, [rdi + 16]",
+    "    mov r13, [rdi + 24]",
+    "    mov r14, [rdi + 32]",
+    "    mov r15, [rdi + 40]",
+    "    mov rsp, [rdi + 48]",
+    "    xor eax, eax",
+    "    ret",
+    ".previous",
+);
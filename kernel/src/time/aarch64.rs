@@ -0,0 +1,126 @@
+// kernel/src/time/aarch64.rs
+//
+// The generic timer's system count is the clock, at the frequency firmware
+// wrote to CNTFRQ_EL0. Events come from the EL1 physical timer's PPI, and
+// the date from a PL031 if the device tree has one. The PL031 is a device
+// tree driver, bound by fdt::probe() after time::init(), so until then the
+// wall clock reads the firmware's GetTime.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::wall::{self, DateTime};
+use super::{timers, NANOS_PER_SECOND};
+use crate::arch::aarch64::timer;
+use crate::drivers::gic;
+use crate::drivers::pl031::Pl031;
+use crate::fdt;
+use crate::interrupts::{self, Trigger};
+use crate::mm::vmm;
+
+const TIMER_COMPATIBLE: &[&str] = &["arm,armv8-timer", "arm,armv7-timer"];
+// Index of the non-secure EL1 physical timer in the timer node's
+// interrupts, after the secure one
+const PHYSICAL_TIMER_INDEX: usize = 1;
+
+const PL031_SIZE: u64 = 0x1000;
+
+static PL031_DRIVER: fdt::Driver = fdt::Driver {
+    name: "pl031",
+    compatible: &["arm,pl031"],
+    probe: probe_pl031,
+};
+
+// Mapped PL031 registers, 0 if there is none
+static RTC_BASE: AtomicU64 = AtomicU64::new(0);
+
+pub const CLOCK_NAME: &str = "CNTPCT_EL0";
+
+pub fn calibrate() -> (u64, &'static str) {
+    (timer::frequency(), "CNTFRQ_EL0")
+}
+
+// Needs the GIC to be up
+pub fn init_events() -> bool {
+    if gic::version().is_none() {
+        return false;
+    }
+    let (intid, trigger) = timer_interrupt_line();
+    if !interrupts::register(intid, timer_interrupt) {
+        return false;
+    }
+    gic::set_trigger(intid, trigger);
+    gic::enable(intid);
+    true
+}
+
+// From the device tree's timer node, else the usual PPI
+fn timer_interrupt_line() -> (u32, Trigger) {
+    fdt::get()
+        .and_then(|fdt| fdt.find_compatible(TIMER_COMPATIBLE))
+        .and_then(|node| node.interrupts().nth(PHYSICAL_TIMER_INDEX))
+        .and_then(|interrupt| interrupts::gic::decode_interrupt(&interrupt))
+        .unwrap_or((timer::PHYSICAL_TIMER_INTID, Trigger::Level))
+}
+
+// Raise the timer interrupt `delay_ns` from now, as soon as possible if 0
+pub fn set_event(delay_ns: u64) {
+    let ticks = delay_ns as u128 * super::frequency() as u128 / NANOS_PER_SECOND as u128;
+    timer::set_physical_timer(ticks.min(u32::MAX as u128) as u32);
+}
+
+fn timer_interrupt(_intid: u32) {
+    // The PPI stays asserted until the timer is reprogrammed or stopped,
+    // and expire() only reprograms it if something is still pending
+    timer::stop_physical_timer();
+    timers::expire();
+}
+
+// There's no hardware clock yet, the PL031 driver hands one over when it
+// binds
+pub fn rtc_init() -> Option<&'static str> {
+    fdt::register(&PL031_DRIVER);
+    None
+}
+
+fn probe_pl031(node: fdt::Node<'static>) -> bool {
+    // The first one found is the clock
+    if RTC_BASE.load(Ordering::Relaxed) != 0 {
+        return false;
+    }
+    let Some((phys, _)) = node.reg().next() else {
+        return false;
+    };
+    let Ok(base) = vmm::map_mmio(phys, PL031_SIZE) else {
+        return false;
+    };
+    unsafe { Pl031::new(base) }.init();
+    RTC_BASE.store(base, Ordering::Relaxed);
+    wall::use_hardware_clock("PL031");
+    true
+}
+
+pub fn rtc_read() -> Option<DateTime> {
+    let rtc = rtc()?;
+    Some(DateTime::from_unix(rtc.seconds() as u64, 0))
+}
+
+// The PL031 counts to 2106
+pub fn rtc_write(time: &DateTime) -> bool {
+    let Some(rtc) = rtc() else {
+        return false;
+    };
+    match time.to_unix().map(u32::try_from) {
+        Some(Ok(seconds)) => {
+            rtc.set_seconds(seconds);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn rtc() -> Option<Pl031> {
+    match RTC_BASE.load(Ordering::Relaxed) {
+        0 => None,
+        base => Some(unsafe { Pl031::new(base) }),
+    }
+}
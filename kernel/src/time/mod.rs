@@ -0,0 +1,174 @@
+// kernel/src/time/mod.rs
+//
+// Monotonic time, timers and the date. The clock is the architecture's
+// cycle counter (the TSC, the generic timer's system count, the time CSR)
+// at a frequency found once at boot; Instant is that count converted to
+// nanoseconds. Each platform also has a one-shot event timer, which
+// timers.rs keeps programmed for the earliest pending deadline, and maybe
+// a real-time clock for wall.rs.
+//
+// A periodic timer counts TICK_HZ ticks a second.
+
+use core::ops::{Add, Sub};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::arch;
+use crate::timeline;
+
+pub mod timers;
+pub mod wall;
+
+#[cfg(test)]
+mod tests;
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+#[cfg(target_arch = "x86_64")]
+use x86_64 as platform;
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(target_arch = "aarch64")]
+use aarch64 as platform;
+
+pub const TICK_HZ: u64 = 100;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+// Counter ticks per second, 0 until init()
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+// Whether the platform's event timer is running timers
+static EVENTS: AtomicBool = AtomicBool::new(false);
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+// A point on the monotonic clock, in nanoseconds since the counter started
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant(counter_to_nanos(arch::read_cycle_counter()))
+    }
+
+    pub fn as_nanos(self) -> u64 {
+        self.0
+    }
+
+    // Zero if `earlier` is actually later
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn elapsed(self) -> Duration {
+        Instant::now().duration_since(self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+        Instant(self.0.saturating_add(nanos))
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+// A point in the future to give up waiting at, for polling loops
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn after(timeout: Duration) -> Deadline {
+        Deadline(Instant::now() + timeout)
+    }
+
+    pub fn has_passed(&self) -> bool {
+        Instant::now() >= self.0
+    }
+}
+
+// Find the counter frequency, read the date and start the event timer.
+// Needs the interrupt controller; interrupts can be enabled afterwards.
+pub fn init() {
+    let (mut frequency, mut source) = platform::calibrate();
+    if frequency == 0 {
+        frequency = timeline::counter_frequency();
+        source = "the bootloader";
+    }
+    FREQUENCY.store(frequency, Ordering::Relaxed);
+    info!(
+        "clock: {} at {}.{:03} MHz, from {}",
+        platform::CLOCK_NAME,
+        frequency / 1_000_000,
+        frequency / 1_000 % 1_000,
+        source
+    );
+    wall::init();
+
+    if !platform::init_events() {
+        warn!("no event timer, timers will not fire");
+        return;
+    }
+    EVENTS.store(true, Ordering::Relaxed);
+    timers::set_interval(Duration::from_nanos(NANOS_PER_SECOND / TICK_HZ), tick, 0);
+}
+
+// Counter ticks per second
+pub fn frequency() -> u64 {
+    FREQUENCY.load(Ordering::Relaxed)
+}
+
+// Periodic ticks since init()
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+fn tick(_arg: usize) {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+// Block the calling CPU for at least `duration`. Halts between interrupts
+// when timers can wake it, spins otherwise.
+pub fn sleep(duration: Duration) {
+    let deadline = Deadline::after(duration);
+    if !EVENTS.load(Ordering::Relaxed) || !arch::interrupts_enabled() {
+        while !deadline.has_passed() {
+            core::hint::spin_loop();
+        }
+        return;
+    }
+
+    let woken = AtomicBool::new(false);
+    let timer = timers::set_timeout(duration, wake, &woken as *const AtomicBool as usize);
+    // Checked with interrupts masked, so the wakeup can't land between the
+    // check and the halt
+    loop {
+        arch::disable_interrupts();
+        if woken.load(Ordering::Acquire) {
+            arch::enable_interrupts();
+            break;
+        }
+        arch::enable_interrupts_and_wait();
+    }
+    timers::cancel(timer);
+}
+
+fn wake(flag: usize) {
+    unsafe { (*(flag as *const AtomicBool)).store(true, Ordering::Release) }
+}
+
+fn counter_to_nanos(count: u64) -> u64 {
+    match frequency() {
+        0 => 0,
+        frequency => (count as u128 * NANOS_PER_SECOND as u128 / frequency as u128) as u64,
+    }
+}
@@ -0,0 +1,51 @@
+// kernel/src/time/tests.rs
+//
+// The monotonic clock, and sleeping on it.
+
+use super::*;
+
+#[test_case]
+fn instant_is_monotonic() {
+    assert!(frequency() > 0);
+    let mut last = Instant::now();
+    for _ in 0..1000 {
+        let now = Instant::now();
+        assert!(now >= last);
+        last = now;
+    }
+}
+
+#[test_case]
+fn instant_arithmetic() {
+    let now = Instant::now();
+    let later = now + Duration::from_millis(5);
+    assert_eq!(later - now, Duration::from_millis(5));
+    assert_eq!(later.duration_since(now), Duration::from_millis(5));
+    // Saturates rather than going negative
+    assert_eq!(now.duration_since(later), Duration::ZERO);
+    assert_eq!(now + Duration::MAX, Instant(u64::MAX));
+}
+
+#[test_case]
+fn sleep_and_deadline() {
+    let start = Instant::now();
+    sleep(Duration::from_millis(20));
+    let slept = start.elapsed();
+    assert!(slept >= Duration::from_millis(20), "slept {:?}", slept);
+    assert!(slept < Duration::from_secs(1), "slept {:?}", slept);
+
+    let deadline = Deadline::after(Duration::from_millis(10));
+    assert!(!deadline.has_passed());
+    while !deadline.has_passed() {
+        core::hint::spin_loop();
+    }
+    assert!(start.elapsed() >= Duration::from_millis(30));
+}
+
+// The periodic tick keeps counting
+#[test_case]
+fn periodic_tick() {
+    let before = ticks();
+    sleep(Duration::from_millis(3 * 1000 / TICK_HZ));
+    assert!(ticks() > before);
+}
@@ -0,0 +1,160 @@
+// kernel/src/time/timers.rs
+//
+// Pending timers, in a min-heap keyed by deadline. The platform's one-shot
+// event timer is always set for the earliest one; its interrupt calls
+// expire(), which runs every callback that is due and sets the event
+// timer again.
+//
+// Callbacks run in interrupt context with interrupts masked, so they must
+// not allocate, which also rules out setting new timers from them.
+// Periodic timers are put back into the slot they came out of.
+
+use alloc::collections::BinaryHeap;
+use core::cmp::{Ordering, Reverse};
+use core::sync::atomic::{self, AtomicU64};
+use core::time::Duration;
+
+use super::{platform, Instant};
+use crate::arch;
+use crate::sync::SpinLock;
+
+#[cfg(test)]
+mod tests;
+
+pub type Callback = fn(arg: usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+struct Timer {
+    deadline: Instant,
+    id: u64,
+    period: Option<Duration>,
+    callback: Callback,
+    arg: usize,
+}
+
+// Earliest deadline first, ties in the order the timers were set
+impl Ord for Timer {
+    fn cmp(&self, other: &Timer) -> Ordering {
+        (self.deadline, self.id).cmp(&(other.deadline, other.id))
+    }
+}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Timer) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Timer) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Timer {}
+
+static TIMERS: SpinLock<BinaryHeap<Reverse<Timer>>> = SpinLock::new(BinaryHeap::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+// Call `callback(arg)` once, `delay` from now
+pub fn set_timeout(delay: Duration, callback: Callback, arg: usize) -> TimerId {
+    add(Instant::now() + delay, None, callback, arg)
+}
+
+// Call `callback(arg)` every `period`, starting one period from now
+pub fn set_interval(period: Duration, callback: Callback, arg: usize) -> TimerId {
+    add(Instant::now() + period, Some(period), callback, arg)
+}
+
+// Returns false if the timer already fired (and wasn't periodic) or was
+// cancelled before
+pub fn cancel(id: TimerId) -> bool {
+    with_timers(|timers| {
+        let before = timers.len();
+        timers.retain(|Reverse(timer)| timer.id != id.0);
+        timers.len() != before
+    })
+}
+
+fn add(deadline: Instant, period: Option<Duration>, callback: Callback, arg: usize) -> TimerId {
+    let id = NEXT_ID.fetch_add(1, atomic::Ordering::Relaxed);
+    with_timers(|timers| {
+        timers.push(Reverse(Timer {
+            deadline,
+            id,
+            period,
+            callback,
+            arg,
+        }));
+        if timers.peek().is_some_and(|Reverse(first)| first.id == id) {
+            program(deadline);
+        }
+    });
+    TimerId(id)
+}
+
+// Event timer interrupt: run whatever is due, then wait for the next one
+pub fn expire() {
+    loop {
+        let now = Instant::now();
+        let due = {
+            let mut timers = TIMERS.lock();
+            match timers.peek() {
+                Some(Reverse(first)) if first.deadline <= now => timers.pop(),
+                Some(Reverse(first)) => {
+                    program(first.deadline);
+                    None
+                }
+                None => None,
+            }
+        };
+        let Some(Reverse(mut timer)) = due else {
+            return;
+        };
+
+        (timer.callback)(timer.arg);
+
+        if let Some(period) = timer.period {
+            timer.deadline = next_deadline(timer.deadline, period, now);
+            TIMERS.lock().push(Reverse(timer));
+        }
+    }
+}
+
+// The first whole period after `now` that's in step with `deadline`. The
+// periods that were missed are skipped rather than fired for each, and a
+// late interrupt doesn't shift the ones after it.
+fn next_deadline(deadline: Instant, period: Duration, now: Instant) -> Instant {
+    let period = (period.as_nanos().min(u64::MAX as u128) as u64).max(1);
+    let missed = now.duration_since(deadline).as_nanos() as u64 / period;
+    let step = period.saturating_mul(missed.saturating_add(1));
+    deadline + Duration::from_nanos(step)
+}
+
+// Set the event timer for the earliest deadline again, for when a panic in
+// a callback that was recovered from cut expire() short
+#[cfg(test)]
+pub fn rearm() {
+    with_timers(|timers| {
+        if let Some(Reverse(first)) = timers.peek() {
+            program(first.deadline);
+        }
+    });
+}
+
+// Set the event timer for `deadline`, however close or past it is
+fn program(deadline: Instant) {
+    let delay = deadline.duration_since(Instant::now());
+    platform::set_event(delay.as_nanos().min(u64::MAX as u128) as u64);
+}
+
+// The interrupt handler takes the same lock, so keep interrupts off while
+// holding it
+fn with_timers<R>(f: impl FnOnce(&mut BinaryHeap<Reverse<Timer>>) -> R) -> R {
+    let enabled = arch::disable_interrupts();
+    let result = f(&mut TIMERS.lock());
+    arch::restore_interrupts(enabled);
+    result
+}
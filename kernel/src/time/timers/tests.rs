@@ -0,0 +1,65 @@
+// kernel/src/time/timers/tests.rs
+//
+// Rescheduling periodic timers, on paper and with the event timer held
+// off by masked interrupts.
+
+use core::sync::atomic::AtomicUsize;
+
+use super::*;
+
+#[test_case]
+fn next_deadline_keeps_phase() {
+    let period = Duration::from_nanos(100);
+    let at = |nanos| Instant(nanos);
+    // On time, and a little late
+    assert_eq!(next_deadline(at(1000), period, at(1000)), at(1100));
+    assert_eq!(next_deadline(at(1000), period, at(1030)), at(1100));
+    // Several periods late: the missed ones are skipped, and the next is
+    // still a whole number of periods after the first
+    assert_eq!(next_deadline(at(1000), period, at(1350)), at(1400));
+    assert_eq!(next_deadline(at(1000), period, at(1399)), at(1400));
+    assert_eq!(next_deadline(at(1000), period, at(1400)), at(1500));
+    // Saturates at the end of time
+    assert_eq!(
+        next_deadline(at(u64::MAX - 10), period, at(u64::MAX)),
+        at(u64::MAX)
+    );
+}
+
+static FIRED: AtomicUsize = AtomicUsize::new(0);
+
+fn count(_arg: usize) {
+    FIRED.fetch_add(1, atomic::Ordering::Relaxed);
+}
+
+fn spin_until(instant: Instant) {
+    while Instant::now() < instant {
+        core::hint::spin_loop();
+    }
+}
+
+// Held off for three and a half periods, an interval timer fires once when
+// interrupts come back, then at the fourth period as if it had never been
+// late
+#[test_case]
+fn delayed_interval_fires_once() {
+    let period = Duration::from_millis(50);
+    let margin = Duration::from_millis(15);
+    FIRED.store(0, atomic::Ordering::Relaxed);
+
+    let was_enabled = arch::disable_interrupts();
+    let start = Instant::now();
+    let id = set_interval(period, count, 0);
+    spin_until(start + period * 7 / 2);
+    arch::enable_interrupts();
+
+    spin_until(start + (period * 4 - margin));
+    assert_eq!(FIRED.load(atomic::Ordering::Relaxed), 1);
+    spin_until(start + (period * 4 + margin));
+    assert_eq!(FIRED.load(atomic::Ordering::Relaxed), 2);
+
+    assert!(cancel(id));
+    if !was_enabled {
+        arch::disable_interrupts();
+    }
+}
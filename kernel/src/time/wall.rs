@@ -0,0 +1,332 @@
+// kernel/src/time/wall.rs
+//
+// Wall-clock time. The hardware clock (the CMOS RTC, a PL031, or the
+// firmware's GetTime when neither is found) is read once at boot, or when
+// its driver binds, and from then on the time is that reading advanced by
+// the monotonic clock. Setting the time moves the offset between the two
+// and writes the hardware clock.
+//
+// All times are UTC. Hardware clocks are assumed to keep UTC too, as they
+// do under QEMU.
+
+use core::fmt;
+use core::ops::{Add, Sub};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use super::{platform, Instant, NANOS_PER_SECOND};
+use crate::efi;
+use crate::sync::SpinLock;
+
+#[cfg(test)]
+mod tests;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+// Unix time minus monotonic time, in nanoseconds
+static OFFSET: AtomicU64 = AtomicU64::new(0);
+static SYNCED: AtomicBool = AtomicBool::new(false);
+
+// Where the time came from, and where set() writes it back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Source {
+    Hardware(&'static str),
+    Firmware,
+}
+
+static SOURCE: SpinLock<Option<Source>> = SpinLock::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    // No hardware clock to read or write
+    NoClock,
+    // The clock held, or was given, a date it can't represent
+    InvalidTime,
+    Firmware(efi::Status),
+}
+
+// A calendar date and time of day, in UTC
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+impl DateTime {
+    // Fields in range, and not before the Unix epoch
+    pub fn is_valid(&self) -> bool {
+        self.year >= 1970
+            && (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+            && self.nanosecond < NANOS_PER_SECOND as u32
+    }
+
+    pub fn from_unix(seconds: u64, nanosecond: u32) -> DateTime {
+        let (year, month, day) = civil_from_days(seconds / SECONDS_PER_DAY);
+        let time_of_day = seconds % SECONDS_PER_DAY;
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (time_of_day / 3600) as u8,
+            minute: (time_of_day / 60 % 60) as u8,
+            second: (time_of_day % 60) as u8,
+            nanosecond,
+        }
+    }
+
+    // Seconds since the Unix epoch, None if the date isn't valid
+    pub fn to_unix(self) -> Option<u64> {
+        if !self.is_valid() {
+            return None;
+        }
+        let days = days_from_civil(self.year, self.month, self.day);
+        Some(
+            days * SECONDS_PER_DAY
+                + self.hour as u64 * 3600
+                + self.minute as u64 * 60
+                + self.second as u64,
+        )
+    }
+}
+
+// ISO 8601, e.g. 2026-10-18T09:30:00.250Z
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )?;
+        if self.nanosecond != 0 {
+            write!(f, ".{:03}", self.nanosecond / 1_000_000)?;
+        }
+        f.write_str("Z")
+    }
+}
+
+// A point in wall-clock time, in nanoseconds since the Unix epoch
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime(u64);
+
+impl SystemTime {
+    pub const UNIX_EPOCH: SystemTime = SystemTime(0);
+
+    // The Unix epoch itself until init() has read a clock
+    pub fn now() -> SystemTime {
+        SystemTime(
+            Instant::now()
+                .as_nanos()
+                .wrapping_add(OFFSET.load(Ordering::Relaxed)),
+        )
+    }
+
+    pub fn from_unix(since_epoch: Duration) -> SystemTime {
+        SystemTime::UNIX_EPOCH + since_epoch
+    }
+
+    pub fn from_date_time(time: &DateTime) -> Option<SystemTime> {
+        let seconds = time.to_unix()?;
+        Some(SystemTime(
+            seconds
+                .checked_mul(NANOS_PER_SECOND)?
+                .checked_add(time.nanosecond as u64)?,
+        ))
+    }
+
+    pub fn since_epoch(self) -> Duration {
+        Duration::from_nanos(self.0)
+    }
+
+    pub fn date_time(self) -> DateTime {
+        DateTime::from_unix(
+            self.0 / NANOS_PER_SECOND,
+            (self.0 % NANOS_PER_SECOND) as u32,
+        )
+    }
+}
+
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn add(self, duration: Duration) -> SystemTime {
+        let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+        SystemTime(self.0.saturating_add(nanos))
+    }
+}
+
+impl Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn sub(self, duration: Duration) -> SystemTime {
+        let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+        SystemTime(self.0.saturating_sub(nanos))
+    }
+}
+
+impl fmt::Display for SystemTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.date_time().fmt(f)
+    }
+}
+
+// Read the hardware clock. Needs the monotonic clock running.
+pub fn init() {
+    let source = match platform::rtc_init() {
+        Some(name) => Source::Hardware(name),
+        None if efi::is_available() => Source::Firmware,
+        None => {
+            warn!("no real-time clock, wall-clock time starts at the epoch");
+            return;
+        }
+    };
+    *SOURCE.lock() = Some(source);
+
+    match sync() {
+        Ok(now) => info!("wall clock: {} from {}", now, source_name(source)),
+        Err(err) => warn!("couldn't read {}: {:?}", source_name(source), err),
+    }
+}
+
+// Switch to a hardware clock whose driver bound after init(), and read it.
+// Only the aarch64 PL031 binds that late.
+#[cfg_attr(not(target_arch = "aarch64"), allow(dead_code))]
+pub fn use_hardware_clock(name: &'static str) {
+    *SOURCE.lock() = Some(Source::Hardware(name));
+    match sync() {
+        Ok(now) => info!("wall clock: {} from {}", now, name),
+        Err(err) => warn!("couldn't read {}: {:?}", name, err),
+    }
+}
+
+// Whether the wall clock has been read from or set to a real time
+pub fn is_synced() -> bool {
+    SYNCED.load(Ordering::Relaxed)
+}
+
+// Re-read the hardware clock, dropping any drift of the monotonic clock
+// against it
+pub fn sync() -> Result<SystemTime, Error> {
+    let source = SOURCE.lock();
+    let time = match *source {
+        Some(Source::Hardware(_)) => platform::rtc_read().ok_or(Error::InvalidTime)?,
+        Some(Source::Firmware) => from_efi(&efi::get_time().map_err(Error::Firmware)?)?,
+        None => return Err(Error::NoClock),
+    };
+    let now = SystemTime::from_date_time(&time).ok_or(Error::InvalidTime)?;
+    set_offset(now);
+    Ok(now)
+}
+
+// Set the wall clock, and the hardware clock to match. The kernel's clock
+// changes even if writing the hardware fails.
+pub fn set(time: SystemTime) -> Result<(), Error> {
+    let source = SOURCE.lock();
+    set_offset(time);
+
+    let date_time = time.date_time();
+    match *source {
+        Some(Source::Hardware(_)) if platform::rtc_write(&date_time) => Ok(()),
+        Some(Source::Hardware(_)) => Err(Error::InvalidTime),
+        Some(Source::Firmware) => efi::set_time(&to_efi(&date_time)).map_err(Error::Firmware),
+        None => Err(Error::NoClock),
+    }
+}
+
+fn set_offset(now: SystemTime) {
+    OFFSET.store(
+        now.0.wrapping_sub(Instant::now().as_nanos()),
+        Ordering::Relaxed,
+    );
+    SYNCED.store(true, Ordering::Relaxed);
+}
+
+fn source_name(source: Source) -> &'static str {
+    match source {
+        Source::Hardware(name) => name,
+        Source::Firmware => "UEFI GetTime",
+    }
+}
+
+// UEFI times carry their offset from UTC in minutes: local = UTC + offset
+fn from_efi(time: &efi::Time) -> Result<DateTime, Error> {
+    let local = DateTime {
+        year: time.year,
+        month: time.month,
+        day: time.day,
+        hour: time.hour,
+        minute: time.minute,
+        second: time.second,
+        nanosecond: time.nanosecond,
+    };
+    if time.time_zone == efi::Time::UNSPECIFIED_TIMEZONE {
+        return Ok(local);
+    }
+
+    let seconds = local.to_unix().ok_or(Error::InvalidTime)? as i64;
+    let utc = seconds - time.time_zone as i64 * 60;
+    let utc = u64::try_from(utc).map_err(|_| Error::InvalidTime)?;
+    Ok(DateTime::from_unix(utc, time.nanosecond))
+}
+
+fn to_efi(time: &DateTime) -> efi::Time {
+    efi::Time::new(
+        time.year,
+        time.month,
+        time.day,
+        time.hour,
+        time.minute,
+        time.second,
+        time.nanosecond,
+    )
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 to and from a proleptic Gregorian date, after
+// Howard Hinnant's algorithms, with eras starting on March 1st so that the
+// leap day comes last. Only dates from 1970 on are handled.
+fn days_from_civil(year: u16, month: u8, day: u8) -> u64 {
+    let year = year as u64 - (month <= 2) as u64;
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let month_from_march = (month as u64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as u64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    // 719468 days from 0000-03-01 to the epoch
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: u64) -> (u16, u8, u8) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = (month_from_march + 2) % 12 + 1;
+    let year = era * 400 + year_of_era + (month <= 2) as u64;
+    (year as u16, month as u8, day as u8)
+}
@@ -0,0 +1,113 @@
+// kernel/src/time/wall/tests.rs
+//
+// The calendar conversions, around the dates they are most likely to get
+// wrong.
+
+use super::*;
+
+fn date_time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+    DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        nanosecond: 0,
+    }
+}
+
+// `time` is `seconds` after the epoch, and converts back to itself
+fn round_trip(time: DateTime, seconds: u64) {
+    assert_eq!(time.to_unix(), Some(seconds));
+    assert_eq!(DateTime::from_unix(seconds, 0), time);
+    let days = seconds / SECONDS_PER_DAY;
+    assert_eq!(days_from_civil(time.year, time.month, time.day), days);
+    assert_eq!(civil_from_days(days), (time.year, time.month, time.day));
+}
+
+#[test_case]
+fn epoch() {
+    round_trip(date_time(1970, 1, 1, 0, 0, 0), 0);
+    round_trip(date_time(1970, 1, 1, 23, 59, 59), 86_399);
+    round_trip(date_time(1970, 1, 2, 0, 0, 0), 86_400);
+    assert!(!date_time(1969, 12, 31, 23, 59, 59).is_valid());
+
+    let epoch = SystemTime::from_date_time(&date_time(1970, 1, 1, 0, 0, 0));
+    assert_eq!(epoch, Some(SystemTime::UNIX_EPOCH));
+}
+
+// Divisible by 400, so a leap year despite being a century
+#[test_case]
+fn leap_day_2000() {
+    round_trip(date_time(2000, 2, 29, 0, 0, 0), 951_782_400);
+    round_trip(date_time(2000, 2, 29, 23, 59, 59), 951_868_799);
+    round_trip(date_time(2000, 3, 1, 0, 0, 0), 951_868_800);
+    assert!(!date_time(2000, 2, 30, 0, 0, 0).is_valid());
+}
+
+// A century not divisible by 400, so no leap day
+#[test_case]
+fn no_leap_day_2100() {
+    assert!(!date_time(2100, 2, 29, 0, 0, 0).is_valid());
+    assert_eq!(date_time(2100, 2, 29, 0, 0, 0).to_unix(), None);
+    round_trip(date_time(2100, 2, 28, 0, 0, 0), 4_107_456_000);
+    round_trip(date_time(2100, 3, 1, 0, 0, 0), 4_107_542_400);
+}
+
+// Past where 32-bit Unix time runs out
+#[test_case]
+fn past_2106() {
+    round_trip(date_time(2106, 2, 7, 6, 28, 15), u32::MAX as u64);
+    round_trip(date_time(2106, 2, 7, 6, 28, 16), 1 << 32);
+    round_trip(date_time(2106, 12, 31, 23, 59, 59), 4_323_283_199);
+
+    let time = date_time(2106, 2, 7, 6, 28, 16);
+    let system_time = SystemTime::from_date_time(&time).unwrap();
+    assert_eq!(system_time.since_epoch(), Duration::from_secs(1 << 32));
+    assert_eq!(system_time.date_time(), time);
+}
+
+// UEFI's time_zone is local time minus UTC, in minutes
+#[test_case]
+fn efi_time_zone() {
+    let mut time = to_efi(&date_time(2000, 1, 1, 0, 30, 0));
+    assert_eq!(time.time_zone, efi::Time::UNSPECIFIED_TIMEZONE);
+    assert_eq!(from_efi(&time), Ok(date_time(2000, 1, 1, 0, 30, 0)));
+
+    // UTC+1, across midnight and the year
+    time.time_zone = 60;
+    assert_eq!(from_efi(&time), Ok(date_time(1999, 12, 31, 23, 30, 0)));
+    // UTC-8, not across anything
+    time.time_zone = -480;
+    assert_eq!(from_efi(&time), Ok(date_time(2000, 1, 1, 8, 30, 0)));
+    // UTC+5:45, into the leap day
+    let mut time = to_efi(&date_time(2000, 3, 1, 5, 0, 0));
+    time.time_zone = 345;
+    assert_eq!(from_efi(&time), Ok(date_time(2000, 2, 29, 23, 15, 0)));
+
+    // Before the epoch once converted
+    let mut time = to_efi(&date_time(1970, 1, 1, 0, 30, 0));
+    time.time_zone = 60;
+    assert_eq!(from_efi(&time), Err(Error::InvalidTime));
+}
+
+// The Firmware source, whatever init() picked. GetTime runs in virtual mode
+// by now, and agrees with the clock the kernel read at boot.
+#[test_case]
+fn firmware_source() {
+    if !efi::is_available() {
+        return;
+    }
+    let before = SystemTime::now();
+    let saved = SOURCE.lock().replace(Source::Firmware);
+    let synced = sync().unwrap();
+    assert!(synced.date_time().is_valid());
+
+    *SOURCE.lock() = saved;
+    if saved.is_some() {
+        sync().unwrap();
+        let apart = synced.since_epoch().abs_diff(before.since_epoch());
+        assert!(apart < Duration::from_secs(5), "{} and {}", before, synced);
+    }
+}
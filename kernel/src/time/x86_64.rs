@@ -0,0 +1,126 @@
+// kernel/src/time/x86_64.rs
+//
+// The TSC is the clock. Its frequency is measured over a short window of
+// the HPET's main counter, or of the PIT's channel 2 on machines without
+// one. Events come from the local APIC timer in one-shot mode, and the
+// date from the CMOS RTC.
+
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use super::wall::DateTime;
+use super::{timers, NANOS_PER_SECOND};
+use crate::acpi::{self, fadt, hpet, GenericAddress, SPACE_SYSTEM_MEMORY};
+use crate::arch;
+use crate::arch::x86_64::idt;
+use crate::drivers::apic;
+use crate::drivers::cmos_rtc;
+use crate::drivers::hpet::Hpet;
+use crate::drivers::pit;
+use crate::interrupts;
+use crate::mm::vmm;
+
+pub const CLOCK_NAME: &str = "TSC";
+
+// Length of the calibration window
+const CALIBRATION_MS: u64 = 10;
+
+// Size of the HPET's register block
+const HPET_WINDOW: u64 = 0x400;
+
+static TIMER_VECTOR: AtomicU8 = AtomicU8::new(0);
+// Local APIC timer ticks per second, after its divider
+static TIMER_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+// CMOS RAM index of the century, 0 if the FADT names none
+static RTC_CENTURY: AtomicU8 = AtomicU8::new(0);
+
+// TSC ticks per second and what they were measured against
+pub fn calibrate() -> (u64, &'static str) {
+    if let Some(hpet) = find_hpet() {
+        hpet.enable();
+        let window = hpet.frequency() * CALIBRATION_MS / 1000;
+        let start = hpet.counter();
+        let tsc_start = arch::read_cycle_counter();
+        while hpet.ticks_since(start) < window {
+            core::hint::spin_loop();
+        }
+        let tsc_elapsed = arch::read_cycle_counter() - tsc_start;
+        let elapsed = hpet.ticks_since(start);
+        return (
+            (tsc_elapsed as u128 * hpet.frequency() as u128 / elapsed as u128) as u64,
+            "the HPET",
+        );
+    }
+
+    let window = pit::FREQUENCY * CALIBRATION_MS / 1000;
+    let tsc_start = arch::read_cycle_counter();
+    pit::wait(window as u16);
+    let tsc_elapsed = arch::read_cycle_counter() - tsc_start;
+    (tsc_elapsed * pit::FREQUENCY / window, "the PIT")
+}
+
+fn find_hpet() -> Option<Hpet> {
+    let table = hpet::Hpet::parse(acpi::find_table(hpet::SIGNATURE)?)?;
+    let GenericAddress { space, address, .. } = table.base;
+    if space != SPACE_SYSTEM_MEMORY || address == 0 {
+        return None;
+    }
+    let base = vmm::map_mmio(address, HPET_WINDOW).ok()?;
+    unsafe { Hpet::new(base) }
+}
+
+// Take a vector for the local APIC timer and measure its rate
+pub fn init_events() -> bool {
+    let Some(vector) = idt::allocate_vector() else {
+        return false;
+    };
+    if !interrupts::register(vector as u32, timer_interrupt) {
+        idt::free_vector(vector);
+        return false;
+    }
+    let frequency = apic::measure_timer_frequency();
+    TIMER_FREQUENCY.store(frequency, Ordering::Relaxed);
+    TIMER_VECTOR.store(vector, Ordering::Relaxed);
+    true
+}
+
+// Raise the timer interrupt `delay_ns` from now, as soon as possible if 0
+pub fn set_event(delay_ns: u64) {
+    let vector = TIMER_VECTOR.load(Ordering::Relaxed);
+    if vector == 0 {
+        return;
+    }
+    let count = delay_ns as u128 * TIMER_FREQUENCY.load(Ordering::Relaxed) as u128
+        / NANOS_PER_SECOND as u128;
+    apic::start_timer_oneshot(vector, count.clamp(1, u32::MAX as u128) as u32);
+}
+
+fn timer_interrupt(_vector: u32) {
+    timers::expire();
+}
+
+// The FADT can say there is no CMOS RTC, and where its century is kept
+pub fn rtc_init() -> Option<&'static str> {
+    if let Some(fadt) = acpi::find_table(fadt::SIGNATURE).and_then(fadt::Fadt::parse) {
+        if fadt.iapc_boot_arch & fadt::CMOS_RTC_NOT_PRESENT != 0 {
+            return None;
+        }
+        RTC_CENTURY.store(fadt.century, Ordering::Relaxed);
+    }
+    rtc_read().map(|_| "CMOS RTC")
+}
+
+pub fn rtc_read() -> Option<DateTime> {
+    cmos_rtc::read_time(century_register())
+}
+
+pub fn rtc_write(time: &DateTime) -> bool {
+    cmos_rtc::write_time(time, century_register());
+    true
+}
+
+fn century_register() -> Option<u8> {
+    match RTC_CENTURY.load(Ordering::Relaxed) {
+        0 => None,
+        register => Some(register),
+    }
+}
@@ -0,0 +1,120 @@
+// kernel/src/timeline.rs
+//
+// Boot timeline: the bootloader's phase timestamps, extended with kernel
+// milestones. Timestamps are raw TSC / CNTVCT_EL0 / time CSR values, which
+// count from reset, so the first entry also shows how long the firmware took.
+
+use core::fmt::{self, Write};
+
+use crate::sync::SpinLock;
+use crate::{arch, BootInfo};
+
+// Must match the bootloader's timing module
+pub const MAX_MILESTONES: usize = 64;
+pub const MILESTONE_NAME_LEN: usize = 32;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Milestone {
+    pub ticks: u64,
+    pub name: [u8; MILESTONE_NAME_LEN],
+}
+
+impl Milestone {
+    pub fn name(&self) -> &str {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(MILESTONE_NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BootTimeline {
+    pub counter_frequency: u64,
+    pub count: usize,
+    pub milestones: [Milestone; MAX_MILESTONES],
+}
+
+static TIMELINE: SpinLock<BootTimeline> = SpinLock::new(BootTimeline {
+    counter_frequency: 0,
+    count: 0,
+    milestones: [Milestone {
+        ticks: 0,
+        name: [0; MILESTONE_NAME_LEN],
+    }; MAX_MILESTONES],
+});
+
+// Take over the bootloader's timeline, keeping milestones recorded so far
+pub fn init(boot_info: &BootInfo) {
+    let mut timeline = TIMELINE.lock();
+    let kernel = *timeline;
+
+    *timeline = boot_info.boot_timeline;
+    timeline.count = timeline.count.min(MAX_MILESTONES);
+    for milestone in &kernel.milestones[..kernel.count] {
+        push(&mut timeline, *milestone);
+    }
+}
+
+fn push(timeline: &mut BootTimeline, milestone: Milestone) {
+    if timeline.count < MAX_MILESTONES {
+        timeline.milestones[timeline.count] = milestone;
+        timeline.count += 1;
+    }
+}
+
+// Record a kernel milestone, dropped once the table is full
+pub fn mark(name: &str) {
+    let mut milestone = Milestone {
+        ticks: arch::read_cycle_counter(),
+        name: [0; MILESTONE_NAME_LEN],
+    };
+
+    let len = name.len().min(MILESTONE_NAME_LEN);
+    milestone.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+    push(&mut TIMELINE.lock(), milestone);
+}
+
+// Frequency of arch::read_cycle_counter in Hz, 0 if the bootloader couldn't measure it
+pub fn counter_frequency() -> u64 {
+    TIMELINE.lock().counter_frequency
+}
+
+// Convert counter ticks to microseconds, 0 if the frequency is unknown
+fn ticks_to_us(ticks: u64, frequency: u64) -> u64 {
+    if frequency == 0 {
+        return 0;
+    }
+    (ticks as u128 * 1_000_000 / frequency as u128) as u64
+}
+
+// Print the combined timeline: time since reset and time since the previous milestone
+pub fn print(out: &mut impl Write) -> fmt::Result {
+    let timeline = *TIMELINE.lock();
+    let frequency = timeline.counter_frequency;
+
+    writeln!(out, "Boot timeline ({} kHz counter):", frequency / 1000)?;
+
+    let mut previous = None;
+    for milestone in &timeline.milestones[..timeline.count] {
+        let since_reset = ticks_to_us(milestone.ticks, frequency);
+        let delta = previous.map_or(0, |ticks| {
+            ticks_to_us(milestone.ticks.saturating_sub(ticks), frequency)
+        });
+        writeln!(
+            out,
+            "  {:>8}.{:03} ms  +{:>8} us  {}",
+            since_reset / 1000,
+            since_reset % 1000,
+            delta,
+            milestone.name()
+        )?;
+        previous = Some(milestone.ticks);
+    }
+    Ok(())
+}
+
+// Write the timeline to the kernel log and the serial console
+pub fn log() {
+    let _ = print(&mut crate::log::Writer);
+}
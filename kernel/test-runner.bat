@@ -0,0 +1,57 @@
+@echo off
+REM Cargo runner for the kernel's tests: boots the test kernel in QEMU with no
+REM display and the serial port on stdout, and exits 0 if every test passed.
+REM
+REM Run `cargo +nightly test` in kernel\ with the config for the architecture
+REM in .cargo\config.toml, after build.bat has built the bootloader. Cargo
+REM calls this with the architecture and the test kernel's path.
+setlocal
+
+set ARCH=%1
+set KERNEL=%~f2
+set PROJECT_ROOT=%~dp0..
+set ESP=%PROJECT_ROOT%\esp-test
+
+if "%ARCH%"=="x86_64" (
+    set BOOT_SOURCE=%PROJECT_ROOT%\target\x86_64-unknown-uefi\release\uefi_bootloader.efi
+    set BOOT_NAME=BOOTX64.EFI
+    set KERNEL_NAME=KERNEL_X64.ELF
+)
+if "%ARCH%"=="aarch64" (
+    set BOOT_SOURCE=%PROJECT_ROOT%\target\aarch64-unknown-uefi\release\uefi_bootloader.efi
+    set BOOT_NAME=BOOTAA64.EFI
+    set KERNEL_NAME=KERNEL_ARM64.ELF
+)
+if not defined KERNEL_NAME (
+    echo Unknown architecture: %ARCH%
+    exit /b 1
+)
+if not exist "%BOOT_SOURCE%" (
+    echo ERROR: no bootloader at %BOOT_SOURCE%, run build.bat %ARCH% first
+    exit /b 1
+)
+
+if not exist "%ESP%\EFI\BOOT" mkdir "%ESP%\EFI\BOOT"
+if not exist "%ESP%\EFI\KERNEL" mkdir "%ESP%\EFI\KERNEL"
+if not exist "%ESP%\EFI\MELONOS" mkdir "%ESP%\EFI\MELONOS"
+copy /Y "%BOOT_SOURCE%" "%ESP%\EFI\BOOT\%BOOT_NAME%" >nul
+copy /Y "%KERNEL%" "%ESP%\EFI\KERNEL\%KERNEL_NAME%" >nul
+
+REM A single boot entry, so the menu boots it straight away. On aarch64 the
+REM kernel reports the result through semihosting, which QEMU needs enabling for.
+set CMDLINE=
+if "%ARCH%"=="aarch64" set CMDLINE= -- semihosting
+echo kernel \EFI\KERNEL\%KERNEL_NAME% MelonOS tests%CMDLINE%> "%ESP%\EFI\MELONOS\BOOT.CFG"
+
+cd /d %PROJECT_ROOT%
+if "%ARCH%"=="x86_64" (
+    REM isa-debug-exit makes the exit status 33 for success, 35 for failure
+    qemu-system-x86_64 -drive file=fat:rw:esp-test,format=raw -bios OVMF.fd -m 128M -display none -serial stdio -device isa-debug-exit,iobase=0xf4,iosize=0x04 -no-reboot
+    if errorlevel 34 exit /b 1
+    if errorlevel 33 exit /b 0
+    exit /b 1
+)
+if "%ARCH%"=="aarch64" (
+    qemu-system-aarch64 -M virt -cpu cortex-a72 -m 512M -bios QEMU_EFI.fd -display none -serial stdio -semihosting -no-reboot -drive if=none,id=esp,format=raw,file=fat:rw:esp-test -device virtio-blk-device,drive=esp
+)
+exit /b %ERRORLEVEL%
@@ -0,0 +1,256 @@
+# kernel/testdata/fdt/mkdtb.py
+#
+# Writes the device trees the firmware::fdt tests parse:
+#
+#   python3 mkdtb.py
+#
+# aarch64-virt.dtb is the tree QEMU builds for aarch64 `-M virt -smp 2`
+# (GICv2, PL011, PL031, the armv8 timer), and riscv64-virt.dtb the one for
+# riscv64 `-M virt -smp 2` (PLIC, CLINT, NS16550A, Goldfish RTC under /soc).
+# They follow what `-M virt,dumpdtb=virt.dtb` gives, node by node and with
+# QEMU's phandles, but are assembled here rather than dumped, and leave out
+# nodes the kernel has no use for (flash, fw-cfg, virtio-mmio, PCIe). Rerun
+# this and the tests after changing it.
+import os
+import struct
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+BEGIN_NODE, END_NODE, PROP, END = 1, 2, 3, 9
+
+
+def cells(*values):
+    return b''.join(struct.pack('>I', value) for value in values)
+
+
+def strings(*values):
+    return b''.join(value.encode() + b'\0' for value in values)
+
+
+def reg(*pairs, address_cells=2, size_cells=2):
+    out = b''
+    for address, size in pairs:
+        out += cells(*[(address >> (32 * i)) & 0xFFFFFFFF for i in reversed(range(address_cells))])
+        out += cells(*[(size >> (32 * i)) & 0xFFFFFFFF for i in reversed(range(size_cells))])
+    return out
+
+
+def node(name, properties, children=()):
+    return (name, properties, list(children))
+
+
+def dtb(root):
+    structure = bytearray()
+    names = bytearray()
+    offsets = {}
+
+    def pad():
+        structure.extend(b'\0' * (-len(structure) % 4))
+
+    def emit(name, properties, children):
+        structure.extend(struct.pack('>I', BEGIN_NODE) + name.encode() + b'\0')
+        pad()
+        for prop, value in properties:
+            if prop not in offsets:
+                offsets[prop] = len(names)
+                names.extend(prop.encode() + b'\0')
+            structure.extend(struct.pack('>III', PROP, len(value), offsets[prop]) + value)
+            pad()
+        for child in children:
+            emit(*child)
+        structure.extend(struct.pack('>I', END_NODE))
+
+    emit(*root)
+    structure.extend(struct.pack('>I', END))
+
+    # Header, an empty memory reservation block, structure, strings
+    struct_offset = 40 + 16
+    strings_offset = struct_offset + len(structure)
+    total = strings_offset + len(names)
+    header = struct.pack('>10I', 0xD00DFEED, total, struct_offset, strings_offset, 40,
+                         17, 16, 0, len(names), len(structure))
+    return header + b'\0' * 16 + bytes(structure) + bytes(names)
+
+
+def aarch64_virt():
+    clock, gic, v2m = 0x8000, 0x8001, 0x8002
+    cpus = [node('cpu@%d' % cpu, [
+        ('phandle', cells(0x8003 + cpu)),
+        ('reg', cells(cpu)),
+        ('enable-method', strings('psci')),
+        ('compatible', strings('arm,cortex-a72')),
+        ('device_type', strings('cpu')),
+    ]) for cpu in range(2)]
+    return node('', [
+        ('interrupt-parent', cells(gic)),
+        ('model', strings('linux,dummy-virt')),
+        ('#size-cells', cells(2)),
+        ('#address-cells', cells(2)),
+        ('compatible', strings('linux,dummy-virt')),
+    ], [
+        node('psci', [
+            ('migrate', cells(0xC4000005)),
+            ('cpu_on', cells(0xC4000003)),
+            ('cpu_off', cells(0x84000002)),
+            ('cpu_suspend', cells(0xC4000001)),
+            ('method', strings('hvc')),
+            ('compatible', strings('arm,psci-1.0', 'arm,psci-0.2', 'arm,psci')),
+        ]),
+        node('memory@40000000', [
+            ('reg', reg((0x40000000, 0x20000000))),
+            ('device_type', strings('memory')),
+        ]),
+        node('pl031@9010000', [
+            ('clock-names', strings('apb_pclk')),
+            ('clocks', cells(clock)),
+            ('interrupts', cells(0, 2, 4)),
+            ('reg', reg((0x9010000, 0x1000))),
+            ('compatible', strings('arm,pl031', 'arm,primecell')),
+        ]),
+        node('pl011@9000000', [
+            ('clock-names', strings('uartclk', 'apb_pclk')),
+            ('clocks', cells(clock, clock)),
+            ('interrupts', cells(0, 1, 4)),
+            ('reg', reg((0x9000000, 0x1000))),
+            ('compatible', strings('arm,pl011', 'arm,primecell')),
+        ]),
+        node('pmu', [
+            ('interrupts', cells(1, 7, 0x304)),
+            ('compatible', strings('arm,armv8-pmuv3')),
+        ]),
+        node('intc@8000000', [
+            ('phandle', cells(gic)),
+            ('reg', reg((0x8000000, 0x10000), (0x8010000, 0x10000))),
+            ('compatible', strings('arm,cortex-a15-gic')),
+            ('ranges', b''),
+            ('#size-cells', cells(2)),
+            ('#address-cells', cells(2)),
+            ('interrupt-controller', b''),
+            ('#interrupt-cells', cells(3)),
+        ], [
+            node('v2m@8020000', [
+                ('phandle', cells(v2m)),
+                ('reg', reg((0x8020000, 0x1000))),
+                ('msi-controller', b''),
+                ('compatible', strings('arm,gic-v2m-frame')),
+            ]),
+        ]),
+        node('cpus', [
+            ('#size-cells', cells(0)),
+            ('#address-cells', cells(1)),
+        ], cpus),
+        node('timer', [
+            ('interrupts', cells(1, 13, 0x304, 1, 14, 0x304, 1, 11, 0x304, 1, 10, 0x304)),
+            ('always-on', b''),
+            ('compatible', strings('arm,armv8-timer', 'arm,armv7-timer')),
+        ]),
+        node('apb-pclk', [
+            ('phandle', cells(clock)),
+            ('clock-output-names', strings('clk24mhz')),
+            ('clock-frequency', cells(24000000)),
+            ('#clock-cells', cells(0)),
+            ('compatible', strings('fixed-clock')),
+        ]),
+        node('chosen', [
+            ('stdout-path', strings('/pl011@9000000')),
+        ]),
+    ])
+
+
+def riscv64_virt():
+    plic, test = 0x3, 0x4
+    cpus = []
+    for cpu in range(2):
+        intc = [2, 5][cpu]
+        cpus.append(node('cpu@%d' % cpu, [
+            ('phandle', cells([1, 6][cpu])),
+            ('device_type', strings('cpu')),
+            ('reg', cells(cpu)),
+            ('status', strings('okay')),
+            ('compatible', strings('riscv')),
+            ('riscv,isa', strings('rv64imafdch_zicsr_zifencei_zba_zbb_zbc_zbs')),
+            ('mmu-type', strings('riscv,sv57')),
+        ], [
+            node('interrupt-controller', [
+                ('#interrupt-cells', cells(1)),
+                ('interrupt-controller', b''),
+                ('compatible', strings('riscv,cpu-intc')),
+                ('phandle', cells(intc)),
+            ]),
+        ]))
+    return node('', [
+        ('#address-cells', cells(2)),
+        ('#size-cells', cells(2)),
+        ('compatible', strings('riscv-virtio')),
+        ('model', strings('riscv-virtio,qemu')),
+    ], [
+        node('poweroff', [
+            ('value', cells(0x5555)),
+            ('offset', cells(0)),
+            ('regmap', cells(test)),
+            ('compatible', strings('syscon-poweroff')),
+        ]),
+        node('reboot', [
+            ('value', cells(0x7777)),
+            ('offset', cells(0)),
+            ('regmap', cells(test)),
+            ('compatible', strings('syscon-reboot')),
+        ]),
+        node('memory@80000000', [
+            ('device_type', strings('memory')),
+            ('reg', reg((0x80000000, 0x20000000))),
+        ]),
+        node('cpus', [
+            ('#address-cells', cells(1)),
+            ('#size-cells', cells(0)),
+            ('timebase-frequency', cells(10000000)),
+        ], cpus),
+        node('soc', [
+            ('#address-cells', cells(2)),
+            ('#size-cells', cells(2)),
+            ('compatible', strings('simple-bus')),
+            ('ranges', b''),
+        ], [
+            node('rtc@101000', [
+                ('interrupts', cells(11)),
+                ('interrupt-parent', cells(plic)),
+                ('reg', reg((0x101000, 0x1000))),
+                ('compatible', strings('google,goldfish-rtc')),
+            ]),
+            node('serial@10000000', [
+                ('interrupts', cells(10)),
+                ('interrupt-parent', cells(plic)),
+                ('clock-frequency', cells(0x384000)),
+                ('reg', reg((0x10000000, 0x100))),
+                ('compatible', strings('ns16550a')),
+            ]),
+            node('test@100000', [
+                ('phandle', cells(test)),
+                ('reg', reg((0x100000, 0x1000))),
+                ('compatible', strings('sifive,test1', 'sifive,test0', 'syscon')),
+            ]),
+            node('plic@c000000', [
+                ('phandle', cells(plic)),
+                ('riscv,ndev', cells(0x5F)),
+                ('reg', reg((0xC000000, 0x600000))),
+                ('interrupts-extended', cells(2, 11, 2, 9, 5, 11, 5, 9)),
+                ('interrupt-controller', b''),
+                ('compatible', strings('sifive,plic-1.0.0', 'riscv,plic0')),
+                ('#address-cells', cells(0)),
+                ('#interrupt-cells', cells(1)),
+            ]),
+            node('clint@2000000', [
+                ('interrupts-extended', cells(2, 3, 2, 7, 5, 3, 5, 7)),
+                ('reg', reg((0x2000000, 0x10000))),
+                ('compatible', strings('sifive,clint0', 'riscv,clint0')),
+            ]),
+        ]),
+        node('chosen', [
+            ('stdout-path', strings('/soc/serial@10000000')),
+        ]),
+    ])
+
+
+for name, tree in (('aarch64-virt.dtb', aarch64_virt()), ('riscv64-virt.dtb', riscv64_virt())):
+    with open(os.path.join(HERE, name), 'wb') as f:
+        f.write(dtb(tree))
@@ -7,4 +7,51 @@ pub fn prepare_jump_to_kernel(_system_table: &mut SystemTable<Boot>) {
     unsafe {
         core::arch::asm!("msr daifset, #2");
     }
-}
\ No newline at end of file
+}
+
+// Affinity fields (Aff3..Aff0) of MPIDR_EL1 for the running processor
+pub fn current_cpu_id() -> u64 {
+    let mpidr: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, mpidr_el1", out(reg) mpidr);
+    }
+    mpidr & 0xFF_00FF_FFFF
+}
+
+// Virtual count of the generic timer, used for boot timing
+pub fn read_counter() -> u64 {
+    let count: u64;
+    unsafe {
+        core::arch::asm!("isb", "mrs {}, cntvct_el0", out(reg) count, options(nomem, nostack));
+    }
+    count
+}
+
+// Clean the data cache and invalidate the instruction cache for freshly
+// written code, so the CPU doesn't fetch stale instructions
+pub fn sync_instruction_cache(addr: u64, len: usize) {
+    // CTR_EL0 gives the smallest D- and I-cache line sizes as log2(words)
+    let ctr: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, ctr_el0", out(reg) ctr);
+    }
+    let dline = 4u64 << ((ctr >> 16) & 0xF);
+    let iline = 4u64 << (ctr & 0xF);
+    let end = addr + len as u64;
+
+    unsafe {
+        let mut line = addr & !(dline - 1);
+        while line < end {
+            core::arch::asm!("dc cvau, {}", in(reg) line);
+            line += dline;
+        }
+        core::arch::asm!("dsb ish");
+
+        let mut line = addr & !(iline - 1);
+        while line < end {
+            core::arch::asm!("ic ivau, {}", in(reg) line);
+            line += iline;
+        }
+        core::arch::asm!("dsb ish", "isb");
+    }
+}
@@ -0,0 +1,112 @@
+// uefi_bootloader/src/chainload.rs
+use core::mem::MaybeUninit;
+
+use log::info;
+use uefi::prelude::*;
+use uefi::proto::device_path::build::{media, DevicePathBuilder};
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::media::file::{File, FileAttribute, FileMode};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::table::boot::{
+    LoadImageSource, OpenProtocolAttributes, OpenProtocolParams, SearchType,
+};
+use uefi::{CStr16, Identify};
+
+// Start another EFI application, such as the UEFI shell or a firmware tool.
+//
+// Every volume is searched for `path` and the first match is loaded. Returns
+// the image's exit status once it exits, so the caller can show the menu again.
+pub fn start_efi_image(
+    image_handle: Handle,
+    system_table: &SystemTable<Boot>,
+    path: &CStr16,
+) -> Result<Status, Status> {
+    let boot_services = system_table.boot_services();
+
+    let volumes = boot_services
+        .locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
+        .map_err(|_| Status::NOT_FOUND)?;
+
+    for &volume in volumes.handles() {
+        if !volume_has_file(image_handle, boot_services, volume, path) {
+            continue;
+        }
+
+        // The image's device path is the volume's path plus a file path node
+        let volume_path = unsafe {
+            boot_services
+                .open_protocol::<DevicePath>(
+                    OpenProtocolParams {
+                        handle: volume,
+                        agent: image_handle,
+                        controller: None,
+                    },
+                    OpenProtocolAttributes::GetProtocol,
+                )
+                .map_err(|_| Status::DEVICE_ERROR)?
+        };
+
+        let mut path_buffer = [MaybeUninit::uninit(); 512];
+        let mut builder = DevicePathBuilder::with_buf(&mut path_buffer);
+        for node in volume_path.node_iter() {
+            builder = builder.push(&node).map_err(|_| Status::BUFFER_TOO_SMALL)?;
+        }
+        let image_path = builder
+            .push(&media::FilePath { path_name: path })
+            .and_then(|builder| builder.finalize())
+            .map_err(|_| Status::BUFFER_TOO_SMALL)?;
+
+        info!("Loading EFI image {}", path);
+        let child = boot_services
+            .load_image(
+                image_handle,
+                LoadImageSource::FromFilePath {
+                    file_path: image_path,
+                    from_boot_manager: false,
+                },
+            )
+            .map_err(|err| err.status())?;
+
+        info!("Starting EFI image {}", path);
+        let status = match boot_services.start_image(child) {
+            Ok(()) => Status::SUCCESS,
+            Err(err) => err.status(),
+        };
+
+        info!("EFI image {} exited with {:?}", path, status);
+        return Ok(status);
+    }
+
+    info!("EFI image {} not found on any volume", path);
+    Err(Status::NOT_FOUND)
+}
+
+fn volume_has_file(
+    image_handle: Handle,
+    boot_services: &BootServices,
+    volume: Handle,
+    path: &CStr16,
+) -> bool {
+    let fs = unsafe {
+        boot_services.open_protocol::<SimpleFileSystem>(
+            OpenProtocolParams {
+                handle: volume,
+                agent: image_handle,
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    };
+
+    let mut fs = match fs {
+        Ok(fs) => fs,
+        Err(_) => return false,
+    };
+
+    match fs.open_volume() {
+        Ok(mut root) => root
+            .open(path, FileMode::Read, FileAttribute::empty())
+            .is_ok(),
+        Err(_) => false,
+    }
+}
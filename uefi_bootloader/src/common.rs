@@ -1,5 +1,39 @@
 // uefi_bootloader/src/common.rs
-use uefi::table::boot::MemoryDescriptor;
+use uefi::table::boot::{MemoryAttribute, MemoryDescriptor};
+use uefi::table::cfg::{ACPI2_GUID, ACPI_GUID};
+use uefi::table::{Boot, Runtime, SystemTable};
+use uefi::{guid, Guid};
+
+use crate::timing::BootTimeline;
+
+// Maximum number of runtime memory regions handed over to the kernel
+pub const MAX_RUNTIME_REGIONS: usize = 64;
+
+// EFI_DTB_TABLE_GUID, the flattened device tree on ARM and RISC-V platforms
+const DTB_GUID: Guid = guid!("b1b621d5-f19c-41a5-830b-d9152c69aae0");
+
+// Size of the kernel command line buffer, including the terminating NUL
+pub const MAX_CMDLINE: usize = 256;
+
+// Maximum number of processors handed over to the kernel
+pub const MAX_CPUS: usize = 64;
+
+// CpuInfo flags, as reported by EFI_MP_SERVICES_PROTOCOL
+pub const CPU_FLAG_BSP: u32 = 1 << 0;
+pub const CPU_FLAG_ENABLED: u32 = 1 << 1;
+pub const CPU_FLAG_HEALTHY: u32 = 1 << 2;
+
+// One logical processor
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct CpuInfo {
+    // Local APIC ID on x86_64, MPIDR affinity bits on aarch64
+    pub hw_id: u64,
+    pub package: u32,
+    pub core: u32,
+    pub thread: u32,
+    pub flags: u32,
+}
 
 #[repr(C)]
 pub struct BootInfo {
@@ -10,6 +44,35 @@ pub struct BootInfo {
     pub framebuffer_width: usize,
     pub framebuffer_height: usize,
     pub framebuffer_stride: usize,
+    // Which bits of a 32-bit pixel hold each colour
+    pub framebuffer_red_mask: u32,
+    pub framebuffer_green_mask: u32,
+    pub framebuffer_blue_mask: u32,
+    // UEFI runtime services, preserved across exit_boot_services
+    pub system_table_addr: u64,
+    pub runtime_services_addr: u64,
+    pub runtime_region_count: usize,
+    pub runtime_regions: [MemoryDescriptor; MAX_RUNTIME_REGIONS],
+    // Processor topology, the BSP is flagged with CPU_FLAG_BSP
+    pub cpu_count: usize,
+    pub cpus: [CpuInfo; MAX_CPUS],
+    // Bootloader log ring, `boot_log_written` counts every byte ever written
+    pub boot_log_addr: u64,
+    pub boot_log_capacity: usize,
+    pub boot_log_written: u64,
+    // Boot phase timestamps, the kernel appends its own
+    pub boot_timeline: BootTimeline,
+    // Platform description tables, 0 when the firmware doesn't provide one
+    pub acpi_rsdp_addr: u64,
+    pub device_tree_addr: u64,
+    // Kernel command line from the boot entry, NUL-terminated
+    pub cmdline_len: usize,
+    pub cmdline: [u8; MAX_CMDLINE],
+    // Kernel ELF symbol and string tables, inside the LOADER_DATA copy of the file
+    pub symtab_addr: u64,
+    pub symtab_size: u64,
+    pub strtab_addr: u64,
+    pub strtab_size: u64,
 }
 
 impl BootInfo {
@@ -26,17 +89,86 @@ impl BootInfo {
             framebuffer_width: 0,
             framebuffer_height: 0,
             framebuffer_stride: 0,
+            framebuffer_red_mask: 0,
+            framebuffer_green_mask: 0,
+            framebuffer_blue_mask: 0,
+            system_table_addr: 0,
+            runtime_services_addr: 0,
+            runtime_region_count: 0,
+            runtime_regions: [MemoryDescriptor::default(); MAX_RUNTIME_REGIONS],
+            cpu_count: 0,
+            cpus: [CpuInfo::default(); MAX_CPUS],
+            boot_log_addr: 0,
+            boot_log_capacity: 0,
+            boot_log_written: 0,
+            boot_timeline: BootTimeline::new(),
+            acpi_rsdp_addr: 0,
+            device_tree_addr: 0,
+            cmdline_len: 0,
+            cmdline: [0; MAX_CMDLINE],
+            symtab_addr: 0,
+            symtab_size: 0,
+            strtab_addr: 0,
+            strtab_size: 0,
         }
     }
     
-    pub fn memory_map<'a>(&self) -> &'a [MemoryDescriptor] {
-        // This function is inherently unsafe because it creates a reference from a raw pointer
-        // with a lifetime not tied to any existing data
-        unsafe {
-            core::slice::from_raw_parts(
-                self.memory_map_addr as *const MemoryDescriptor,
-                self.memory_map_size / self.memory_map_entry_size,
-            )
+    // Find the ACPI RSDP (preferring ACPI 2.0+) and the device tree in the
+    // UEFI configuration table
+    pub fn set_platform_tables(&mut self, system_table: &SystemTable<Boot>) {
+        for entry in system_table.config_table() {
+            if entry.guid == ACPI2_GUID || (entry.guid == ACPI_GUID && self.acpi_rsdp_addr == 0) {
+                self.acpi_rsdp_addr = entry.address as u64;
+            } else if entry.guid == DTB_GUID {
+                self.device_tree_addr = entry.address as u64;
+            }
+        }
+    }
+
+    // Copy the command line, cutting it at the last whole word that fits
+    pub fn set_command_line(&mut self, cmdline: &str) {
+        let mut len = cmdline.len();
+        if len >= MAX_CMDLINE {
+            len = cmdline.as_bytes()[..MAX_CMDLINE]
+                .iter()
+                .rposition(|&b| b == b' ')
+                .unwrap_or(0);
+        }
+
+        self.cmdline = [0; MAX_CMDLINE];
+        self.cmdline[..len].copy_from_slice(&cmdline.as_bytes()[..len]);
+        self.cmdline_len = len;
+    }
+
+    // Record the runtime services table and every memory region the firmware
+    // needs at runtime (RUNTIME_SERVICES_CODE/DATA and runtime MMIO), so the
+    // kernel can map them and call SetVirtualAddressMap later.
+    //
+    // Must be called with the final memory map returned by exit_boot_services.
+    pub fn set_runtime_services<'a>(
+        &mut self,
+        system_table: &SystemTable<Runtime>,
+        memory_map: impl Iterator<Item = &'a MemoryDescriptor>,
+    ) {
+        self.system_table_addr = system_table.get_current_system_table_addr();
+        self.runtime_services_addr =
+            unsafe { system_table.runtime_services() as *const _ as u64 };
+
+        self.runtime_region_count = 0;
+        for desc in memory_map {
+            if !desc.att.contains(MemoryAttribute::RUNTIME) {
+                continue;
+            }
+
+            // Logging is gone after exit_boot_services, so rather than hand over
+            // an incomplete region list we hide runtime services from the kernel.
+            if self.runtime_region_count == MAX_RUNTIME_REGIONS {
+                self.runtime_services_addr = 0;
+                break;
+            }
+
+            self.runtime_regions[self.runtime_region_count] = *desc;
+            self.runtime_region_count += 1;
         }
     }
-}
\ No newline at end of file
+}
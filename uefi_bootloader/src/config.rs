@@ -0,0 +1,78 @@
+// uefi_bootloader/src/config.rs
+//
+// Reading the boot entries from the boot volume. The format and the parser
+// are in entries.rs.
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use log::info;
+use uefi::prelude::*;
+use uefi::proto::media::file::{File, FileAttribute, FileInfo, FileMode};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::CString16;
+
+pub use uefi_bootloader::entries::*;
+
+// Load the boot entries, falling back to the default kernel alone when the
+// config file is missing or has no usable entry
+pub fn load(system_table: &SystemTable<Boot>, default_kernel: &str) -> Vec<BootEntry> {
+    let entries = match read_config(system_table) {
+        Some(text) => parse(&text),
+        None => Vec::new(),
+    };
+
+    if entries.is_empty() {
+        return vec![BootEntry {
+            kind: EntryKind::Kernel,
+            path: default_kernel.to_string(),
+            title: "MelonOS".to_string(),
+            cmdline: String::new(),
+        }];
+    }
+
+    entries
+}
+
+fn read_config(system_table: &SystemTable<Boot>) -> Option<String> {
+    #[allow(deprecated)]
+    let fs_proto = unsafe {
+        system_table
+            .boot_services()
+            .locate_protocol::<SimpleFileSystem>()
+            .ok()?
+    };
+    let fs = unsafe { &mut *fs_proto.get() };
+    let mut root = fs.open_volume().ok()?;
+
+    let path = CString16::try_from(CONFIG_PATH).ok()?;
+    let mut file = root
+        .open(&path, FileMode::Read, FileAttribute::empty())
+        .ok()?
+        .into_regular_file()?;
+
+    let file_info_size = file
+        .get_info::<FileInfo>(&mut [])
+        .unwrap_err()
+        .data()
+        .unwrap_or(512);
+    let mut file_info_buffer = vec![0u8; file_info_size];
+    let file_size = file
+        .get_info::<FileInfo>(&mut file_info_buffer)
+        .ok()?
+        .file_size() as usize;
+
+    let mut contents = vec![0u8; file_size];
+    let read = file.read(&mut contents).ok()?;
+    contents.truncate(read);
+
+    info!("Loaded boot configuration from {}", CONFIG_PATH);
+    match String::from_utf8(contents) {
+        Ok(text) => Some(text),
+        Err(_) => {
+            info!("{} is not valid UTF-8, ignoring it", CONFIG_PATH);
+            None
+        }
+    }
+}
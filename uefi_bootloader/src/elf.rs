@@ -158,6 +158,7 @@ impl ElfHeader {
                 40 => "ARM",
                 62 => "x86_64",
                 183 => "AArch64",
+                243 => "RISC-V",
                 _ => "Unknown"
             });
         info!("  Entry Point: 0x{:x}", self.entry_point);
@@ -203,9 +204,64 @@ impl ProgramHeader {
     }
 }
 
-// Program header type constants
-pub const PT_NULL: u32 = 0;
+// Program header type, the only one the loader looks at
 pub const PT_LOAD: u32 = 1;
-pub const PT_DYNAMIC: u32 = 2;
-pub const PT_INTERP: u32 = 3;
-pub const PT_NOTE: u32 = 4;
\ No newline at end of file
+
+// Program header flag, the only one the loader looks at
+pub const PF_X: u32 = 1;
+
+#[repr(C)]
+pub struct SectionHeader {
+    pub sh_name: u32,
+    pub sh_type: u32,
+    pub sh_flags: u64,
+    pub sh_addr: u64,
+    pub sh_offset: u64,
+    pub sh_size: u64,
+    pub sh_link: u32,
+    pub sh_info: u32,
+    pub sh_addralign: u64,
+    pub sh_entsize: u64,
+}
+
+// Section header type constants
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+
+// Symbol table and its string table, as addresses inside the loaded file
+pub struct SymbolTable {
+    pub symtab_addr: u64,
+    pub symtab_size: u64,
+    pub strtab_addr: u64,
+    pub strtab_size: u64,
+}
+
+// Find .symtab and the string table it links to in an ELF file at `base`.
+// None if the kernel was stripped.
+pub fn find_symbol_table(base: u64, header: &ElfHeader, file_size: usize) -> Option<SymbolTable> {
+    let section = |index: usize| -> Option<&SectionHeader> {
+        if index >= header.e_shnum as usize {
+            return None;
+        }
+        let offset = header.e_shoff as usize + index * header.e_shentsize as usize;
+        if offset + core::mem::size_of::<SectionHeader>() > file_size {
+            return None;
+        }
+        Some(unsafe { &*((base + offset as u64) as *const SectionHeader) })
+    };
+
+    let in_file = |sh: &SectionHeader| sh.sh_offset.saturating_add(sh.sh_size) <= file_size as u64;
+
+    let symtab = (0..header.e_shnum as usize)
+        .filter_map(section)
+        .find(|sh| sh.sh_type == SHT_SYMTAB && in_file(sh))?;
+    let strtab = section(symtab.sh_link as usize)
+        .filter(|sh| sh.sh_type == SHT_STRTAB && in_file(sh))?;
+
+    Some(SymbolTable {
+        symtab_addr: base + symtab.sh_offset,
+        symtab_size: symtab.sh_size,
+        strtab_addr: base + strtab.sh_offset,
+        strtab_size: strtab.sh_size,
+    })
+}
@@ -0,0 +1,109 @@
+// uefi_bootloader/src/entries.rs
+//
+// Boot entries, and parsing them out of the boot configuration file.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use log::info;
+
+#[cfg(test)]
+mod tests;
+
+// Boot configuration on the boot volume. One entry per line:
+//
+//   kernel \EFI\KERNEL\KERNEL_X64.ELF MelonOS -- panic=reboot:5
+//   kernel \EFI\KERNEL\DEBUG.ELF -- panic=debug
+//   efi    \EFI\BOOT\Shell.efi        UEFI Shell
+//
+// The first word is the entry type, the second the path, the rest the title.
+// Words can be separated by any run of spaces and tabs. Anything after a
+// "--" word in a kernel entry is passed to the kernel as its command line.
+// An entry without a title is named after its path. Lines starting with '#'
+// are comments.
+pub const CONFIG_PATH: &str = "\\EFI\\MELONOS\\BOOT.CFG";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    // A MelonOS kernel ELF, loaded from the boot volume
+    Kernel,
+    // Another EFI application, started with LoadImage/StartImage from any volume
+    Efi,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BootEntry {
+    pub kind: EntryKind,
+    pub path: String,
+    pub title: String,
+    // Kernel command line, empty for EFI applications
+    pub cmdline: String,
+}
+
+// Parse the config file contents, skipping (and logging) malformed lines
+pub fn parse(text: &str) -> Vec<BootEntry> {
+    let mut entries = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (kind, rest) = first_word(line);
+        let kind = match kind {
+            "kernel" => EntryKind::Kernel,
+            "efi" => EntryKind::Efi,
+            other => {
+                info!(
+                    "{} line {}: unknown entry type '{}'",
+                    CONFIG_PATH,
+                    number + 1,
+                    other
+                );
+                continue;
+            }
+        };
+
+        let (path, rest) = first_word(rest);
+        if path.is_empty() {
+            info!("{} line {}: missing path", CONFIG_PATH, number + 1);
+            continue;
+        }
+
+        let (title, cmdline) = match split_command_line(rest) {
+            Some((title, cmdline)) if kind == EntryKind::Kernel => (title, cmdline),
+            _ => (rest, ""),
+        };
+        let title = Some(title).filter(|t| !t.is_empty()).unwrap_or(path);
+
+        entries.push(BootEntry {
+            kind,
+            path: path.to_string(),
+            title: title.to_string(),
+            cmdline: cmdline.to_string(),
+        });
+    }
+
+    entries
+}
+
+// The first word of `text`, which starts with one, and the rest after the
+// whitespace that follows it
+fn first_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], text[end..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+// Split the end of an entry line at the first "--" that stands as a word of
+// its own, into the title before it and the command line after, trimmed
+fn split_command_line(text: &str) -> Option<(&str, &str)> {
+    let (start, _) = text.match_indices("--").find(|&(start, marker)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + marker.len()..].chars().next();
+        before.is_none_or(char::is_whitespace) && after.is_none_or(char::is_whitespace)
+    })?;
+    Some((text[..start].trim_end(), text[start + 2..].trim()))
+}
@@ -0,0 +1,131 @@
+// uefi_bootloader/src/entries/tests.rs
+//
+// Parsing boot configuration lines, however they are spaced.
+
+use alloc::string::ToString;
+
+use super::*;
+
+fn entry(kind: EntryKind, path: &str, title: &str, cmdline: &str) -> BootEntry {
+    BootEntry {
+        kind,
+        path: path.to_string(),
+        title: title.to_string(),
+        cmdline: cmdline.to_string(),
+    }
+}
+
+#[test]
+fn single_spaces() {
+    let entries = parse(
+        "kernel \\EFI\\KERNEL\\KERNEL_X64.ELF MelonOS -- panic=reboot:5\n\
+         efi \\EFI\\BOOT\\Shell.efi UEFI Shell\n",
+    );
+    assert_eq!(
+        entries,
+        [
+            entry(
+                EntryKind::Kernel,
+                "\\EFI\\KERNEL\\KERNEL_X64.ELF",
+                "MelonOS",
+                "panic=reboot:5"
+            ),
+            entry(EntryKind::Efi, "\\EFI\\BOOT\\Shell.efi", "UEFI Shell", ""),
+        ]
+    );
+}
+
+#[test]
+fn multiple_spaces() {
+    let entries = parse(
+        "efi    \\EFI\\BOOT\\Shell.efi        UEFI   Shell  \n\
+         kernel   \\K.ELF   Debug  kernel   --   panic=debug   log=trace\n",
+    );
+    assert_eq!(
+        entries,
+        [
+            entry(EntryKind::Efi, "\\EFI\\BOOT\\Shell.efi", "UEFI   Shell", ""),
+            entry(
+                EntryKind::Kernel,
+                "\\K.ELF",
+                "Debug  kernel",
+                "panic=debug   log=trace"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn tabs() {
+    let entries = parse("\tkernel\t\\K.ELF\tMelonOS\t--\tpanic=halt\r\nefi\t\t\\S.EFI\t\tShell\n");
+    assert_eq!(
+        entries,
+        [
+            entry(EntryKind::Kernel, "\\K.ELF", "MelonOS", "panic=halt"),
+            entry(EntryKind::Efi, "\\S.EFI", "Shell", ""),
+        ]
+    );
+}
+
+// An entry without a title is named after its path
+#[test]
+fn missing_title() {
+    let entries = parse(
+        "kernel \\K.ELF\n\
+         kernel  \\DEBUG.ELF   --  panic=debug\n\
+         kernel \\BARE.ELF --\n\
+         efi \\S.EFI   \n",
+    );
+    assert_eq!(
+        entries,
+        [
+            entry(EntryKind::Kernel, "\\K.ELF", "\\K.ELF", ""),
+            entry(
+                EntryKind::Kernel,
+                "\\DEBUG.ELF",
+                "\\DEBUG.ELF",
+                "panic=debug"
+            ),
+            entry(EntryKind::Kernel, "\\BARE.ELF", "\\BARE.ELF", ""),
+            entry(EntryKind::Efi, "\\S.EFI", "\\S.EFI", ""),
+        ]
+    );
+}
+
+// "--" only splits off the command line as a word of its own, and only in
+// kernel entries
+#[test]
+fn command_line_marker() {
+    let entries = parse(
+        "kernel \\K.ELF Melon--OS --verbose -- a -- b\n\
+         efi \\S.EFI Shell -- not a command line\n",
+    );
+    assert_eq!(
+        entries,
+        [
+            entry(
+                EntryKind::Kernel,
+                "\\K.ELF",
+                "Melon--OS --verbose",
+                "a -- b"
+            ),
+            entry(EntryKind::Efi, "\\S.EFI", "Shell -- not a command line", ""),
+        ]
+    );
+}
+
+#[test]
+fn comments_and_bad_lines() {
+    let entries = parse(
+        "# MelonOS boot entries\n\
+         \n\
+         \t  # indented comment\n\
+         kernel\n\
+         linux \\vmlinuz Linux\n\
+         kernel \\K.ELF # not a comment\n",
+    );
+    assert_eq!(
+        entries,
+        [entry(EntryKind::Kernel, "\\K.ELF", "# not a comment", "")]
+    );
+}
@@ -0,0 +1,12 @@
+// uefi_bootloader/src/lib.rs
+//
+// The part of the bootloader that doesn't touch UEFI, built as a library so
+// it builds for the host as well. `cargo test -p uefi_bootloader --lib`
+// from the workspace root runs its tests there. The bootloader binary uses
+// it as the `uefi_bootloader` crate.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod entries;
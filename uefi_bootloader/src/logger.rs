@@ -0,0 +1,89 @@
+// uefi_bootloader/src/logger.rs
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+
+use log::{Log, Metadata, Record};
+use uefi::prelude::*;
+
+// Size of the boot log ring handed to the kernel
+pub const LOG_BUFFER_SIZE: usize = 64 * 1024;
+
+// Fixed-size ring of formatted log lines, oldest bytes are overwritten first
+struct LogRing {
+    buffer: [u8; LOG_BUFFER_SIZE],
+    // Total bytes ever written, the write position is written % LOG_BUFFER_SIZE
+    written: u64,
+}
+
+impl Write for LogRing {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.buffer[(self.written % LOG_BUFFER_SIZE as u64) as usize] = byte;
+            self.written += 1;
+        }
+        Ok(())
+    }
+}
+
+// log backend that prints to the UEFI console and records into the ring
+struct BootLogger {
+    console: UnsafeCell<Option<uefi::logger::Logger>>,
+    ring: UnsafeCell<LogRing>,
+}
+
+// The UEFI boot environment only runs the bootloader on one processor
+unsafe impl Sync for BootLogger {}
+
+static LOGGER: BootLogger = BootLogger {
+    console: UnsafeCell::new(None),
+    ring: UnsafeCell::new(LogRing {
+        buffer: [0; LOG_BUFFER_SIZE],
+        written: 0,
+    }),
+};
+
+impl Log for BootLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        unsafe {
+            if let Some(console) = &*self.console.get() {
+                console.log(record);
+            }
+
+            let _ = writeln!(&mut *self.ring.get(), "[{:>5}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// Install the logger, replacing the one uefi_services would set up
+pub fn init(system_table: &mut SystemTable<Boot>) {
+    unsafe {
+        *LOGGER.console.get() = Some(uefi::logger::Logger::new(system_table.stdout()));
+    }
+
+    log::set_logger(&LOGGER).expect("Logger already initialized");
+    log::set_max_level(log::STATIC_MAX_LEVEL);
+}
+
+// Stop writing to the UEFI console, which is gone after exit_boot_services.
+// Log lines still land in the ring.
+pub fn disable_console() {
+    unsafe {
+        if let Some(console) = &mut *LOGGER.console.get() {
+            console.disable();
+        }
+    }
+}
+
+// Address, capacity and total bytes written of the ring
+pub fn ring_buffer() -> (u64, usize, u64) {
+    unsafe {
+        let ring = &*LOGGER.ring.get();
+        (ring.buffer.as_ptr() as u64, LOG_BUFFER_SIZE, ring.written)
+    }
+}
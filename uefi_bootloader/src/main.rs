@@ -8,7 +8,9 @@ use alloc::vec;
 
 use log::info;
 use uefi::data_types::CStr16;
+use uefi::CString16;
 use uefi::prelude::*;
+use uefi::proto::console::gop::PixelFormat;
 use uefi::proto::media::file::{File, FileAttribute, FileInfo, FileMode};
 use uefi::proto::media::fs::SimpleFileSystem;
 use uefi::table::boot::{AllocateType, MemoryType};
@@ -26,11 +28,35 @@ mod common;
 // ELF parsing module
 mod elf;
 
+// Multiprocessor topology discovery
+mod mp;
+
+// Logger that keeps a copy of the boot trace for the kernel
+mod logger;
+
+// Boot phase timestamps
+mod timing;
+
+// Boot configuration, boot menu and chainloading of other EFI applications
+mod chainload;
+mod config;
+mod menu;
+
+use config::EntryKind;
+
 // Entry point for the UEFI bootloader
 #[entry]
 fn efi_main(image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    timing::mark("bootloader entry");
+
     // Initialize UEFI services
     uefi_services::init(&mut system_table).expect("Failed to initialize UEFI services");
+    logger::init(&mut system_table);
+    timing::mark("services init");
+
+    // Calibrate the boot counter (stalls for a few milliseconds)
+    timing::calibrate(&system_table);
+    timing::mark("counter calibrated");
 
     // Print architecture-specific welcome message
     #[cfg(target_arch = "x86_64")]
@@ -47,29 +73,71 @@ fn efi_main(image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status
             return Status::DEVICE_ERROR;
         }
     };
+    timing::mark("GOP setup");
+
+    // Tell the kernel which processors exist so it can start them later
+    mp::collect_processor_topology(&system_table, &mut boot_info);
+    timing::mark("MP topology");
+
+    // Let the kernel find its hardware through ACPI or the device tree
+    boot_info.set_platform_tables(&system_table);
+    info!(
+        "ACPI RSDP at 0x{:x}, device tree at 0x{:x}",
+        boot_info.acpi_rsdp_addr, boot_info.device_tree_addr
+    );
 
-    // Load the kernel file path
+    // Default kernel for this architecture, booted when there is no boot config
     #[cfg(target_arch = "x86_64")]
-    let kernel_path = b"\\EFI\\KERNEL\\KERNEL_X64.ELF\0";
+    let default_kernel = "\\EFI\\KERNEL\\KERNEL_X64.ELF";
 
     #[cfg(target_arch = "aarch64")]
-    let kernel_path = b"\\EFI\\KERNEL\\KERNEL_ARM64.ELF\0";
-
-    // Convert the path to UTF-16
-    let mut kernel_path_utf16 = [0u16; 128];
-    for (i, &b) in kernel_path.iter().enumerate() {
-        if b == 0 {
-            kernel_path_utf16[i] = 0;
-            break;
+    let default_kernel = "\\EFI\\KERNEL\\KERNEL_ARM64.ELF";
+
+    let mut entries = config::load(&system_table, default_kernel);
+
+    // Run the boot menu until a kernel entry is chosen. Chained EFI
+    // applications that exit bring us back to the menu; entries that can't
+    // be started are dropped from it, so they aren't tried again.
+    let mut back_from_image = false;
+    let (kernel_path, cmdline) = loop {
+        if entries.is_empty() {
+            info!("No boot entries left to try");
+            return Status::NOT_FOUND;
         }
-        kernel_path_utf16[i] = b as u16;
-    }
+        let index = menu::select(&mut system_table, &entries, back_from_image);
+        let entry = &entries[index];
+
+        let path = match CString16::try_from(entry.path.as_str()) {
+            Ok(path) => path,
+            Err(_) => {
+                info!("Invalid path for boot entry {}: {}", entry.title, entry.path);
+                entries.remove(index);
+                continue;
+            }
+        };
+
+        match entry.kind {
+            EntryKind::Kernel => break (path, entry.cmdline.clone()),
+            EntryKind::Efi => {
+                match chainload::start_efi_image(image_handle, &system_table, &path) {
+                    Ok(_) => back_from_image = true,
+                    Err(status) => {
+                        info!("Failed to start {}: {:?}", entry.title, status);
+                        entries.remove(index);
+                    }
+                }
+            }
+        }
+    };
+    timing::mark("boot menu");
 
-    // Create CStr16 from the UTF-16 array
-    let kernel_path_cstr = unsafe { CStr16::from_u16_with_nul_unchecked(&kernel_path_utf16) };
+    if !cmdline.is_empty() {
+        info!("Kernel command line: {}", cmdline);
+    }
+    boot_info.set_command_line(&cmdline);
 
     // Load the appropriate kernel
-    match load_kernel(image_handle, &mut system_table, kernel_path_cstr) {
+    match load_kernel(image_handle, &mut system_table, &kernel_path, &mut boot_info) {
         Ok(kernel_entry) => {
             info!("Kernel loaded successfully, jumping to entry point");
 
@@ -81,10 +149,12 @@ fn efi_main(image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status
             aarch64::prepare_jump_to_kernel(&mut system_table);
 
             // Prepare boot parameters
+            // LOADER_DATA keeps these out of the runtime regions that are
+            // handed to SetVirtualAddressMap
             let boot_params_size = core::mem::size_of::<common::BootInfo>();
             let boot_params_addr = system_table
                 .boot_services()
-                .allocate_pool(MemoryType::RUNTIME_SERVICES_DATA, boot_params_size)
+                .allocate_pool(MemoryType::LOADER_DATA, boot_params_size)
                 .expect("Failed to allocate memory for boot parameters");
 
             // We'll get memory map size information first
@@ -96,7 +166,7 @@ fn efi_main(image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status
             let memory_map_buffer_size = memory_map_info.map_size + 4096; // Add some extra space
             let memory_map_buffer = system_table
                 .boot_services()
-                .allocate_pool(MemoryType::RUNTIME_SERVICES_DATA, memory_map_buffer_size)
+                .allocate_pool(MemoryType::LOADER_DATA, memory_map_buffer_size)
                 .expect("Failed to allocate memory map buffer");
 
             // We'll store the descriptor size in boot_info
@@ -109,38 +179,45 @@ fn efi_main(image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status
                 core::ptr::write_volatile(boot_params_addr as *mut common::BootInfo, boot_info);
             }
 
-            // Create a temporary buffer for exit_boot_services
-            let mut temp_map_buf = [0u8; 16384];
-
-            // Get the memory map into our permanent buffer before exiting boot services
-            let map_size = unsafe {
-                let map_buffer = core::slice::from_raw_parts_mut(
-                    memory_map_buffer as *mut u8,
-                    memory_map_buffer_size,
-                );
+            // Exit boot services straight into our permanent buffer, so the
+            // kernel receives the final memory map rather than a stale copy
+            let map_buffer = unsafe {
+                core::slice::from_raw_parts_mut(memory_map_buffer, memory_map_buffer_size)
+            };
 
-                let (_size, map) = system_table
-                    .boot_services()
-                    .memory_map(map_buffer)
-                    .expect("Failed to get memory map");
+            // The console is unusable from here on, later log lines only go to the ring
+            logger::disable_console();
+            timing::mark("exit_boot_services");
 
-                map.len() * descriptor_size
-            };
+            let (runtime_table, memory_map) = system_table
+                .exit_boot_services(image_handle, map_buffer)
+                .expect("Failed to exit boot services");
 
-            // Update the memory map size in boot info
+            // Update the memory map size and runtime services in boot info
             unsafe {
                 let boot_info_mut = &mut *(boot_params_addr as *mut common::BootInfo);
-                boot_info_mut.memory_map_size = map_size;
-            }
+                boot_info_mut.memory_map_size = memory_map.len() * descriptor_size;
+                boot_info_mut.set_runtime_services(&runtime_table, memory_map);
 
-            // Now exit boot services
-            let _ = system_table
-                .exit_boot_services(image_handle, &mut temp_map_buf)
-                .expect("Failed to exit boot services");
+                let (log_addr, log_capacity, log_written) = logger::ring_buffer();
+                boot_info_mut.boot_log_addr = log_addr;
+                boot_info_mut.boot_log_capacity = log_capacity;
+                boot_info_mut.boot_log_written = log_written;
+
+                timing::mark("jump to kernel");
+                boot_info_mut.boot_timeline = timing::timeline();
+            }
 
-            // Jump to the kernel, passing the boot info structure
+            // Jump to the kernel, passing the boot info structure.
+            // The kernel's `extern "C"` is System V on x86_64, while ours is
+            // the Microsoft ABI, so the calling convention must be spelled out.
             unsafe {
-                let kernel_entry: fn(*const common::BootInfo) -> ! =
+                #[cfg(target_arch = "x86_64")]
+                let kernel_entry: extern "sysv64" fn(*const common::BootInfo) -> ! =
+                    core::mem::transmute(kernel_entry);
+
+                #[cfg(not(target_arch = "x86_64"))]
+                let kernel_entry: extern "C" fn(*const common::BootInfo) -> ! =
                     core::mem::transmute(kernel_entry);
 
                 kernel_entry(boot_params_addr as *const common::BootInfo);
@@ -174,12 +251,35 @@ fn setup_graphics(system_table: &mut SystemTable<Boot>) -> Option<common::BootIn
         // Get current graphics mode info
         let mode_info = gop.current_mode_info();
 
-        // Get framebuffer
-        let mut framebuffer = gop.frame_buffer();
-
         // Create a boot info structure
         let mut boot_info = common::BootInfo::new(0, 0, 0); // We'll fill memory map details later
 
+        // Where each colour goes in a pixel. The kernel only draws 32-bit
+        // pixels, and without a framebuffer at all it prints on serial.
+        let (red, green, blue) = match mode_info.pixel_format() {
+            PixelFormat::Rgb => (0x0000FF, 0x00FF00, 0xFF0000),
+            PixelFormat::Bgr => (0xFF0000, 0x00FF00, 0x0000FF),
+            PixelFormat::Bitmask => match mode_info.pixel_bitmask() {
+                Some(mask) if (mask.red | mask.green | mask.blue | mask.reserved) >> 24 != 0 => {
+                    (mask.red, mask.green, mask.blue)
+                }
+                _ => {
+                    info!("GOP pixels are narrower than 32 bits, no framebuffer for the kernel");
+                    return Some(boot_info);
+                }
+            },
+            PixelFormat::BltOnly => {
+                info!("GOP mode is Blt only, no framebuffer for the kernel");
+                return Some(boot_info);
+            }
+        };
+        boot_info.framebuffer_red_mask = red;
+        boot_info.framebuffer_green_mask = green;
+        boot_info.framebuffer_blue_mask = blue;
+
+        // Get framebuffer
+        let mut framebuffer = gop.frame_buffer();
+
         // Set framebuffer info
         boot_info.framebuffer_addr = framebuffer.as_mut_ptr() as u64;
         boot_info.framebuffer_width = mode_info.resolution().0;
@@ -195,6 +295,7 @@ fn load_kernel(
     _image_handle: Handle,
     system_table: &mut SystemTable<Boot>,
     kernel_path: &CStr16,
+    boot_info: &mut common::BootInfo,
 ) -> Result<u64, Status> {
     // Get the file system protocol
     let boot_services = system_table.boot_services();
@@ -213,6 +314,7 @@ fn load_kernel(
 
     // Open the root directory
     let mut root = fs.open_volume().map_err(|_| Status::DEVICE_ERROR)?;
+    timing::mark("volume open");
 
     info!("Opening kernel file: {:?}", kernel_path);
 
@@ -222,6 +324,7 @@ fn load_kernel(
         .map_err(|_| Status::NOT_FOUND)?
         .into_regular_file()
         .ok_or(Status::INVALID_PARAMETER)?;
+    timing::mark("kernel file open");
 
     info!("Getting kernel file size...");
 
@@ -252,19 +355,20 @@ fn load_kernel(
         .allocate_pages(
             AllocateType::AnyPages,
             MemoryType::LOADER_DATA,
-            (file_size + 0xFFF) / 0x1000, // Round up to the next page
+            file_size.div_ceil(0x1000), // Round up to the next page
         )
         .map_err(|_| Status::OUT_OF_RESOURCES)?;
 
     // Read the kernel into memory
     // Creating a slice from a raw pointer requires unsafe
-    let mut buffer =
+    let buffer =
         unsafe { core::slice::from_raw_parts_mut(elf_buffer_addr as *mut u8, file_size) };
 
     info!("Reading kernel file...");
     kernel_file
-        .read(&mut buffer)
+        .read(buffer)
         .map_err(|_| Status::DEVICE_ERROR)?;
+    timing::mark("kernel file read");
 
     // Parse the ELF header - use the elf module now
     info!("Parsing ELF header...");
@@ -282,6 +386,7 @@ fn load_kernel(
         "Valid ELF header found. Entry point: {:x}",
         elf_header.entry_point
     );
+    timing::mark("ELF parse");
 
     // Process program headers to load segments
     let ph_offset = elf_header.e_phoff;
@@ -308,21 +413,33 @@ fn load_kernel(
             i, ph.p_vaddr, ph.p_memsz
         );
 
-        // Calculate pages needed
-        let pages = (ph.p_memsz + 0xFFF) / 0x1000;
+        // The kernel is linked to run at its physical load address, and the
+        // firmware's identity map makes that address valid as-is
+        let page_start = ph.p_paddr & !0xFFF;
+        let pages = ((ph.p_paddr - page_start) + ph.p_memsz).div_ceil(0x1000);
 
-        // Allocate memory for the segment - using a standard memory type
-        let segment_addr = boot_services
+        // Executable segments need LOADER_CODE, firmware with memory
+        // protection (AAVMF, recent OVMF) maps LOADER_DATA non-executable
+        let memory_type = if ph.p_flags & elf::PF_X != 0 {
+            MemoryType::LOADER_CODE
+        } else {
+            MemoryType::LOADER_DATA
+        };
+
+        boot_services
             .allocate_pages(
-                AllocateType::AnyPages,
-                MemoryType::LOADER_DATA,
+                AllocateType::Address(page_start),
+                memory_type,
                 pages as usize,
             )
-            .map_err(|_| Status::OUT_OF_RESOURCES)?;
+            .map_err(|_| {
+                info!("Segment {} address 0x{:x} is not available", i, ph.p_paddr);
+                Status::OUT_OF_RESOURCES
+            })?;
 
         // Copy segment data
         let src = elf_buffer_addr + ph.p_offset;
-        let dst = segment_addr;
+        let dst = ph.p_paddr;
         let size = ph.p_filesz as usize;
 
         // Memory operations with raw pointers require unsafe
@@ -339,6 +456,29 @@ fn load_kernel(
                 );
             }
         }
+
+        // Make the new code visible to instruction fetch
+        if ph.p_flags & elf::PF_X != 0 {
+            #[cfg(target_arch = "aarch64")]
+            aarch64::sync_instruction_cache(dst, ph.p_memsz as usize);
+        }
+        timing::mark_args(format_args!("load segment {}", i));
+    }
+
+    // Keep the symbols around for kernel backtraces. They point into the
+    // ELF buffer, which is never freed.
+    match elf::find_symbol_table(elf_buffer_addr, elf_header, file_size) {
+        Some(symbols) => {
+            info!(
+                "Kernel symbols: {} bytes at 0x{:x}, strings {} bytes at 0x{:x}",
+                symbols.symtab_size, symbols.symtab_addr, symbols.strtab_size, symbols.strtab_addr
+            );
+            boot_info.symtab_addr = symbols.symtab_addr;
+            boot_info.symtab_size = symbols.symtab_size;
+            boot_info.strtab_addr = symbols.strtab_addr;
+            boot_info.strtab_size = symbols.strtab_size;
+        }
+        None => info!("Kernel has no symbol table, backtraces will show addresses only"),
     }
 
     // Return the entry point
@@ -0,0 +1,68 @@
+// uefi_bootloader/src/menu.rs
+use log::info;
+use uefi::prelude::*;
+use uefi::proto::console::text::Key;
+
+use crate::config::{BootEntry, EntryKind};
+
+// How long the menu waits for a key before booting the first entry
+const MENU_TIMEOUT_SECS: usize = 5;
+
+// Poll interval for key presses, in microseconds
+const POLL_INTERVAL_US: usize = 100_000;
+
+// Show the boot entries and return the index of the chosen one.
+// A single entry is booted straight away, as before the menu existed, unless
+// we are back from a chained image. Then the menu always shows, and the
+// timeout boots the first kernel entry rather than the first entry, which
+// may be the image that just exited. With no kernel entry to fall back on
+// it waits for a key.
+pub fn select(
+    system_table: &mut SystemTable<Boot>,
+    entries: &[BootEntry],
+    back_from_image: bool,
+) -> usize {
+    if entries.len() <= 1 && !back_from_image {
+        return 0;
+    }
+    let default = match back_from_image {
+        false => Some(0),
+        true => entries
+            .iter()
+            .position(|entry| entry.kind == EntryKind::Kernel),
+    };
+
+    info!("MelonOS boot menu:");
+    for (index, entry) in entries.iter().enumerate() {
+        info!("  [{}] {} ({:?}: {})", index + 1, entry.title, entry.kind, entry.path);
+    }
+    match default {
+        Some(default) => info!(
+            "Press 1-{} to choose, booting [{}] in {} seconds",
+            entries.len().min(9),
+            default + 1,
+            MENU_TIMEOUT_SECS
+        ),
+        None => info!("Press 1-{} to choose", entries.len().min(9)),
+    }
+
+    let mut polls_left = MENU_TIMEOUT_SECS * 1_000_000 / POLL_INTERVAL_US;
+    loop {
+        if let Ok(Some(Key::Printable(c))) = system_table.stdin().read_key() {
+            if let Some(digit) = char::from(c).to_digit(10) {
+                let index = digit as usize;
+                if index >= 1 && index <= entries.len() {
+                    return index - 1;
+                }
+            }
+        }
+
+        if let Some(default) = default {
+            if polls_left == 0 {
+                return default;
+            }
+            polls_left -= 1;
+        }
+        system_table.boot_services().stall(POLL_INTERVAL_US);
+    }
+}
@@ -0,0 +1,98 @@
+// uefi_bootloader/src/mp.rs
+use log::info;
+use uefi::prelude::*;
+use uefi::proto::pi::mp::MpServices;
+
+use crate::common::{
+    BootInfo, CpuInfo, CPU_FLAG_BSP, CPU_FLAG_ENABLED, CPU_FLAG_HEALTHY, MAX_CPUS,
+};
+
+// Fill in the processor topology from EFI_MP_SERVICES_PROTOCOL.
+// Firmware without MP services only gets the BSP reported.
+pub fn collect_processor_topology(system_table: &SystemTable<Boot>, boot_info: &mut BootInfo) {
+    boot_info.cpu_count = 0;
+
+    #[allow(deprecated)]
+    let mp = match unsafe { system_table.boot_services().locate_protocol::<MpServices>() } {
+        Ok(mp) => unsafe { &*mp.get() },
+        Err(_) => {
+            info!("MP services not available, assuming a single processor");
+            add_bsp_only(boot_info);
+            return;
+        }
+    };
+
+    let count = match mp.get_number_of_processors() {
+        Ok(count) => count,
+        Err(status) => {
+            info!("Failed to get processor count: {:?}", status);
+            add_bsp_only(boot_info);
+            return;
+        }
+    };
+
+    info!("Found {} processors ({} enabled)", count.total, count.enabled);
+
+    if count.total > MAX_CPUS {
+        info!("Only the first {} processors are passed to the kernel", MAX_CPUS);
+    }
+
+    for index in 0..count.total.min(MAX_CPUS) {
+        let processor = match mp.get_processor_info(index) {
+            Ok(processor) => processor,
+            Err(status) => {
+                info!("Failed to query processor {}: {:?}", index, status);
+                continue;
+            }
+        };
+
+        let mut flags = 0;
+        if processor.is_bsp() {
+            flags |= CPU_FLAG_BSP;
+        }
+        if processor.is_enabled() {
+            flags |= CPU_FLAG_ENABLED;
+        }
+        if processor.is_healthy() {
+            flags |= CPU_FLAG_HEALTHY;
+        }
+
+        info!(
+            "  CPU {}: id=0x{:x} package={} core={} thread={}{}",
+            index,
+            processor.processor_id,
+            processor.location.package,
+            processor.location.core,
+            processor.location.thread,
+            if processor.is_bsp() { " (BSP)" } else { "" }
+        );
+
+        boot_info.cpus[boot_info.cpu_count] = CpuInfo {
+            hw_id: processor.processor_id,
+            package: processor.location.package,
+            core: processor.location.core,
+            thread: processor.location.thread,
+            flags,
+        };
+        boot_info.cpu_count += 1;
+    }
+
+    if boot_info.cpu_count == 0 {
+        add_bsp_only(boot_info);
+    }
+}
+
+fn add_bsp_only(boot_info: &mut BootInfo) {
+    #[cfg(target_arch = "x86_64")]
+    let hw_id = crate::x86_64::current_cpu_id();
+
+    #[cfg(target_arch = "aarch64")]
+    let hw_id = crate::aarch64::current_cpu_id();
+
+    boot_info.cpus[0] = CpuInfo {
+        hw_id,
+        flags: CPU_FLAG_BSP | CPU_FLAG_ENABLED | CPU_FLAG_HEALTHY,
+        ..CpuInfo::default()
+    };
+    boot_info.cpu_count = 1;
+}
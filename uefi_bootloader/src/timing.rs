@@ -0,0 +1,120 @@
+// uefi_bootloader/src/timing.rs
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+
+use log::info;
+use uefi::prelude::*;
+
+// Maximum number of milestones, shared between bootloader and kernel
+pub const MAX_MILESTONES: usize = 64;
+pub const MILESTONE_NAME_LEN: usize = 32;
+
+// How long we stall to calibrate the counter, in microseconds
+const CALIBRATION_STALL_US: usize = 10_000;
+
+// A named point in time, `ticks` is the raw TSC / CNTVCT_EL0 / time CSR value
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Milestone {
+    pub ticks: u64,
+    pub name: [u8; MILESTONE_NAME_LEN],
+}
+
+// Boot timeline handed to the kernel, which appends its own milestones
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BootTimeline {
+    pub counter_frequency: u64,
+    pub count: usize,
+    pub milestones: [Milestone; MAX_MILESTONES],
+}
+
+impl BootTimeline {
+    pub const fn new() -> Self {
+        Self {
+            counter_frequency: 0,
+            count: 0,
+            milestones: [Milestone {
+                ticks: 0,
+                name: [0; MILESTONE_NAME_LEN],
+            }; MAX_MILESTONES],
+        }
+    }
+}
+
+// Writes a milestone name, silently truncating it
+struct NameWriter<'a> {
+    name: &'a mut [u8; MILESTONE_NAME_LEN],
+    len: usize,
+}
+
+impl Write for NameWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.len == MILESTONE_NAME_LEN {
+                break;
+            }
+            self.name[self.len] = byte;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+struct Timeline(UnsafeCell<BootTimeline>);
+
+// The UEFI boot environment only runs the bootloader on one processor
+unsafe impl Sync for Timeline {}
+
+static TIMELINE: Timeline = Timeline(UnsafeCell::new(BootTimeline::new()));
+
+fn read_counter() -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    return crate::x86_64::read_counter();
+
+    #[cfg(target_arch = "aarch64")]
+    return crate::aarch64::read_counter();
+}
+
+// Record a milestone, dropped once the table is full
+pub fn mark(name: &str) {
+    mark_args(format_args!("{}", name));
+}
+
+pub fn mark_args(args: fmt::Arguments) {
+    let ticks = read_counter();
+    let timeline = unsafe { &mut *TIMELINE.0.get() };
+
+    if timeline.count == MAX_MILESTONES {
+        return;
+    }
+
+    let milestone = &mut timeline.milestones[timeline.count];
+    milestone.ticks = ticks;
+    milestone.name = [0; MILESTONE_NAME_LEN];
+    let _ = NameWriter {
+        name: &mut milestone.name,
+        len: 0,
+    }
+    .write_fmt(args);
+    timeline.count += 1;
+}
+
+// Measure the counter frequency against the firmware's Stall service
+pub fn calibrate(system_table: &SystemTable<Boot>) {
+    let start = read_counter();
+    system_table.boot_services().stall(CALIBRATION_STALL_US);
+    let end = read_counter();
+
+    let frequency = (end - start) * (1_000_000 / CALIBRATION_STALL_US as u64);
+    unsafe {
+        (*TIMELINE.0.get()).counter_frequency = frequency;
+    }
+
+    info!("Boot counter frequency: {} kHz", frequency / 1000);
+}
+
+// Copy of the timeline for BootInfo
+pub fn timeline() -> BootTimeline {
+    unsafe { *TIMELINE.0.get() }
+}
@@ -7,4 +7,21 @@ pub fn prepare_jump_to_kernel(_system_table: &mut SystemTable<Boot>) {
     unsafe {
         core::arch::asm!("cli");
     }
-}
\ No newline at end of file
+}
+
+// Initial local APIC ID of the running processor (CPUID leaf 1, EBX[31:24])
+#[allow(unused_unsafe)]
+pub fn current_cpu_id() -> u64 {
+    let cpuid = unsafe { core::arch::x86_64::__cpuid(1) };
+    (cpuid.ebx >> 24) as u64
+}
+
+// Time stamp counter, used for boot timing
+pub fn read_counter() -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe {
+        core::arch::asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack));
+    }
+    ((high as u64) << 32) | low as u64
+}